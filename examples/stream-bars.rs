@@ -0,0 +1,66 @@
+// Copyright (C) 2023 The apca Developers
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+#![allow(clippy::let_unit_value)]
+
+use std::env::args;
+
+use apca::data::v2::stream::drive;
+use apca::data::v2::stream::Data;
+use apca::data::v2::stream::MarketData;
+use apca::data::v2::stream::RealtimeData;
+use apca::data::v2::stream::Symbols;
+use apca::data::v2::stream::IEX;
+use apca::ApiInfo;
+use apca::Client;
+
+use futures::FutureExt as _;
+use futures::StreamExt as _;
+
+
+/// Stream minute aggregate bars for the symbols provided on the
+/// command line, or for all symbols if none (or `*`) are given, and
+/// print them until interrupted (e.g., via Ctrl-C).
+#[tokio::main]
+async fn main() {
+  // Requires the following environment variables to be present:
+  // - APCA_API_KEY_ID -> your API key
+  // - APCA_API_SECRET_KEY -> your secret key
+  let api_info = ApiInfo::from_env().unwrap();
+  let client = Client::new(api_info);
+
+  // Connecting to the stream also authenticates us using the
+  // credentials from above.
+  let (mut stream, mut subscription) = client.subscribe::<RealtimeData<IEX>>().await.unwrap();
+
+  let symbols = args().skip(1).collect::<Symbols>();
+  let data = MarketData {
+    bars: if symbols.is_empty() {
+      Symbols::All
+    } else {
+      symbols
+    },
+    ..Default::default()
+  };
+
+  let subscribe = subscription.subscribe(&data).boxed();
+  let subscribed = drive(subscribe, &mut stream)
+    .await
+    .unwrap()
+    .unwrap()
+    .unwrap();
+  println!("subscribed to bars for: {:?}", subscribed.bars);
+
+  while let Some(result) = stream.next().await {
+    match result {
+      Ok(Ok(Data::Bar { bar, .. })) => println!("{bar:?}"),
+      // We only subscribed to bars, so nothing else is expected.
+      Ok(Ok(_)) => (),
+      Ok(Err(err)) => eprintln!("failed to decode message: {err}"),
+      Err(err) => {
+        eprintln!("encountered websocket error: {err}");
+        break
+      },
+    }
+  }
+}
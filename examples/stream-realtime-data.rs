@@ -5,6 +5,7 @@
 
 use apca::data::v2::stream::drive;
 use apca::data::v2::stream::Bar;
+use apca::data::v2::stream::DataMessage;
 use apca::data::v2::stream::MarketData;
 use apca::data::v2::stream::Quote;
 use apca::data::v2::stream::RealtimeData;
@@ -77,7 +78,7 @@ async fn main() {
   let client = Client::new(api_info);
 
   let (mut stream, mut subscription) = client
-    .subscribe::<RealtimeData<IEX, Bar, Quote, Trade>>()
+    .subscribe::<RealtimeData<IEX, DataMessage<Bar, Quote, Trade>>>()
     .await
     .unwrap();
 
@@ -91,7 +92,7 @@ async fn main() {
 
   let subscribe = subscription.subscribe(&data).boxed();
   // Actually subscribe with the websocket server.
-  let () = drive(subscribe, &mut stream)
+  let _data = drive(subscribe, &mut stream)
     .await
     .unwrap()
     .unwrap()
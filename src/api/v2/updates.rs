@@ -5,6 +5,9 @@ use std::borrow::Cow;
 
 use async_trait::async_trait;
 
+use chrono::DateTime;
+use chrono::Utc;
+
 use futures::stream::Fuse;
 use futures::stream::Map;
 use futures::stream::SplitSink;
@@ -13,6 +16,8 @@ use futures::FutureExt as _;
 use futures::Sink;
 use futures::StreamExt as _;
 
+use num_decimal::Num;
+
 use serde::Deserialize;
 use serde::Serialize;
 use serde_json::from_slice as json_from_slice;
@@ -36,6 +41,8 @@ use crate::api_info::ApiInfo;
 use crate::subscribable::Subscribable;
 use crate::websocket::connect;
 use crate::websocket::MessageResult;
+use crate::websocket::Monitor;
+use crate::websocket::PING_INTERVAL;
 use crate::Error;
 
 
@@ -238,6 +245,20 @@ pub struct OrderUpdate {
   /// The order that received an update.
   #[serde(rename = "order")]
   pub order: order::Order,
+  /// The time at which the event occurred.
+  #[serde(rename = "timestamp")]
+  pub timestamp: Option<DateTime<Utc>>,
+  /// The price per share at which a fill occurred.
+  ///
+  /// Only set for fill and partial fill events.
+  #[serde(rename = "price")]
+  pub price: Option<Num>,
+  /// The size of the position in the order's symbol after a fill
+  /// occurred.
+  ///
+  /// Only set for fill and partial fill events.
+  #[serde(rename = "position_qty")]
+  pub position_quantity: Option<Num>,
 }
 
 
@@ -361,7 +382,7 @@ where
 }
 
 
-type Stream = Map<Wrapper<WebSocketStream<MaybeTlsStream<TcpStream>>>, MapFn>;
+type Stream = Map<Wrapper<Monitor<WebSocketStream<MaybeTlsStream<TcpStream>>>>, MapFn>;
 type MapFn = fn(Result<wrap::Message, WebSocketError>) -> ParsedMessage;
 
 
@@ -391,7 +412,8 @@ impl Subscribable for OrderUpdates {
       ..
     } = api_info;
 
-    let stream = connect(url).await?.map(map as MapFn);
+    let (stream, _connection) = connect(url, Some(PING_INTERVAL)).await?;
+    let stream = stream.map(map as MapFn);
     let (send, recv) = stream.split();
     let (stream, subscription) = subscribe::subscribe(recv, send);
     let mut stream = stream.fuse();
@@ -506,6 +528,49 @@ mod tests {
       OrderMessage::OrderUpdate(update) => {
         assert_eq!(update.event, OrderStatus::New);
         assert_eq!(update.order.side, order::Side::Buy);
+        assert_eq!(
+          update.timestamp,
+          Some(
+            DateTime::parse_from_rfc3339("2021-12-09T19:48:46.182987144Z")
+              .unwrap()
+              .into()
+          )
+        );
+        assert_eq!(update.price, None);
+        assert_eq!(update.position_quantity, None);
+      },
+      _ => panic!("Decoded unexpected message variant: {message:?}"),
+    }
+  }
+
+  /// Verify that we can decode the fill related information of an
+  /// order update.
+  #[test]
+  fn decode_fill_order_update() {
+    let json = r#"{
+  "stream":"trade_updates","data":{
+    "event":"fill","execution_id":"11111111-2222-3333-4444-555555555555","order":{
+      "asset_class":"us_equity","asset_id":"11111111-2222-3333-4444-555555555555",
+      "canceled_at":null,"client_order_id":"11111111-2222-3333-4444-555555555555",
+      "created_at":"2021-12-09T19:48:46.176628398Z","expired_at":null,
+      "extended_hours":false,"failed_at":null,"filled_at":"2021-12-09T19:48:47.1Z",
+      "filled_avg_price":"175.08","filled_qty":"1","hwm":null,
+      "id":"11111111-2222-3333-4444-555555555555","legs":null,"limit_price":"176",
+      "notional":null,"order_class":"simple","order_type":"limit","qty":"1",
+      "replaced_at":null,"replaced_by":null,"replaces":null,"side":"buy",
+      "status":"filled","stop_price":null,"submitted_at":"2021-12-09T19:48:46.175261379Z",
+      "symbol":"AAPL","time_in_force":"day","trail_percent":null,"trail_price":null,
+      "type":"limit","updated_at":"2021-12-09T19:48:47.1Z"
+    },"price":"175.08","position_qty":"11","qty":"1",
+    "timestamp":"2021-12-09T19:48:47.1Z"
+  }
+}"#;
+    let message = json_from_str::<OrderMessage>(json).unwrap();
+    match message {
+      OrderMessage::OrderUpdate(update) => {
+        assert_eq!(update.event, OrderStatus::Filled);
+        assert_eq!(update.price, Some(Num::new(17508, 100)));
+        assert_eq!(update.position_quantity, Some(Num::from(11)));
       },
       _ => panic!("Decoded unexpected message variant: {message:?}"),
     }
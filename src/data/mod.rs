@@ -1,6 +1,12 @@
 // Copyright (C) 2020-2022 The apca Developers
 // SPDX-License-Identifier: GPL-3.0-or-later
 
+/// Definitions for the `v1beta1` version of the Alpaca Data API, which
+/// covers news.
+pub mod v1beta1;
+/// Definitions for the `v1beta3` version of the Alpaca Data API, which
+/// covers crypto market data.
+pub mod v1beta3;
 /// Definitions for the second version of the Alpaca Data API.
 pub mod v2;
 
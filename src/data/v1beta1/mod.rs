@@ -0,0 +1,5 @@
+// Copyright (C) 2023 The apca Developers
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+/// Definitions for real-time streaming of news.
+pub mod news;
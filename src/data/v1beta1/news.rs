@@ -0,0 +1,420 @@
+// Copyright (C) 2023 The apca Developers
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use chrono::DateTime;
+use chrono::Utc;
+
+use serde::de::Error as _;
+use serde::Deserialize;
+use serde::Deserializer;
+use serde::Serialize;
+use serde::Serializer;
+use serde_json::Value as JsonValue;
+
+use crate::data::v2::stream::private;
+use crate::data::v2::stream::ControlMessage;
+use crate::data::v2::stream::Count;
+use crate::data::v2::stream::MessageKind;
+use crate::data::v2::stream::Source;
+use crate::data::v2::stream::SourceVariant;
+use crate::data::v2::stream::StreamApiError;
+use crate::data::v2::stream::StreamMessage;
+use crate::data::v2::stream::SymbolList;
+use crate::data::v2::stream::Symbols;
+
+pub use crate::data::v2::stream::drive;
+pub use crate::data::v2::stream::Channels;
+
+
+/// A news article.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct News {
+  /// The article's ID.
+  #[serde(rename = "id")]
+  pub id: u64,
+  /// The article's headline.
+  #[serde(rename = "headline")]
+  pub headline: String,
+  /// A summary of the article.
+  #[serde(rename = "summary")]
+  pub summary: String,
+  /// The article's author.
+  #[serde(rename = "author")]
+  pub author: String,
+  /// The symbols the article relates to.
+  #[serde(rename = "symbols")]
+  pub symbols: Vec<String>,
+  /// The source of the article, e.g., `benzinga`.
+  #[serde(rename = "source")]
+  pub source: String,
+  /// The URL of the article.
+  #[serde(rename = "url", default)]
+  pub url: Option<String>,
+  /// The time at which the article was created.
+  #[serde(rename = "created_at")]
+  pub created_at: DateTime<Utc>,
+  /// The time at which the article was last updated.
+  #[serde(rename = "updated_at")]
+  pub updated_at: DateTime<Utc>,
+}
+
+
+/// The number of symbols news are subscribed to for, as reported by
+/// [`Channels::counts`] for [`MarketData`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub struct SubscriptionCounts {
+  /// The number of news symbols.
+  pub news: Count,
+}
+
+
+/// A type defining the news a client intends to subscribe to.
+///
+/// Please refer to
+/// [`v2::stream::MarketData`][crate::data::v2::stream::MarketData] for
+/// details on how objects of this type are (de)serialized.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct MarketData {
+  /// The symbols for which to subscribe to news.
+  #[serde(default, skip_serializing_if = "Symbols::is_empty")]
+  pub news: Symbols,
+}
+
+impl MarketData {
+  /// A convenience function for setting the [`news`][MarketData::news]
+  /// member.
+  #[inline]
+  pub fn set_news<S>(&mut self, symbols: S)
+  where
+    S: Into<SymbolList>,
+  {
+    self.news = Symbols::from(symbols.into());
+  }
+}
+
+impl Channels for MarketData {
+  type Counts = SubscriptionCounts;
+
+  const NAMES: &'static [&'static str] = &["news"];
+
+  fn fields(&self) -> Vec<&Symbols> {
+    vec![&self.news]
+  }
+
+  fn fields_mut(&mut self) -> Vec<&mut Symbols> {
+    vec![&mut self.news]
+  }
+
+  #[inline]
+  fn counts(&self) -> SubscriptionCounts {
+    SubscriptionCounts {
+      news: self.news.count(),
+    }
+  }
+}
+
+impl private::Sealed for MarketData {}
+
+
+/// An enum representing the different messages we may receive over our
+/// websocket channel.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[doc(hidden)]
+#[serde(remote = "Self", tag = "T")]
+#[allow(clippy::large_enum_variant)]
+pub enum DataMessage {
+  /// A variant representing a news article.
+  #[serde(rename = "n")]
+  News(News),
+  /// A control message describing the current list of subscriptions.
+  #[serde(rename = "subscription")]
+  Subscription(MarketData),
+  /// A control message indicating that the last operation was
+  /// successful.
+  #[serde(rename = "success")]
+  Success {
+    /// The message accompanying the success indication, e.g.,
+    /// `connected` or `authenticated`.
+    #[serde(rename = "msg", default)]
+    message: String,
+  },
+  /// An error reported by the Alpaca Stream API.
+  #[serde(rename = "error")]
+  Error(StreamApiError),
+  /// A message of a type not known to this crate, in its raw JSON
+  /// form (including the `"T"` tag).
+  #[serde(skip)]
+  Unknown(JsonValue),
+}
+
+impl<'de> Deserialize<'de> for DataMessage {
+  fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+  where
+    D: Deserializer<'de>,
+  {
+    let value = JsonValue::deserialize(deserializer)?;
+    match value.get("T").and_then(JsonValue::as_str) {
+      Some("n" | "subscription" | "success" | "error") => {
+        Self::deserialize(value).map_err(D::Error::custom)
+      },
+      _ => Ok(Self::Unknown(value)),
+    }
+  }
+}
+
+impl Serialize for DataMessage {
+  #[inline]
+  fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+  where
+    S: Serializer,
+  {
+    match self {
+      Self::Unknown(value) => value.serialize(serializer),
+      _ => Self::serialize(self, serializer),
+    }
+  }
+}
+
+impl StreamMessage for DataMessage {
+  type Data = Data;
+  type Channels = MarketData;
+
+  fn kind(&self) -> MessageKind {
+    match self {
+      Self::News(..) => MessageKind::OtherData,
+      Self::Subscription(..) => MessageKind::Subscription,
+      Self::Success { .. } => MessageKind::Success,
+      Self::Error(..) => MessageKind::Error,
+      Self::Unknown(..) => MessageKind::Unknown,
+    }
+  }
+
+  fn classify(self) -> Result<Self::Data, ControlMessage<MarketData>> {
+    match self {
+      Self::News(news) => Ok(Data::News(news)),
+      Self::Subscription(data) => Err(ControlMessage::Subscription(data)),
+      Self::Success { message } => Err(ControlMessage::Success { message }),
+      Self::Error(error) => Err(ControlMessage::Error(error)),
+      Self::Unknown(value) => Err(ControlMessage::Unknown(value)),
+    }
+  }
+
+  fn to_control(&self) -> Option<ControlMessage<MarketData>> {
+    match self {
+      Self::Subscription(data) => Some(ControlMessage::Subscription(data.clone())),
+      Self::Success { message } => Some(ControlMessage::Success {
+        message: message.clone(),
+      }),
+      Self::Error(error) => Some(ControlMessage::Error(error.clone())),
+      Self::Unknown(value) => Some(ControlMessage::Unknown(value.clone())),
+      Self::News(..) => None,
+    }
+  }
+}
+
+
+/// A data item as received over our websocket channel.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Data {
+  /// A variant representing a news article.
+  News(News),
+}
+
+impl Data {
+  /// Check whether this object is of the `News` variant.
+  #[inline]
+  pub fn is_news(&self) -> bool {
+    matches!(self, Self::News(..))
+  }
+}
+
+
+/// The source of realtime news.
+#[derive(Clone, Copy, Debug)]
+pub enum NewsSource {}
+
+impl Source for NewsSource {
+  #[inline]
+  fn source() -> SourceVariant {
+    SourceVariant::Path("v1beta1/news")
+  }
+}
+
+impl private::Sealed for NewsSource {}
+
+
+/// A subscription allowing certain control operations pertaining
+/// a real time news stream.
+///
+/// Please refer to [`v2::stream::Subscription`][crate::data::v2::stream::Subscription]
+/// for details. News are subscribed to via the
+/// [`news`][MarketData::news] member of [`MarketData`].
+pub type Subscription<S> = crate::data::v2::stream::Subscription<S, DataMessage>;
+
+
+/// A type used for requesting a subscription to real time news.
+///
+/// The news stream shares its handshake and control protocol with the
+/// `v2` stock data stream. As such, all the functionality of
+/// [`v2::stream::RealtimeData`][crate::data::v2::stream::RealtimeData]
+/// is available.
+pub type RealtimeNews = crate::data::v2::stream::RealtimeData<NewsSource, DataMessage>;
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use std::borrow::Cow;
+  use std::str::FromStr;
+
+  use futures::FutureExt as _;
+  use futures::SinkExt as _;
+  use futures::StreamExt as _;
+  use futures::TryStreamExt as _;
+
+  use serde_json::from_str as json_from_str;
+  use serde_json::to_string as to_json;
+
+  use test_log::test;
+
+  use websocket_util::test::WebSocketStream;
+  use websocket_util::tungstenite::Error as WebSocketError;
+  use websocket_util::tungstenite::Message;
+
+  use crate::data::v2::stream::Request;
+  use crate::websocket::test::mock_stream;
+  use crate::Error;
+
+
+  const CONN_RESP: &str = r#"[{"T":"success","msg":"connected"}]"#;
+  const AUTH_REQ: &str = r#"{"action":"auth","key":"USER12345678","secret":"justletmein"}"#;
+  const AUTH_RESP: &str = r#"[{"T":"success","msg":"authenticated"}]"#;
+  const SUB_REQ: &str = r#"{"action":"subscribe","news":["AAPL","TSLA"]}"#;
+  const SUB_RESP: &str = r#"[{"T":"subscription","news":["AAPL","TSLA"]}]"#;
+
+
+  /// Check that we can deserialize and serialize the
+  /// [`DataMessage::News`] variant.
+  #[test]
+  fn serialize_deserialize_news() {
+    let json = r#"{
+  "T": "n",
+  "id": 24918784,
+  "headline": "Corsair Reports Purchase Of Majority Ownership In iDisplay, No Terms Disclosed",
+  "summary": "Corsair Gaming, Inc. (NASDAQ:CRSR) (“Corsair”), a leading global provider and innovator of high-performance gear for gamers and content creators, today announced that it acquired a 51% stake in iDisplay",
+  "author": "Benzinga Newsdesk",
+  "created_at": "2022-01-05T22:00:37Z",
+  "updated_at": "2022-01-05T22:00:38Z",
+  "url": "https://www.benzinga.com/m-a/22/01/24918784/corsair-reports-purchase-of-majority-ownership-in-idisplay-no-terms-disclosed",
+  "content": "<p>Corsair Gaming, Inc. (NASDAQ:<a class=\"ticker\" href=\"https://www.benzinga.com/quote/CRSR\">CRSR</a>) (&ldquo;Corsair&rdquo;), a leading global ...",
+  "symbols": ["CRSR"],
+  "source": "benzinga"
+}"#;
+
+    let message = json_from_str::<DataMessage>(json).unwrap();
+    let news = match &message {
+      DataMessage::News(news) => news,
+      _ => panic!("Decoded unexpected message variant: {message:?}"),
+    };
+    assert_eq!(news.id, 24918784);
+    assert_eq!(
+      news.headline,
+      "Corsair Reports Purchase Of Majority Ownership In iDisplay, No Terms Disclosed"
+    );
+    assert_eq!(news.author, "Benzinga Newsdesk");
+    assert_eq!(news.symbols, vec!["CRSR"]);
+    assert_eq!(news.source, "benzinga");
+    assert_eq!(
+      news.created_at,
+      DateTime::<Utc>::from_str("2022-01-05T22:00:37Z").unwrap()
+    );
+    assert_eq!(
+      news.updated_at,
+      DateTime::<Utc>::from_str("2022-01-05T22:00:38Z").unwrap()
+    );
+
+    assert_eq!(
+      json_from_str::<DataMessage>(&to_json(&message).unwrap()).unwrap(),
+      message
+    );
+  }
+
+  /// Check that we can deserialize messages of unknown type into the
+  /// [`DataMessage::Unknown`] variant.
+  #[test]
+  fn serialize_deserialize_unknown() {
+    let json = r#"{"T":"x","foo":42}"#;
+    let message = json_from_str::<DataMessage>(json).unwrap();
+    let value = match &message {
+      DataMessage::Unknown(value) => value,
+      _ => panic!("Decoded unexpected message variant: {message:?}"),
+    };
+    assert_eq!(value["T"], "x");
+    assert_eq!(to_json(&message).unwrap(), json);
+    assert_eq!(message.kind(), MessageKind::Unknown);
+  }
+
+  /// Check that we can serialize and deserialize the
+  /// [`Request::Subscribe`] variant properly.
+  #[test]
+  fn serialize_deserialize_subscribe_request() {
+    let mut data = MarketData::default();
+    data.set_news(["TSLA", "AAPL"]);
+    let request = Request::Subscribe(Cow::Borrowed(&data));
+
+    let json = to_json(&request).unwrap();
+    assert_eq!(json, SUB_REQ);
+    assert_eq!(
+      json_from_str::<Request<'_, MarketData>>(&json).unwrap(),
+      request
+    );
+  }
+
+  /// Check that we can correctly handle a successful subscription
+  /// without pushing actual data.
+  #[test(tokio::test)]
+  async fn authenticate_and_subscribe() {
+    async fn test(mut stream: WebSocketStream) -> Result<(), WebSocketError> {
+      stream.send(Message::Text(CONN_RESP.to_string())).await?;
+      // Authentication.
+      assert_eq!(
+        stream.next().await.unwrap()?,
+        Message::Text(AUTH_REQ.to_string()),
+      );
+      stream.send(Message::Text(AUTH_RESP.to_string())).await?;
+
+      // Subscription.
+      assert_eq!(
+        stream.next().await.unwrap()?,
+        Message::Text(SUB_REQ.to_string()),
+      );
+      stream.send(Message::Text(SUB_RESP.to_string())).await?;
+      stream.send(Message::Close(None)).await?;
+      Ok(())
+    }
+
+    let (mut stream, mut subscription) = mock_stream::<RealtimeNews, _, _>(test).await.unwrap();
+
+    let mut data = MarketData::default();
+    data.set_news(["AAPL", "TSLA"]);
+
+    let subscribe = subscription.subscribe(&data).boxed_local();
+    let subscribed = drive(subscribe, &mut stream)
+      .await
+      .unwrap()
+      .unwrap()
+      .unwrap();
+
+    assert_eq!(subscribed, data);
+    assert_eq!(subscription.subscriptions(), &data);
+
+    stream
+      .map_err(Error::WebSocket)
+      .try_for_each(|result| async { result.map(|_data| ()).map_err(Error::Json) })
+      .await
+      .unwrap();
+  }
+}
@@ -0,0 +1,888 @@
+// Copyright (C) 2023 The apca Developers
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use chrono::DateTime;
+use chrono::Utc;
+
+use num_decimal::Num;
+
+use serde::de::Error as _;
+use serde::Deserialize;
+use serde::Deserializer;
+use serde::Serialize;
+use serde::Serializer;
+use serde_json::Value as JsonValue;
+
+use crate::data::v2::stream::private;
+use crate::data::v2::stream::ControlMessage;
+use crate::data::v2::stream::Count;
+use crate::data::v2::stream::MessageKind;
+use crate::data::v2::stream::Source;
+use crate::data::v2::stream::SourceVariant;
+use crate::data::v2::stream::StreamApiError;
+use crate::data::v2::stream::StreamMessage;
+use crate::data::v2::stream::SymbolList;
+use crate::data::v2::stream::Symbols;
+
+pub use crate::data::v2::stream::drive;
+pub use crate::data::v2::stream::BarKind;
+pub use crate::data::v2::stream::Channels;
+
+
+/// Aggregate data for a crypto currency.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Bar {
+  /// The bar's symbol, e.g., `BTC/USD`.
+  #[serde(rename = "S")]
+  pub symbol: String,
+  /// The bar's open price.
+  #[serde(rename = "o")]
+  pub open_price: Num,
+  /// The bar's high price.
+  #[serde(rename = "h")]
+  pub high_price: Num,
+  /// The bar's low price.
+  #[serde(rename = "l")]
+  pub low_price: Num,
+  /// The bar's close price.
+  #[serde(rename = "c")]
+  pub close_price: Num,
+  /// The bar's volume.
+  #[serde(rename = "v")]
+  pub volume: Num,
+  /// The bar's time stamp.
+  #[serde(rename = "t")]
+  pub timestamp: DateTime<Utc>,
+}
+
+
+/// A quote for a crypto currency.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Quote {
+  /// The quote's symbol, e.g., `BTC/USD`.
+  #[serde(rename = "S")]
+  pub symbol: String,
+  /// The bid's price.
+  #[serde(rename = "bp")]
+  pub bid_price: Num,
+  /// The bid's size.
+  #[serde(rename = "bs")]
+  pub bid_size: Num,
+  /// The ask's price.
+  #[serde(rename = "ap")]
+  pub ask_price: Num,
+  /// The ask's size.
+  #[serde(rename = "as")]
+  pub ask_size: Num,
+  /// The quote's time stamp.
+  #[serde(rename = "t")]
+  pub timestamp: DateTime<Utc>,
+}
+
+
+/// A trade for a crypto currency.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Trade {
+  /// The trade's symbol, e.g., `BTC/USD`.
+  #[serde(rename = "S")]
+  pub symbol: String,
+  /// The trade's ID.
+  #[serde(rename = "i")]
+  pub trade_id: u64,
+  /// The trade's price.
+  #[serde(rename = "p")]
+  pub trade_price: Num,
+  /// The trade's size.
+  #[serde(rename = "s")]
+  pub trade_size: Num,
+  /// The trade's time stamp.
+  #[serde(rename = "t")]
+  pub timestamp: DateTime<Utc>,
+}
+
+
+/// A single price level of an order book.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Level {
+  /// The level's price.
+  #[serde(rename = "p")]
+  pub price: Num,
+  /// The size available at the level's price.
+  #[serde(rename = "s")]
+  pub size: Num,
+}
+
+
+/// An order book update for a crypto currency.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct OrderBook {
+  /// The order book's symbol, e.g., `BTC/USD`.
+  #[serde(rename = "S")]
+  pub symbol: String,
+  /// The bid levels.
+  #[serde(rename = "b")]
+  pub bids: Vec<Level>,
+  /// The ask levels.
+  #[serde(rename = "a")]
+  pub asks: Vec<Level>,
+  /// The order book's time stamp.
+  #[serde(rename = "t")]
+  pub timestamp: DateTime<Utc>,
+}
+
+
+/// The number of symbols per kind of crypto market data, as reported
+/// by [`Channels::counts`] for [`MarketData`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub struct SubscriptionCounts {
+  /// The number of aggregate bar symbols.
+  pub bars: Count,
+  /// The number of quote symbols.
+  pub quotes: Count,
+  /// The number of trade symbols.
+  pub trades: Count,
+  /// The number of daily aggregate bar symbols.
+  pub daily_bars: Count,
+  /// The number of updated aggregate bar symbols.
+  pub updated_bars: Count,
+  /// The number of order book symbols.
+  pub orderbooks: Count,
+}
+
+
+/// A type defining the crypto market data a client intends to
+/// subscribe to.
+///
+/// Please refer to
+/// [`v2::stream::MarketData`][crate::data::v2::stream::MarketData] for
+/// details on how objects of this type are (de)serialized.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct MarketData {
+  /// The aggregate bars to subscribe to.
+  #[serde(default, skip_serializing_if = "Symbols::is_empty")]
+  pub bars: Symbols,
+  /// The quotes to subscribe to.
+  #[serde(default, skip_serializing_if = "Symbols::is_empty")]
+  pub quotes: Symbols,
+  /// The trades to subscribe to.
+  #[serde(default, skip_serializing_if = "Symbols::is_empty")]
+  pub trades: Symbols,
+  /// The daily aggregate bars to subscribe to.
+  #[serde(
+    default,
+    rename = "dailyBars",
+    skip_serializing_if = "Symbols::is_empty"
+  )]
+  pub daily_bars: Symbols,
+  /// The updated aggregate bars to subscribe to.
+  ///
+  /// Updated bars correct minute bars reported earlier, e.g., due to
+  /// late trades.
+  #[serde(
+    default,
+    rename = "updatedBars",
+    skip_serializing_if = "Symbols::is_empty"
+  )]
+  pub updated_bars: Symbols,
+  /// The order books to subscribe to.
+  #[serde(default, skip_serializing_if = "Symbols::is_empty")]
+  pub orderbooks: Symbols,
+}
+
+impl MarketData {
+  /// A convenience function for setting the [`bars`][MarketData::bars]
+  /// member.
+  #[inline]
+  pub fn set_bars<S>(&mut self, symbols: S)
+  where
+    S: Into<SymbolList>,
+  {
+    self.bars = Symbols::from(symbols.into());
+  }
+
+  /// A convenience function for setting the [`quotes`][MarketData::quotes]
+  /// member.
+  #[inline]
+  pub fn set_quotes<S>(&mut self, symbols: S)
+  where
+    S: Into<SymbolList>,
+  {
+    self.quotes = Symbols::from(symbols.into());
+  }
+
+  /// A convenience function for setting the [`trades`][MarketData::trades]
+  /// member.
+  #[inline]
+  pub fn set_trades<S>(&mut self, symbols: S)
+  where
+    S: Into<SymbolList>,
+  {
+    self.trades = Symbols::from(symbols.into());
+  }
+
+  /// A convenience function for setting the
+  /// [`daily_bars`][MarketData::daily_bars] member.
+  #[inline]
+  pub fn set_daily_bars<S>(&mut self, symbols: S)
+  where
+    S: Into<SymbolList>,
+  {
+    self.daily_bars = Symbols::from(symbols.into());
+  }
+
+  /// A convenience function for setting the
+  /// [`updated_bars`][MarketData::updated_bars] member.
+  #[inline]
+  pub fn set_updated_bars<S>(&mut self, symbols: S)
+  where
+    S: Into<SymbolList>,
+  {
+    self.updated_bars = Symbols::from(symbols.into());
+  }
+
+  /// A convenience function for setting the
+  /// [`orderbooks`][MarketData::orderbooks] member.
+  #[inline]
+  pub fn set_orderbooks<S>(&mut self, symbols: S)
+  where
+    S: Into<SymbolList>,
+  {
+    self.orderbooks = Symbols::from(symbols.into());
+  }
+}
+
+impl Channels for MarketData {
+  type Counts = SubscriptionCounts;
+
+  const NAMES: &'static [&'static str] = &[
+    "bars",
+    "quotes",
+    "trades",
+    "daily bars",
+    "updated bars",
+    "order books",
+  ];
+
+  fn fields(&self) -> Vec<&Symbols> {
+    vec![
+      &self.bars,
+      &self.quotes,
+      &self.trades,
+      &self.daily_bars,
+      &self.updated_bars,
+      &self.orderbooks,
+    ]
+  }
+
+  fn fields_mut(&mut self) -> Vec<&mut Symbols> {
+    vec![
+      &mut self.bars,
+      &mut self.quotes,
+      &mut self.trades,
+      &mut self.daily_bars,
+      &mut self.updated_bars,
+      &mut self.orderbooks,
+    ]
+  }
+
+  #[inline]
+  fn counts(&self) -> SubscriptionCounts {
+    SubscriptionCounts {
+      bars: self.bars.count(),
+      quotes: self.quotes.count(),
+      trades: self.trades.count(),
+      daily_bars: self.daily_bars.count(),
+      updated_bars: self.updated_bars.count(),
+      orderbooks: self.orderbooks.count(),
+    }
+  }
+}
+
+impl private::Sealed for MarketData {}
+
+
+/// An enum representing the different messages we may receive over our
+/// websocket channel.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[doc(hidden)]
+#[serde(remote = "Self", tag = "T")]
+#[allow(clippy::large_enum_variant)]
+pub enum DataMessage {
+  /// A variant representing aggregate data for a given symbol.
+  #[serde(rename = "b")]
+  Bar(Bar),
+  /// A variant representing a quote for a given symbol.
+  #[serde(rename = "q")]
+  Quote(Quote),
+  /// A variant representing a trade for a given symbol.
+  #[serde(rename = "t")]
+  Trade(Trade),
+  /// A variant representing daily aggregate data for a given symbol.
+  #[serde(rename = "d")]
+  DailyBar(Bar),
+  /// A variant representing aggregate data for a given symbol that
+  /// corrects a previously reported bar, e.g., due to late trades.
+  #[serde(rename = "u")]
+  UpdatedBar(Bar),
+  /// A variant representing an order book update for a given symbol.
+  #[serde(rename = "o")]
+  OrderBook(OrderBook),
+  /// A control message describing the current list of subscriptions.
+  #[serde(rename = "subscription")]
+  Subscription(MarketData),
+  /// A control message indicating that the last operation was
+  /// successful.
+  #[serde(rename = "success")]
+  Success {
+    /// The message accompanying the success indication, e.g.,
+    /// `connected` or `authenticated`.
+    #[serde(rename = "msg", default)]
+    message: String,
+  },
+  /// An error reported by the Alpaca Stream API.
+  #[serde(rename = "error")]
+  Error(StreamApiError),
+  /// A message of a type not known to this crate, in its raw JSON
+  /// form (including the `"T"` tag).
+  #[serde(skip)]
+  Unknown(JsonValue),
+}
+
+impl<'de> Deserialize<'de> for DataMessage {
+  fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+  where
+    D: Deserializer<'de>,
+  {
+    // Contrary to the stock data stream, we buffer each message in
+    // order to inspect its tag, as the crypto stream's volume does not
+    // warrant a hand written visitor.
+    let value = JsonValue::deserialize(deserializer)?;
+    match value.get("T").and_then(JsonValue::as_str) {
+      Some("b" | "q" | "t" | "d" | "u" | "o" | "subscription" | "success" | "error") => {
+        Self::deserialize(value).map_err(D::Error::custom)
+      },
+      _ => Ok(Self::Unknown(value)),
+    }
+  }
+}
+
+impl Serialize for DataMessage {
+  #[inline]
+  fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+  where
+    S: Serializer,
+  {
+    match self {
+      Self::Unknown(value) => value.serialize(serializer),
+      _ => Self::serialize(self, serializer),
+    }
+  }
+}
+
+impl StreamMessage for DataMessage {
+  type Data = Data;
+  type Channels = MarketData;
+
+  fn kind(&self) -> MessageKind {
+    match self {
+      Self::Bar(..) => MessageKind::Bar,
+      Self::Quote(..) => MessageKind::Quote,
+      Self::Trade(..) => MessageKind::Trade,
+      Self::DailyBar(..) => MessageKind::DailyBar,
+      Self::UpdatedBar(..) => MessageKind::UpdatedBar,
+      Self::OrderBook(..) => MessageKind::OtherData,
+      Self::Subscription(..) => MessageKind::Subscription,
+      Self::Success { .. } => MessageKind::Success,
+      Self::Error(..) => MessageKind::Error,
+      Self::Unknown(..) => MessageKind::Unknown,
+    }
+  }
+
+  fn classify(self) -> Result<Self::Data, ControlMessage<MarketData>> {
+    match self {
+      Self::Bar(bar) => Ok(Data::Bar {
+        bar,
+        kind: BarKind::Minute,
+      }),
+      Self::Quote(quote) => Ok(Data::Quote(quote)),
+      Self::Trade(trade) => Ok(Data::Trade(trade)),
+      Self::DailyBar(bar) => Ok(Data::Bar {
+        bar,
+        kind: BarKind::Daily,
+      }),
+      Self::UpdatedBar(bar) => Ok(Data::Bar {
+        bar,
+        kind: BarKind::Updated,
+      }),
+      Self::OrderBook(book) => Ok(Data::OrderBook(book)),
+      Self::Subscription(data) => Err(ControlMessage::Subscription(data)),
+      Self::Success { message } => Err(ControlMessage::Success { message }),
+      Self::Error(error) => Err(ControlMessage::Error(error)),
+      Self::Unknown(value) => Err(ControlMessage::Unknown(value)),
+    }
+  }
+
+  fn to_control(&self) -> Option<ControlMessage<MarketData>> {
+    match self {
+      Self::Subscription(data) => Some(ControlMessage::Subscription(data.clone())),
+      Self::Success { message } => Some(ControlMessage::Success {
+        message: message.clone(),
+      }),
+      Self::Error(error) => Some(ControlMessage::Error(error.clone())),
+      Self::Unknown(value) => Some(ControlMessage::Unknown(value.clone())),
+      Self::Bar(..)
+      | Self::Quote(..)
+      | Self::Trade(..)
+      | Self::DailyBar(..)
+      | Self::UpdatedBar(..)
+      | Self::OrderBook(..) => None,
+    }
+  }
+}
+
+
+/// A data item as received over our websocket channel.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Data {
+  /// A variant representing aggregate data for a given symbol.
+  Bar {
+    /// The actual bar.
+    bar: Bar,
+    /// The cadence of the bar.
+    kind: BarKind,
+  },
+  /// A variant representing quote data for a given symbol.
+  Quote(Quote),
+  /// A variant representing trade data for a given symbol.
+  Trade(Trade),
+  /// A variant representing an order book update for a given symbol.
+  OrderBook(OrderBook),
+}
+
+impl Data {
+  /// Check whether this object is of the `Bar` variant, irrespective
+  /// of the bar's cadence.
+  #[inline]
+  pub fn is_bar(&self) -> bool {
+    matches!(self, Self::Bar { .. })
+  }
+
+  /// Check whether this object is of the `Quote` variant.
+  #[inline]
+  pub fn is_quote(&self) -> bool {
+    matches!(self, Self::Quote(..))
+  }
+
+  /// Check whether this object is of the `Trade` variant.
+  #[inline]
+  pub fn is_trade(&self) -> bool {
+    matches!(self, Self::Trade(..))
+  }
+
+  /// Check whether this object is of the `OrderBook` variant.
+  #[inline]
+  pub fn is_order_book(&self) -> bool {
+    matches!(self, Self::OrderBook(..))
+  }
+
+  /// Check whether this object is of the `Bar` variant representing a
+  /// daily bar.
+  #[inline]
+  pub fn is_daily_bar(&self) -> bool {
+    matches!(
+      self,
+      Self::Bar {
+        kind: BarKind::Daily,
+        ..
+      }
+    )
+  }
+}
+
+
+/// The source of realtime crypto market data.
+#[derive(Clone, Copy, Debug)]
+pub enum Crypto {}
+
+impl Source for Crypto {
+  #[inline]
+  fn source() -> SourceVariant {
+    SourceVariant::Path("v1beta3/crypto/us")
+  }
+}
+
+impl private::Sealed for Crypto {}
+
+
+/// A subscription allowing certain control operations pertaining
+/// a real time crypto market data stream.
+///
+/// Please refer to [`v2::stream::Subscription`][crate::data::v2::stream::Subscription]
+/// for details.
+pub type Subscription<S> = crate::data::v2::stream::Subscription<S, DataMessage>;
+
+
+/// A type used for requesting a subscription to real time crypto
+/// market data.
+///
+/// The crypto stream shares its handshake and control protocol with
+/// the `v2` stock data stream. As such, all the functionality of
+/// [`v2::stream::RealtimeData`][crate::data::v2::stream::RealtimeData],
+/// e.g., connecting with a custom
+/// [`StreamConfig`][crate::data::v2::stream::StreamConfig], is
+/// available.
+pub type RealtimeData = crate::data::v2::stream::RealtimeData<Crypto, DataMessage>;
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use std::borrow::Cow;
+  use std::str::FromStr;
+
+  use futures::channel::oneshot;
+  use futures::FutureExt as _;
+  use futures::SinkExt as _;
+  use futures::StreamExt as _;
+  use futures::TryStreamExt as _;
+
+  use serde_json::from_str as json_from_str;
+  use serde_json::to_string as to_json;
+
+  use test_log::test;
+
+  use websocket_util::test::WebSocketStream;
+  use websocket_util::tungstenite::Error as WebSocketError;
+  use websocket_util::tungstenite::Message;
+
+  use crate::data::v2::stream::Request;
+  use crate::websocket::test::mock_stream;
+  use crate::Error;
+
+
+  const CONN_RESP: &str = r#"[{"T":"success","msg":"connected"}]"#;
+  const AUTH_REQ: &str = r#"{"action":"auth","key":"USER12345678","secret":"justletmein"}"#;
+  const AUTH_RESP: &str = r#"[{"T":"success","msg":"authenticated"}]"#;
+  const SUB_REQ: &str = r#"{"action":"subscribe","trades":["BTC/USD"],"orderbooks":["ETH/USD"]}"#;
+  const SUB_RESP: &str = r#"[{"T":"subscription","trades":["BTC/USD"],"quotes":[],"orderbooks":["ETH/USD"],"bars":[],"updatedBars":[],"dailyBars":[]}]"#;
+
+
+  /// Check that we can deserialize and serialize the
+  /// [`DataMessage::Bar`] variant.
+  #[test]
+  fn serialize_deserialize_bar() {
+    let json = r#"{
+  "T": "b",
+  "S": "BTC/USD",
+  "o": 26959.14,
+  "h": 26962.18,
+  "l": 26950.2,
+  "c": 26950.2,
+  "v": 0.6384,
+  "t": "2023-09-29T10:09:00Z",
+  "n": 21,
+  "vw": 26954.6387832
+}"#;
+
+    let message = json_from_str::<DataMessage>(json).unwrap();
+    let bar = match &message {
+      DataMessage::Bar(bar) => bar,
+      _ => panic!("Decoded unexpected message variant: {message:?}"),
+    };
+    assert_eq!(bar.symbol, "BTC/USD");
+    assert_eq!(bar.open_price, Num::new(2695914, 100));
+    assert_eq!(bar.high_price, Num::new(2696218, 100));
+    assert_eq!(bar.low_price, Num::new(269502, 10));
+    assert_eq!(bar.close_price, Num::new(269502, 10));
+    assert_eq!(bar.volume, Num::new(6384, 10000));
+    assert_eq!(
+      bar.timestamp,
+      DateTime::<Utc>::from_str("2023-09-29T10:09:00Z").unwrap()
+    );
+
+    assert_eq!(
+      json_from_str::<DataMessage>(&to_json(&message).unwrap()).unwrap(),
+      message
+    );
+  }
+
+  /// Check that we can deserialize and serialize the
+  /// [`DataMessage::DailyBar`] and [`DataMessage::UpdatedBar`]
+  /// variants.
+  #[test]
+  fn serialize_deserialize_daily_updated_bar() {
+    let json = r#"{
+  "T": "d",
+  "S": "BTC/USD",
+  "o": 26959.14,
+  "h": 27101.5,
+  "l": 26807.3,
+  "c": 26950.2,
+  "v": 1824.0135,
+  "t": "2023-09-29T00:00:00Z"
+}"#;
+
+    let message = json_from_str::<DataMessage>(json).unwrap();
+    let bar = match &message {
+      DataMessage::DailyBar(bar) => bar,
+      _ => panic!("Decoded unexpected message variant: {message:?}"),
+    };
+    assert_eq!(bar.symbol, "BTC/USD");
+    assert_eq!(bar.high_price, Num::new(271015, 10));
+    assert_eq!(
+      json_from_str::<DataMessage>(&to_json(&message).unwrap()).unwrap(),
+      message
+    );
+    assert!(message.classify().unwrap().is_daily_bar());
+
+    let json = json.replace(r#""T": "d""#, r#""T": "u""#);
+    let message = json_from_str::<DataMessage>(&json).unwrap();
+    assert!(matches!(message, DataMessage::UpdatedBar(..)));
+    assert!(matches!(
+      message.classify().unwrap(),
+      Data::Bar {
+        kind: BarKind::Updated,
+        ..
+      }
+    ));
+  }
+
+  /// Check that we can deserialize messages of unknown type into the
+  /// [`DataMessage::Unknown`] variant.
+  #[test]
+  fn serialize_deserialize_unknown() {
+    let json = r#"{"T":"x","foo":42}"#;
+    let message = json_from_str::<DataMessage>(json).unwrap();
+    let value = match &message {
+      DataMessage::Unknown(value) => value,
+      _ => panic!("Decoded unexpected message variant: {message:?}"),
+    };
+    assert_eq!(value["T"], "x");
+    assert_eq!(to_json(&message).unwrap(), json);
+    assert_eq!(message.kind(), MessageKind::Unknown);
+
+    // A malformed message of known type should still fail to decode.
+    let json = r#"{"T":"b","S":"BTC/USD"}"#;
+    let _err = json_from_str::<DataMessage>(json).unwrap_err();
+  }
+
+  /// Check that we can deserialize and serialize the
+  /// [`DataMessage::Quote`] variant.
+  #[test]
+  fn serialize_deserialize_quote() {
+    let json = r#"{
+  "T": "q",
+  "S": "BTC/USD",
+  "bp": 26948.5,
+  "bs": 0.27,
+  "ap": 26951.86,
+  "as": 0.5416,
+  "t": "2023-09-29T10:10:02.613Z"
+}"#;
+
+    let message = json_from_str::<DataMessage>(json).unwrap();
+    let quote = match &message {
+      DataMessage::Quote(quote) => quote,
+      _ => panic!("Decoded unexpected message variant: {message:?}"),
+    };
+    assert_eq!(quote.symbol, "BTC/USD");
+    assert_eq!(quote.bid_price, Num::new(269485, 10));
+    assert_eq!(quote.bid_size, Num::new(27, 100));
+    assert_eq!(quote.ask_price, Num::new(2695186, 100));
+    assert_eq!(quote.ask_size, Num::new(5416, 10000));
+
+    assert_eq!(
+      json_from_str::<DataMessage>(&to_json(&message).unwrap()).unwrap(),
+      message
+    );
+  }
+
+  /// Check that we can deserialize and serialize the
+  /// [`DataMessage::Trade`] variant.
+  #[test]
+  fn serialize_deserialize_trade() {
+    let json = r#"{
+  "T": "t",
+  "S": "BTC/USD",
+  "p": 26950.2,
+  "s": 0.0261,
+  "t": "2023-09-29T10:10:02.516Z",
+  "i": 26493290,
+  "tks": "B"
+}"#;
+
+    let message = json_from_str::<DataMessage>(json).unwrap();
+    let trade = match &message {
+      DataMessage::Trade(trade) => trade,
+      _ => panic!("Decoded unexpected message variant: {message:?}"),
+    };
+    assert_eq!(trade.symbol, "BTC/USD");
+    assert_eq!(trade.trade_id, 26493290);
+    assert_eq!(trade.trade_price, Num::new(269502, 10));
+    assert_eq!(trade.trade_size, Num::new(261, 10000));
+
+    assert_eq!(
+      json_from_str::<DataMessage>(&to_json(&message).unwrap()).unwrap(),
+      message
+    );
+  }
+
+  /// Check that we can deserialize and serialize the
+  /// [`DataMessage::OrderBook`] variant.
+  #[test]
+  fn serialize_deserialize_order_book() {
+    let json = r#"{
+  "T": "o",
+  "S": "BTC/USD",
+  "t": "2023-09-29T10:10:02.604Z",
+  "b": [{"p": 26948.5, "s": 0.27}, {"p": 26947.1, "s": 0}],
+  "a": [{"p": 26951.86, "s": 0.5416}]
+}"#;
+
+    let message = json_from_str::<DataMessage>(json).unwrap();
+    let book = match &message {
+      DataMessage::OrderBook(book) => book,
+      _ => panic!("Decoded unexpected message variant: {message:?}"),
+    };
+    assert_eq!(book.symbol, "BTC/USD");
+    assert_eq!(
+      book.bids,
+      vec![
+        Level {
+          price: Num::new(269485, 10),
+          size: Num::new(27, 100),
+        },
+        Level {
+          price: Num::new(269471, 10),
+          size: Num::from(0),
+        },
+      ]
+    );
+    assert_eq!(
+      book.asks,
+      vec![Level {
+        price: Num::new(2695186, 100),
+        size: Num::new(5416, 10000),
+      }]
+    );
+
+    assert_eq!(
+      json_from_str::<DataMessage>(&to_json(&message).unwrap()).unwrap(),
+      message
+    );
+  }
+
+  /// Check that we can serialize and deserialize the
+  /// [`Request::Subscribe`] variant properly.
+  #[test]
+  fn serialize_deserialize_subscribe_request() {
+    let mut data = MarketData::default();
+    data.set_trades(["BTC/USD"]);
+    data.set_orderbooks(["ETH/USD"]);
+    let request = Request::Subscribe(Cow::Borrowed(&data));
+
+    let json = to_json(&request).unwrap();
+    assert_eq!(json, SUB_REQ);
+    assert_eq!(
+      json_from_str::<Request<'_, MarketData>>(&json).unwrap(),
+      request
+    );
+  }
+
+  /// Check that we can correctly handle a successful subscription
+  /// without pushing actual data.
+  #[test(tokio::test)]
+  async fn authenticate_and_subscribe() {
+    async fn test(mut stream: WebSocketStream) -> Result<(), WebSocketError> {
+      stream.send(Message::Text(CONN_RESP.to_string())).await?;
+      // Authentication.
+      assert_eq!(
+        stream.next().await.unwrap()?,
+        Message::Text(AUTH_REQ.to_string()),
+      );
+      stream.send(Message::Text(AUTH_RESP.to_string())).await?;
+
+      // Subscription.
+      assert_eq!(
+        stream.next().await.unwrap()?,
+        Message::Text(SUB_REQ.to_string()),
+      );
+      stream.send(Message::Text(SUB_RESP.to_string())).await?;
+      stream.send(Message::Close(None)).await?;
+      Ok(())
+    }
+
+    let (mut stream, mut subscription) = mock_stream::<RealtimeData, _, _>(test).await.unwrap();
+
+    let mut data = MarketData::default();
+    data.set_trades(["BTC/USD"]);
+    data.set_orderbooks(["ETH/USD"]);
+
+    let subscribe = subscription.subscribe(&data).boxed_local();
+    let subscribed = drive(subscribe, &mut stream)
+      .await
+      .unwrap()
+      .unwrap()
+      .unwrap();
+
+    assert_eq!(subscribed, data);
+    assert_eq!(subscription.subscriptions(), &data);
+
+    stream
+      .map_err(Error::WebSocket)
+      .try_for_each(|result| async { result.map(|_data| ()).map_err(Error::Json) })
+      .await
+      .unwrap();
+  }
+
+  /// Check that order book updates are routed to the data stream.
+  #[test(tokio::test)]
+  async fn stream_order_book() {
+    async fn test(
+      mut stream: WebSocketStream,
+      connected: oneshot::Receiver<()>,
+    ) -> Result<(), WebSocketError> {
+      stream.send(Message::Text(CONN_RESP.to_string())).await?;
+      // Authentication.
+      assert_eq!(
+        stream.next().await.unwrap()?,
+        Message::Text(AUTH_REQ.to_string()),
+      );
+      stream.send(Message::Text(AUTH_RESP.to_string())).await?;
+
+      // Only send data once the client is done connecting, as it would
+      // otherwise be discarded during the handshake.
+      let () = connected.await.unwrap();
+      let book = r#"[{"T":"o","S":"ETH/USD","t":"2023-09-29T10:10:02.604Z","b":[{"p":1670.5,"s":2}],"a":[]}]"#;
+      stream.send(Message::Text(book.to_string())).await?;
+      stream.send(Message::Close(None)).await?;
+      Ok(())
+    }
+
+    let (connected, wait) = oneshot::channel();
+    let (stream, _subscription) = mock_stream::<RealtimeData, _, _>(|stream| test(stream, wait))
+      .await
+      .unwrap();
+    let () = connected.send(()).unwrap();
+
+    let data = stream.try_collect::<Vec<_>>().await.unwrap();
+    assert_eq!(data.len(), 1);
+
+    let book = match &data[0] {
+      Ok(Data::OrderBook(book)) => book,
+      data => panic!("received unexpected data: {data:?}"),
+    };
+    assert_eq!(book.symbol, "ETH/USD");
+    assert_eq!(
+      book.bids,
+      vec![Level {
+        price: Num::new(16705, 10),
+        size: Num::from(2),
+      }]
+    );
+    assert!(book.asks.is_empty());
+  }
+}
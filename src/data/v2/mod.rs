@@ -2,7 +2,7 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 
 mod feed;
-mod unfold;
+pub(crate) mod unfold;
 
 /// Definitions for retrieval of market data bars.
 pub mod bars;
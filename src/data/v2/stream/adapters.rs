@@ -0,0 +1,791 @@
+// Copyright (C) 2021-2023 The apca Developers
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use futures::stream::Fuse;
+use futures::stream::FusedStream;
+use futures::task::Context;
+use futures::task::Poll;
+use futures::Stream as FuturesStream;
+use futures::StreamExt as _;
+
+use serde_json::Error as JsonError;
+
+use websocket_util::tungstenite::Error as WebSocketError;
+
+use super::close_frame;
+use super::Bar;
+use super::BarKind;
+use super::CloseFrame;
+use super::Data;
+use super::HasSymbol;
+use super::OwnedStreamError;
+use super::Quote;
+use super::StreamMessage;
+use super::Subscription;
+use super::Trade;
+
+use crate::websocket::ConnectionState;
+
+
+/// The type of item produced by the realtime data stream.
+type StreamItem<B, Q, T> = Result<Result<Data<B, Q, T>, JsonError>, WebSocketError>;
+
+
+/// The default number of items a [`LatestBars`] adapter queues up.
+const LATEST_BARS_CAPACITY: usize = 1024;
+
+
+/// A lossy stream adapter only ever yielding the most recent minute
+/// bar for each symbol.
+///
+/// Whenever the wrapped stream is polled, items readily available are
+/// pulled from it and queued up, until the queue holds `capacity`
+/// items. Minute bars superseding a still queued minute bar for the
+/// same symbol replace said bar in the queue, meaning that the
+/// superseded bar is *discarded* and never yielded. All other items,
+/// including daily and updated bars, are passed through unaltered and
+/// in order. Once the queue is full, no further items are pulled until
+/// some have been yielded, leaving them to the wrapped stream's
+/// buffering.
+///
+/// This adapter intentionally trades completeness for freshness: it
+/// is meant for consumers that may fall behind, e.g., during times of
+/// high volatility, and that only care about the latest state. Do not
+/// use it if every bar needs to be seen.
+///
+/// The adapter can be combined with [`FilterSymbols`] and [`WithEnd`]:
+/// ```no_run
+/// # use apca::ApiInfo;
+/// # use apca::Client;
+/// # use apca::data::v2::stream::FilterSymbols;
+/// # use apca::data::v2::stream::IEX;
+/// # use apca::data::v2::stream::LatestBars;
+/// # use apca::data::v2::stream::RealtimeData;
+/// # use apca::data::v2::stream::StreamEvent;
+/// # use apca::data::v2::stream::WithEnd;
+/// # use futures::StreamExt as _;
+/// # tokio::runtime::Runtime::new().unwrap().block_on(async move {
+/// let api_info = ApiInfo::from_env().unwrap();
+/// let client = Client::new(api_info);
+/// let (stream, _subscription) = client
+///   .subscribe::<RealtimeData<IEX>>()
+///   .await
+///   .unwrap();
+/// let stream = FilterSymbols::new(stream, |symbol| symbol.starts_with('A'));
+/// let mut stream = WithEnd::new(LatestBars::new(stream));
+/// while let Some(event) = stream.next().await {
+///   match event {
+///     StreamEvent::Item(result) => {
+///       // Process the most recent data for symbols starting with 'A'...
+///     },
+///     StreamEvent::End(end) => println!("stream ended: {end:?}"),
+///   }
+/// }
+/// # })
+/// ```
+#[derive(Debug)]
+#[must_use = "streams do nothing unless polled"]
+pub struct LatestBars<S, B = Bar, Q = Quote, T = Trade> {
+  /// The wrapped stream.
+  inner: Fuse<S>,
+  /// Items retrieved from the wrapped stream but not yet yielded.
+  queue: VecDeque<StreamItem<B, Q, T>>,
+  /// The maximum number of items to queue up.
+  capacity: usize,
+  /// The positions of the queued minute bars, keyed by symbol.
+  ///
+  /// Positions count all items ever queued, i.e., they have to be
+  /// offset by `popped` to index into `queue`.
+  bars: HashMap<String, usize>,
+  /// The number of items popped off the queue so far.
+  popped: usize,
+}
+
+impl<S, B, Q, T> LatestBars<S, B, Q, T>
+where
+  S: FuturesStream<Item = StreamItem<B, Q, T>>,
+  B: HasSymbol,
+{
+  /// Create a new `LatestBars` object wrapping the provided stream.
+  #[inline]
+  pub fn new(stream: S) -> Self {
+    Self::with_capacity(stream, LATEST_BARS_CAPACITY)
+  }
+
+  /// Create a new `LatestBars` object wrapping the provided stream,
+  /// queuing up at most `capacity` items.
+  ///
+  /// A `capacity` of zero is treated as one.
+  #[inline]
+  pub fn with_capacity(stream: S, capacity: usize) -> Self {
+    Self {
+      inner: stream.fuse(),
+      queue: VecDeque::new(),
+      capacity: capacity.max(1),
+      bars: HashMap::new(),
+      popped: 0,
+    }
+  }
+
+  /// Queue up an item, replacing any queued bar it supersedes.
+  fn enqueue(&mut self, item: StreamItem<B, Q, T>) {
+    if let Ok(Ok(Data::Bar {
+      bar,
+      kind: BarKind::Minute,
+    })) = &item
+    {
+      if let Some(position) = self.bars.get(bar.symbol()) {
+        self.queue[*position - self.popped] = item;
+        return
+      }
+
+      let position = self.popped + self.queue.len();
+      let _position = self.bars.insert(bar.symbol().to_string(), position);
+    }
+    let () = self.queue.push_back(item);
+  }
+
+  /// Pop the oldest item off the queue.
+  fn dequeue(&mut self) -> Option<StreamItem<B, Q, T>> {
+    let item = self.queue.pop_front()?;
+    if let Ok(Ok(Data::Bar {
+      bar,
+      kind: BarKind::Minute,
+    })) = &item
+    {
+      let _position = self.bars.remove(bar.symbol());
+    }
+    self.popped += 1;
+    Some(item)
+  }
+}
+
+impl<S, B, Q, T> FuturesStream for LatestBars<S, B, Q, T>
+where
+  S: FuturesStream<Item = StreamItem<B, Q, T>> + Unpin,
+  B: HasSymbol + Unpin,
+  Q: Unpin,
+  T: Unpin,
+{
+  type Item = StreamItem<B, Q, T>;
+
+  fn poll_next(mut self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+    // Bound the number of items pulled, so that we neither starve
+    // other tasks nor queue up an unbounded number of items while the
+    // wrapped stream keeps having data ready.
+    let budget = self.capacity.saturating_sub(self.queue.len());
+    for _ in 0..budget {
+      match self.inner.poll_next_unpin(ctx) {
+        Poll::Ready(Some(item)) => self.enqueue(item),
+        Poll::Ready(None) | Poll::Pending => break,
+      }
+    }
+
+    match self.dequeue() {
+      Some(item) => Poll::Ready(Some(item)),
+      None if self.inner.is_terminated() => Poll::Ready(None),
+      None => Poll::Pending,
+    }
+  }
+}
+
+impl<S, B, Q, T> FusedStream for LatestBars<S, B, Q, T>
+where
+  S: FuturesStream<Item = StreamItem<B, Q, T>> + Unpin,
+  B: HasSymbol + Unpin,
+  Q: Unpin,
+  T: Unpin,
+{
+  #[inline]
+  fn is_terminated(&self) -> bool {
+    self.inner.is_terminated() && self.queue.is_empty()
+  }
+}
+
+
+/// A stream adapter only yielding market data for symbols matching a
+/// predicate.
+///
+/// The predicate is invoked with the symbol of every [`Data`] object
+/// received and data for which it returns `false` is discarded.
+/// Errors are always passed through. Filtering locally allows for
+/// changing the set of symbols of interest cheaply and without any
+/// communication with the server, at the cost of still receiving data
+/// for all subscribed symbols.
+///
+/// Please refer to [`LatestBars`] for an example of its usage.
+#[derive(Debug)]
+#[must_use = "streams do nothing unless polled"]
+pub struct FilterSymbols<S, F> {
+  /// The wrapped stream.
+  inner: S,
+  /// The predicate deciding which symbols to keep.
+  predicate: F,
+}
+
+impl<S, F, B, Q, T> FilterSymbols<S, F>
+where
+  S: FuturesStream<Item = StreamItem<B, Q, T>>,
+  F: FnMut(&str) -> bool,
+{
+  /// Create a new `FilterSymbols` object wrapping the provided stream.
+  #[inline]
+  pub fn new(stream: S, predicate: F) -> Self {
+    Self {
+      inner: stream,
+      predicate,
+    }
+  }
+}
+
+impl<S, F, B, Q, T> FuturesStream for FilterSymbols<S, F>
+where
+  S: FuturesStream<Item = StreamItem<B, Q, T>> + Unpin,
+  F: FnMut(&str) -> bool + Unpin,
+  B: HasSymbol,
+  Q: HasSymbol,
+  T: HasSymbol,
+{
+  type Item = StreamItem<B, Q, T>;
+
+  fn poll_next(mut self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+    let this = &mut *self;
+
+    loop {
+      match this.inner.poll_next_unpin(ctx) {
+        Poll::Ready(Some(Ok(Ok(data)))) if !(this.predicate)(data.symbol()) => continue,
+        poll => return poll,
+      }
+    }
+  }
+}
+
+impl<S, F, B, Q, T> FusedStream for FilterSymbols<S, F>
+where
+  S: FusedStream<Item = StreamItem<B, Q, T>> + Unpin,
+  F: FnMut(&str) -> bool + Unpin,
+  B: HasSymbol,
+  Q: HasSymbol,
+  T: HasSymbol,
+{
+  #[inline]
+  fn is_terminated(&self) -> bool {
+    self.inner.is_terminated()
+  }
+}
+
+
+/// The reason for a realtime data stream to have ended.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum StreamEnd {
+  /// The connection was closed without any error, e.g., because the
+  /// server shut it down orderly.
+  ///
+  /// The close frame sent by the server is included if available, which
+  /// requires the [`WithEnd`] adapter to have been created using
+  /// [`WithEnd::with_close_frame`].
+  Closed(Option<CloseFrame>),
+  /// The connection was terminated by an error.
+  ///
+  /// The error is the last websocket error reported by the stream.
+  Error(OwnedStreamError),
+}
+
+
+/// An event yielded by a [`WithEnd`] stream.
+#[derive(Debug)]
+pub enum StreamEvent<I> {
+  /// An item produced by the wrapped stream.
+  Item(I),
+  /// The wrapped stream has ended. This event is always the last one.
+  End(StreamEnd),
+}
+
+
+/// A stream adapter reporting how the wrapped realtime data stream
+/// ended.
+///
+/// Items of the wrapped stream are passed through as
+/// [`StreamEvent::Item`]. Once the wrapped stream is exhausted, a final
+/// [`StreamEvent::End`] is yielded, indicating whether the connection
+/// was closed cleanly or whether it was brought down by a websocket
+/// error. Note that a websocket error does not by itself end the
+/// stream; only when it is the last item before the wrapped stream is
+/// exhausted is the stream considered to have ended in error.
+///
+/// Please refer to [`LatestBars`] for an example of its usage.
+#[derive(Debug)]
+#[must_use = "streams do nothing unless polled"]
+pub struct WithEnd<S> {
+  /// The wrapped stream.
+  inner: Fuse<S>,
+  /// The websocket error reported by the last item, if any.
+  error: Option<OwnedStreamError>,
+  /// The state of the connection the wrapped stream belongs to, if
+  /// known.
+  connection: Option<Arc<ConnectionState>>,
+  /// Whether we already yielded the end event.
+  ended: bool,
+}
+
+impl<S, B, Q, T> WithEnd<S>
+where
+  S: FuturesStream<Item = StreamItem<B, Q, T>>,
+{
+  /// Create a new `WithEnd` object wrapping the provided stream.
+  #[inline]
+  pub fn new(stream: S) -> Self {
+    Self {
+      inner: stream.fuse(),
+      error: None,
+      connection: None,
+      ended: false,
+    }
+  }
+
+  /// Include the close frame sent by the server in the
+  /// [`StreamEnd::Closed`] event, with `subscription` being the
+  /// [`Subscription`] associated with the wrapped stream.
+  #[inline]
+  pub fn with_close_frame<Si, M>(mut self, subscription: &Subscription<Si, M>) -> Self
+  where
+    M: StreamMessage,
+  {
+    self.connection = Some(subscription.connection.clone());
+    self
+  }
+}
+
+impl<S, B, Q, T> FuturesStream for WithEnd<S>
+where
+  S: FuturesStream<Item = StreamItem<B, Q, T>> + Unpin,
+{
+  type Item = StreamEvent<StreamItem<B, Q, T>>;
+
+  fn poll_next(mut self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+    if self.ended {
+      return Poll::Ready(None)
+    }
+
+    match self.inner.poll_next_unpin(ctx) {
+      Poll::Ready(Some(item)) => {
+        self.error = item.as_ref().err().map(OwnedStreamError::from);
+        Poll::Ready(Some(StreamEvent::Item(item)))
+      },
+      Poll::Ready(None) => {
+        self.ended = true;
+        let end = match self.error.take() {
+          Some(error) => StreamEnd::Error(error),
+          None => StreamEnd::Closed(self.connection.as_ref().and_then(close_frame)),
+        };
+        Poll::Ready(Some(StreamEvent::End(end)))
+      },
+      Poll::Pending => Poll::Pending,
+    }
+  }
+}
+
+impl<S, B, Q, T> FusedStream for WithEnd<S>
+where
+  S: FuturesStream<Item = StreamItem<B, Q, T>> + Unpin,
+{
+  #[inline]
+  fn is_terminated(&self) -> bool {
+    self.ended
+  }
+}
+
+
+/// An item tagged with the generation of the connection it was
+/// received over.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Generational<I> {
+  /// The generation of the connection the item was received over, as
+  /// reported by [`Subscription::generation`].
+  pub generation: u64,
+  /// The actual item.
+  pub item: I,
+}
+
+
+/// A stream adapter tagging items with the generation of the connection
+/// they were received over.
+///
+/// After reconnecting, items tagged with a generation lower than that
+/// of the new connection's [`Subscription`] stem from a previous
+/// connection and can be discarded, e.g., when they were buffered
+/// before state was reset.
+#[derive(Debug)]
+#[must_use = "streams do nothing unless polled"]
+pub struct WithGeneration<S> {
+  /// The wrapped stream.
+  inner: S,
+  /// The generation to tag items with.
+  generation: u64,
+}
+
+impl<S> WithGeneration<S> {
+  /// Create a new `WithGeneration` object wrapping the provided stream,
+  /// tagging each item with the generation of the connection that
+  /// `subscription`, which is associated with `stream`, belongs to.
+  #[inline]
+  pub fn new<Si, M>(stream: S, subscription: &Subscription<Si, M>) -> Self
+  where
+    M: StreamMessage,
+  {
+    Self {
+      inner: stream,
+      generation: subscription.generation(),
+    }
+  }
+}
+
+impl<S> FuturesStream for WithGeneration<S>
+where
+  S: FuturesStream + Unpin,
+{
+  type Item = Generational<S::Item>;
+
+  fn poll_next(mut self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+    let generation = self.generation;
+    self
+      .inner
+      .poll_next_unpin(ctx)
+      .map(|item| item.map(|item| Generational { generation, item }))
+  }
+}
+
+impl<S> FusedStream for WithGeneration<S>
+where
+  S: FusedStream + Unpin,
+{
+  #[inline]
+  fn is_terminated(&self) -> bool {
+    self.inner.is_terminated()
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use std::str::FromStr;
+
+  use chrono::DateTime;
+
+  use futures::channel::mpsc::unbounded;
+  use futures::FutureExt as _;
+  use futures::SinkExt as _;
+  use futures::TryStreamExt as _;
+
+  use num_decimal::Num;
+
+  use serde::de::Error as _;
+
+  use serde_json::from_str as json_from_str;
+
+  use test_log::test;
+
+  use websocket_util::test::WebSocketStream;
+  use websocket_util::tungstenite::protocol::frame::coding::CloseCode;
+  use websocket_util::tungstenite::protocol::CloseFrame as WsCloseFrame;
+  use websocket_util::tungstenite::Message;
+
+  use crate::data::v2::stream::drive;
+  use crate::data::v2::stream::test::mock_subscription;
+  use crate::data::v2::stream::tests::respond;
+  use crate::data::v2::stream::tests::AUTH_REQ;
+  use crate::data::v2::stream::tests::AUTH_RESP;
+  use crate::data::v2::stream::tests::BAR_RESP;
+  use crate::data::v2::stream::tests::CONN_RESP;
+  use crate::data::v2::stream::DataMessage;
+  use crate::data::v2::stream::InternedSymbol;
+  use crate::data::v2::stream::RealtimeData;
+  use crate::data::v2::stream::IEX;
+  use crate::websocket::test::mock_stream;
+
+
+  /// Check that `LatestBars` discards superseded bars while passing
+  /// through everything else.
+  #[test(tokio::test)]
+  async fn latest_bars() {
+    let (stream, subscription, injector) = mock_subscription::<Bar, Quote, Trade, _>(|request| {
+      panic!("unexpected request: {request:?}")
+    });
+
+    let bar = |symbol: &str, close: i64| Bar {
+      symbol: symbol.to_string(),
+      open_price: Num::from(close),
+      high_price: Num::from(close),
+      low_price: Num::from(close),
+      close_price: Num::from(close),
+      volume: Num::from(1),
+      timestamp: DateTime::from_str("2021-02-22T19:15:00Z").unwrap(),
+    };
+    let trade = Trade {
+      symbol: "SPY".to_string(),
+      trade_id: 1,
+      trade_price: Num::from(42),
+      trade_size: Num::from(1),
+      timestamp: DateTime::from_str("2021-02-22T19:15:00Z").unwrap(),
+    };
+
+    let () = injector.push(DataMessage::Bar(bar("SPY", 1)));
+    let () = injector.push(DataMessage::Bar(bar("AAPL", 2)));
+    let () = injector.push(DataMessage::Trade(trade.clone()));
+    let () = injector.push(DataMessage::Bar(bar("SPY", 3)));
+    let () = drop(injector);
+    let () = drop(subscription);
+
+    let data = LatestBars::new(stream)
+      .map(|result| result.unwrap().unwrap())
+      .collect::<Vec<_>>()
+      .await;
+    assert_eq!(data.len(), 3);
+    assert!(matches!(&data[0], Data::Bar { bar: data, .. } if data == &bar("SPY", 3)));
+    assert!(matches!(&data[1], Data::Bar { bar: data, .. } if data == &bar("AAPL", 2)));
+    assert!(matches!(&data[2], Data::Trade(data) if data == &trade));
+  }
+
+  /// Check that `LatestBars` replaces the correct queued bar after
+  /// items have already been yielded.
+  #[test(tokio::test)]
+  async fn latest_bars_after_yield() {
+    let bar = |symbol: &str, close: i64| Bar {
+      symbol: symbol.to_string(),
+      open_price: Num::from(close),
+      high_price: Num::from(close),
+      low_price: Num::from(close),
+      close_price: Num::from(close),
+      volume: Num::from(1),
+      timestamp: DateTime::from_str("2021-02-22T19:15:00Z").unwrap(),
+    };
+    let minute = |bar| Data::<Bar, Quote, Trade>::Bar {
+      bar,
+      kind: BarKind::Minute,
+    };
+
+    let (sender, receiver) = unbounded();
+    let mut stream = LatestBars::new(receiver);
+
+    let () = sender
+      .unbounded_send(Ok(Ok(minute(bar("SPY", 1)))))
+      .unwrap();
+    let () = sender
+      .unbounded_send(Ok(Ok(minute(bar("AAPL", 2)))))
+      .unwrap();
+    let data = stream.next().await.unwrap().unwrap().unwrap();
+    assert!(matches!(&data, Data::Bar { bar: data, .. } if data == &bar("SPY", 1)));
+
+    let () = sender
+      .unbounded_send(Ok(Ok(minute(bar("AAPL", 3)))))
+      .unwrap();
+    let () = sender
+      .unbounded_send(Ok(Ok(minute(bar("SPY", 4)))))
+      .unwrap();
+    let () = drop(sender);
+
+    let data = stream
+      .map(|result| result.unwrap().unwrap())
+      .collect::<Vec<_>>()
+      .await;
+    assert_eq!(data.len(), 2);
+    assert!(matches!(&data[0], Data::Bar { bar: data, .. } if data == &bar("AAPL", 3)));
+    assert!(matches!(&data[1], Data::Bar { bar: data, .. } if data == &bar("SPY", 4)));
+  }
+
+  /// Check that `LatestBars` stops pulling items from the wrapped
+  /// stream once its queue is full.
+  #[test(tokio::test)]
+  async fn latest_bars_capacity() {
+    let bar = |symbol: &str, close: i64| Bar {
+      symbol: InternedSymbol::new(symbol),
+      open_price: Num::from(close),
+      high_price: Num::from(close),
+      low_price: Num::from(close),
+      close_price: Num::from(close),
+      volume: Num::from(1),
+      timestamp: DateTime::from_str("2021-02-22T19:15:00Z").unwrap(),
+    };
+    let minute = |bar| Data::<Bar<InternedSymbol>, Quote, Trade>::Bar {
+      bar,
+      kind: BarKind::Minute,
+    };
+
+    let (sender, receiver) = unbounded();
+    let () = sender
+      .unbounded_send(Ok(Ok(minute(bar("SPY", 1)))))
+      .unwrap();
+    let () = sender
+      .unbounded_send(Ok(Ok(minute(bar("AAPL", 2)))))
+      .unwrap();
+    let () = sender
+      .unbounded_send(Ok(Ok(minute(bar("SPY", 3)))))
+      .unwrap();
+    let () = drop(sender);
+
+    // With room for only two items, the first bar for SPY is yielded
+    // before the one superseding it is ever pulled.
+    let data = LatestBars::with_capacity(receiver, 2)
+      .map(|result| result.unwrap().unwrap())
+      .collect::<Vec<_>>()
+      .await;
+    assert_eq!(data.len(), 3);
+    assert!(matches!(&data[0], Data::Bar { bar: data, .. } if data == &bar("SPY", 1)));
+    assert!(matches!(&data[1], Data::Bar { bar: data, .. } if data == &bar("AAPL", 2)));
+    assert!(matches!(&data[2], Data::Bar { bar: data, .. } if data == &bar("SPY", 3)));
+  }
+
+  /// Check that `FilterSymbols` only yields data for symbols matching
+  /// the provided predicate.
+  #[test(tokio::test)]
+  async fn filter_symbols() {
+    let (stream, mut subscription, injector) = mock_subscription(respond());
+    let messages = json_from_str::<Vec<DataMessage>>(BAR_RESP).unwrap();
+
+    let mut stream = FilterSymbols::new(stream, |symbol| symbol == "VOO");
+    let subscribe = subscription.subscribe_all_bars().boxed_local();
+    let _data = drive(subscribe, &mut stream)
+      .await
+      .unwrap()
+      .unwrap()
+      .unwrap();
+
+    let () = messages
+      .into_iter()
+      .for_each(|message| injector.push(message));
+    let () = drop(injector);
+    let () = drop(subscription);
+
+    let data = stream.try_collect::<Vec<_>>().await.unwrap();
+    assert_eq!(data.len(), 1);
+    assert_eq!(data[0].as_ref().unwrap().symbol(), "VOO");
+  }
+
+  /// Check that `WithEnd` reports whether a stream ended cleanly or
+  /// due to an error.
+  #[test(tokio::test)]
+  async fn stream_end() {
+    let items = vec![Ok(Err(JsonError::custom("invalid value")))];
+    let events = WithEnd::new(futures::stream::iter(items))
+      .collect::<Vec<StreamEvent<StreamItem<Bar, Quote, Trade>>>>()
+      .await;
+    assert_eq!(events.len(), 2);
+    assert!(matches!(events[0], StreamEvent::Item(Ok(Err(..)))));
+    assert!(matches!(
+      events[1],
+      StreamEvent::End(StreamEnd::Closed(None))
+    ));
+
+    let items = vec![
+      Ok(Err(JsonError::custom("invalid value"))),
+      Err(WebSocketError::ConnectionClosed),
+    ];
+    let events = WithEnd::new(futures::stream::iter(items))
+      .collect::<Vec<StreamEvent<StreamItem<Bar, Quote, Trade>>>>()
+      .await;
+    assert_eq!(events.len(), 3);
+    match &events[2] {
+      StreamEvent::End(StreamEnd::Error(error)) => assert_eq!(
+        error,
+        &OwnedStreamError::from(&WebSocketError::ConnectionClosed)
+      ),
+      event => panic!("unexpected event: {event:?}"),
+    }
+  }
+
+  /// Check that a `WithEnd` stream reports a clean close when the
+  /// server closes the connection.
+  #[test(tokio::test)]
+  async fn stream_end_closed() {
+    async fn test(mut stream: WebSocketStream) -> Result<(), WebSocketError> {
+      stream.send(Message::Text(CONN_RESP.to_string())).await?;
+      // Authentication.
+      assert_eq!(
+        stream.next().await.unwrap()?,
+        Message::Text(AUTH_REQ.to_string()),
+      );
+      stream.send(Message::Text(AUTH_RESP.to_string())).await?;
+      stream.send(Message::Close(None)).await?;
+      Ok(())
+    }
+
+    let (stream, _subscription) = mock_stream::<RealtimeData<IEX>, _, _>(test).await.unwrap();
+    let events = WithEnd::new(stream).collect::<Vec<_>>().await;
+    assert!(matches!(
+      events.last(),
+      Some(StreamEvent::End(StreamEnd::Closed(None)))
+    ));
+  }
+
+  /// Check that the close frame sent by the server is surfaced once
+  /// the stream ended.
+  #[test(tokio::test)]
+  async fn stream_end_close_frame() {
+    async fn test(mut stream: WebSocketStream) -> Result<(), WebSocketError> {
+      stream.send(Message::Text(CONN_RESP.to_string())).await?;
+      // Authentication.
+      assert_eq!(
+        stream.next().await.unwrap()?,
+        Message::Text(AUTH_REQ.to_string()),
+      );
+      stream.send(Message::Text(AUTH_RESP.to_string())).await?;
+      let frame = WsCloseFrame {
+        code: CloseCode::Policy,
+        reason: "invalid credentials".into(),
+      };
+      stream.send(Message::Close(Some(frame))).await?;
+      Ok(())
+    }
+
+    let (stream, subscription) = mock_stream::<RealtimeData<IEX>, _, _>(test).await.unwrap();
+    let events = WithEnd::new(stream)
+      .with_close_frame(&subscription)
+      .collect::<Vec<_>>()
+      .await;
+    let expected = CloseFrame {
+      code: 1008,
+      reason: "invalid credentials".to_string(),
+    };
+    match events.last() {
+      Some(StreamEvent::End(StreamEnd::Closed(Some(frame)))) => assert_eq!(frame, &expected),
+      event => panic!("unexpected event: {event:?}"),
+    }
+    assert_eq!(subscription.close_frame(), Some(expected));
+  }
+
+  /// Check that `WithGeneration` tags items with the generation of
+  /// the connection they were received over.
+  #[test(tokio::test)]
+  async fn with_generation() {
+    let (stream, mut subscription, injector) =
+      mock_subscription::<Bar, Quote, Trade, _>(|request| {
+        panic!("unexpected request: {request:?}")
+      });
+    assert_eq!(subscription.generation(), 0);
+    // Pretend the connection replaced another one.
+    subscription.generation = 3;
+
+    let messages = json_from_str::<Vec<DataMessage>>(BAR_RESP).unwrap();
+    let () = messages
+      .into_iter()
+      .for_each(|message| injector.push(message));
+    let () = drop(injector);
+
+    let stream = WithGeneration::new(stream, &subscription);
+    let () = drop(subscription);
+    let items = stream.collect::<Vec<_>>().await;
+    assert_eq!(items.len(), 2);
+    assert!(items.iter().all(|item| item.generation == 3));
+    assert!(items
+      .iter()
+      .all(|item| matches!(item.item, Ok(Ok(Data::Bar { .. })))));
+  }
+}
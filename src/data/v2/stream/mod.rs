@@ -0,0 +1,8678 @@
+// Copyright (C) 2021-2023 The apca Developers
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+mod adapters;
+mod pool;
+mod reconnect;
+
+pub use adapters::FilterSymbols;
+pub use adapters::Generational;
+pub use adapters::LatestBars;
+pub use adapters::StreamEnd;
+pub use adapters::StreamEvent;
+pub use adapters::WithEnd;
+pub use adapters::WithGeneration;
+pub use pool::RealtimeDataPool;
+pub use pool::SubscriptionPool;
+pub use reconnect::AutoEvent;
+pub use reconnect::AutoSubscription;
+pub use reconnect::ReconnectPolicy;
+
+use std::borrow::Borrow as _;
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::fmt::Debug;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::fmt::Result as FmtResult;
+use std::io::BufRead as _;
+use std::io::BufReader;
+use std::io::Read;
+use std::io::Result as IoResult;
+use std::marker::PhantomData;
+use std::ops::Deref;
+use std::pin::Pin;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::AtomicU8;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering as AtomicOrdering;
+use std::sync::Arc;
+use std::sync::Mutex as StdMutex;
+use std::time::Duration;
+use std::time::Instant;
+
+use async_trait::async_trait;
+
+use chrono::DateTime;
+use chrono::Utc;
+
+use futures::channel::mpsc::unbounded;
+use futures::channel::mpsc::UnboundedReceiver;
+use futures::channel::mpsc::UnboundedSender;
+use futures::future::poll_fn;
+use futures::future::ready;
+use futures::future::select;
+use futures::future::Either;
+use futures::io::AsyncBufRead;
+use futures::io::AsyncBufReadExt as _;
+use futures::lock::Mutex;
+use futures::lock::MutexGuard;
+use futures::stream::Fuse;
+use futures::stream::FusedStream;
+use futures::stream::Map;
+use futures::stream::SplitSink;
+use futures::stream::SplitStream;
+use futures::task::AtomicWaker;
+use futures::task::Context;
+use futures::task::Poll;
+use futures::Future;
+use futures::FutureExt as _;
+use futures::Sink;
+use futures::SinkExt as _;
+use futures::Stream as FuturesStream;
+use futures::StreamExt as _;
+
+use num_decimal::Num;
+
+use serde::de::value::MapAccessDeserializer;
+use serde::de::DeserializeOwned;
+use serde::de::Deserializer;
+use serde::de::Error as _;
+use serde::de::MapAccess;
+use serde::de::Visitor;
+use serde::ser::SerializeSeq as _;
+use serde::ser::Serializer;
+use serde::Deserialize;
+use serde::Serialize;
+use serde_json::from_slice as json_from_slice;
+use serde_json::from_str as json_from_str;
+use serde_json::to_string as to_json;
+use serde_json::Deserializer as JsonDeserializer;
+use serde_json::Error as JsonError;
+use serde_json::Map as JsonMap;
+use serde_json::Value as JsonValue;
+
+use thiserror::Error as ThisError;
+
+use tokio::io::AsyncRead;
+use tokio::io::AsyncWrite;
+use tokio::net::TcpStream;
+use tokio::time::timeout as tokio_timeout;
+use tokio::time::timeout_at;
+use tokio::time::Instant as TokioInstant;
+
+use tracing::debug;
+use tracing::trace;
+
+use tungstenite::MaybeTlsStream;
+use tungstenite::WebSocketStream;
+
+use url::Url;
+
+use websocket_util::subscribe;
+use websocket_util::subscribe::MessageStream;
+use websocket_util::tungstenite::protocol::CloseFrame as WsCloseFrame;
+use websocket_util::tungstenite::Error as WebSocketError;
+use websocket_util::wrap;
+use websocket_util::wrap::Wrapper;
+
+use super::unfold::Unfold;
+
+use crate::subscribable::Subscribable;
+use crate::util::datetime_from_str_or_nanos;
+use crate::websocket::connect;
+use crate::websocket::connect_with;
+use crate::websocket::ConnectionState;
+use crate::websocket::MessageResult;
+use crate::websocket::Monitor;
+use crate::websocket::PING_INTERVAL;
+use crate::ApiInfo;
+use crate::Error;
+use crate::Str;
+
+
+type UserMessage<M> = <ParsedMessage<M> as subscribe::Message>::UserMessage;
+
+
+/// A [`subscribe::Message`] standing in for any [`ParsedMessage`]
+/// containing market data of type `D`.
+///
+/// The user message type of a [`ParsedMessage`] only depends on the
+/// type of market data contained in it. This type allows for naming it
+/// without knowing the actual message type, which can't be inferred
+/// from a stream's items. It is never instantiated.
+#[derive(Debug)]
+enum AnyMessage<D> {
+  #[allow(dead_code)]
+  Never(Infallible, PhantomData<D>),
+}
+
+impl<D> subscribe::Message for AnyMessage<D> {
+  type UserMessage = Result<Result<D, JsonError>, WebSocketError>;
+  type ControlMessage = ControlMessage;
+
+  fn classify(self) -> subscribe::Classification<Self::UserMessage, Self::ControlMessage> {
+    match self {
+      Self::Never(never, _) => match never {},
+    }
+  }
+
+  #[inline]
+  fn is_error(user_message: &Self::UserMessage) -> bool {
+    is_error(user_message)
+  }
+}
+
+
+/// Check whether a user message, as yielded by the realtime data
+/// stream, constitutes an error.
+fn is_error<D>(user_message: &Result<Result<D, JsonError>, WebSocketError>) -> bool {
+  // Both outer `WebSocketError` and inner `JsonError` errors
+  // constitute errors in our sense. Note, however, that an API error
+  // does not. It's just a regular control message from our
+  // perspective.
+  user_message
+    .as_ref()
+    .map(|result| result.is_err())
+    .unwrap_or(true)
+}
+
+
+/// Helper function to drive a [`Subscription`] related future to
+/// completion. The function makes sure to poll the provided stream,
+/// which is assumed to be associated with the `Subscription` that the
+/// future belongs to, so that control messages can be received.
+///
+/// Market data received in the meantime is discarded. Errors reported
+/// by the stream short circuit and fail the operation immediately.
+#[inline]
+pub async fn drive<F, S, D>(
+  future: F,
+  stream: &mut S,
+) -> Result<F::Output, Result<Result<D, JsonError>, WebSocketError>>
+where
+  F: Future + Unpin,
+  S: FusedStream<Item = Result<Result<D, JsonError>, WebSocketError>> + Unpin,
+{
+  subscribe::drive::<AnyMessage<D>, _, _>(future, stream).await
+}
+
+
+/// Convert an erroneous message, as reported by [`drive`], into an
+/// [`Error`].
+fn stream_error<D>(message: Result<Result<D, JsonError>, WebSocketError>) -> Error
+where
+  D: Debug,
+{
+  message
+    .map(|result| Error::Json(result.unwrap_err()))
+    .map_err(Error::WebSocket)
+    .unwrap_or_else(|err| err)
+}
+
+
+/// Discard messages of unknown type.
+///
+/// Such messages could otherwise be mistaken for the response to a
+/// pending request.
+fn retain_known<M>(mut messages: Vec<M>) -> Vec<M>
+where
+  M: StreamMessage,
+{
+  let () = messages.retain(|message| match message.kind() {
+    MessageKind::Unknown => {
+      match message.to_control() {
+        Some(ControlMessage::Unknown(value)) => debug!(
+          message = "discarding message of unknown type",
+          value = display(&value)
+        ),
+        _ => debug!("discarding message of unknown type"),
+      }
+      false
+    },
+    _ => true,
+  });
+  messages
+}
+
+
+/// Await the provided future, giving up once `timeout` has elapsed.
+///
+/// The function returns `None` if the timeout elapsed before the
+/// future resolved.
+async fn with_timeout<F>(timeout: Option<Duration>, future: F) -> Option<F::Output>
+where
+  F: Future,
+{
+  match timeout {
+    Some(timeout) => tokio_timeout(timeout, future).await.ok(),
+    None => Some(future.await),
+  }
+}
+
+
+pub(crate) mod private {
+  pub trait Sealed {}
+}
+
+
+#[doc(hidden)]
+#[derive(Clone, Debug)]
+pub enum SourceVariant {
+  /// The source provided is a path component to be appended to an
+  /// already present base URL.
+  PathComponent(&'static str),
+  /// The source provided is the complete path to use with the already
+  /// present base URL.
+  Path(&'static str),
+  /// The source provided is a complete URL.
+  Url(String),
+}
+
+
+/// A trait representing the source from which to stream real time data.
+// TODO: Once we can use enumerations as const generic parameters we
+//       should probably switch over to repurposing `data::v2::Feed`
+//       here instead.
+pub trait Source: private::Sealed {
+  /// Return the source.
+  #[doc(hidden)]
+  fn source() -> SourceVariant;
+}
+
+
+/// Use the Investors Exchange (IEX) as the data source.
+///
+/// This source is available unconditionally, i.e., with the free and
+/// unlimited plans.
+#[derive(Clone, Copy, Debug)]
+pub enum IEX {}
+
+impl Source for IEX {
+  #[inline]
+  fn source() -> SourceVariant {
+    SourceVariant::PathComponent("iex")
+  }
+}
+
+impl private::Sealed for IEX {}
+
+
+/// Use CTA (administered by NYSE) and UTP (administered by Nasdaq) SIPs
+/// as the data source.
+///
+/// This source is only usable with the unlimited market data plan.
+#[derive(Clone, Copy, Debug)]
+pub enum SIP {}
+
+impl Source for SIP {
+  #[inline]
+  fn source() -> SourceVariant {
+    SourceVariant::PathComponent("sip")
+  }
+}
+
+impl private::Sealed for SIP {}
+
+
+/// A realtime data source that uses a custom URL.
+///
+/// This type provides a way to stream realtime data from a custom URL.
+/// The endpoint at said URL has to follow the `v2` handshake and
+/// message protocol. Provided that is the case, usage could be as
+/// follows:
+/// ```no_run
+/// # use apca::ApiInfo;
+/// # use apca::Client;
+/// # use apca::data::v2::stream::CustomUrl;
+/// # use apca::data::v2::stream::RealtimeData;
+/// // The v1beta3 crypto API happens to be using the same handshake as
+/// // the v2 stock APIs.
+/// #[derive(Default)]
+/// struct Crypto;
+///
+/// impl ToString for Crypto {
+///   fn to_string(&self) -> String {
+///     "wss://stream.data.alpaca.markets/v1beta3/crypto/us".into()
+///   }
+/// }
+///
+/// let api_info = ApiInfo::from_env().unwrap();
+/// let client = Client::new(api_info);
+/// # tokio::runtime::Runtime::new().unwrap().block_on(async move {
+/// let (mut stream, mut subscription) = client
+///   .subscribe::<RealtimeData<CustomUrl<Crypto>>>()
+///   .await
+///   .unwrap();
+/// # })
+///
+/// // Use `subscription` to subscribe to quotes, trades, or bars, then
+/// // handle `stream` as usual.
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct CustomUrl<URL> {
+  _phantom: PhantomData<URL>,
+}
+
+impl<URL> Source for CustomUrl<URL>
+where
+  URL: Default + ToString,
+{
+  #[inline]
+  fn source() -> SourceVariant {
+    let url = URL::default();
+    SourceVariant::Url(url.to_string())
+  }
+}
+
+impl<URL> private::Sealed for CustomUrl<URL> {}
+
+
+/// A symbol.
+pub type Symbol = Str;
+
+
+/// An error indicating that a string does not represent a valid
+/// [`Symbol`].
+#[derive(Clone, Debug, Eq, PartialEq, ThisError)]
+pub enum SymbolError {
+  /// The symbol is empty.
+  #[error("symbol is empty")]
+  Empty,
+  /// The symbol contains a character that is not valid in a symbol.
+  #[error("symbol {symbol:?} contains invalid character {character:?}")]
+  InvalidCharacter {
+    /// The symbol in question.
+    symbol: String,
+    /// The first invalid character found.
+    character: char,
+  },
+}
+
+
+/// Create a [`Symbol`], checking it for validity.
+///
+/// A valid symbol is non-empty and consists only of ASCII letters and
+/// digits as well as the separators `.`, `/`, and `-`, as used in
+/// symbols such as `BRK.B` or `BTC/USD`.
+pub fn try_symbol<S>(symbol: S) -> Result<Symbol, SymbolError>
+where
+  S: Into<Symbol>,
+{
+  let symbol = symbol.into();
+  if symbol.is_empty() {
+    return Err(SymbolError::Empty)
+  }
+
+  let invalid = symbol
+    .chars()
+    .find(|c| !(c.is_ascii_alphanumeric() || matches!(c, '.' | '/' | '-')));
+
+  if let Some(character) = invalid {
+    Err(SymbolError::InvalidCharacter {
+      symbol: symbol.into_owned(),
+      character,
+    })
+  } else {
+    Ok(symbol)
+  }
+}
+
+
+/// Check whether a slice of symbols is normalized.
+///
+/// Such a slice is normalized if it is sorted lexically and all
+/// duplicates are removed.
+fn is_normalized(symbols: &[Symbol]) -> bool {
+  // `Symbol` is totally ordered, so a list is normalized if each
+  // element is strictly smaller than its successor. Requiring strict
+  // ordering also catches duplicates.
+  symbols.windows(2).all(|pair| pair[0] < pair[1])
+}
+
+
+/// Normalize an owned list of symbols in place.
+///
+/// The provided buffer is reused, i.e., no allocation takes place.
+fn normalize_vec(mut symbols: Vec<Symbol>) -> Vec<Symbol> {
+  if !is_normalized(&symbols) {
+    // An unstable sort does not allocate and, because we remove
+    // duplicates subsequently, stability is of no concern to us.
+    symbols.sort_unstable();
+    symbols.dedup();
+    debug_assert!(is_normalized(&symbols));
+  }
+  symbols
+}
+
+
+/// Normalize a list of symbols.
+fn normalize(symbols: Cow<'static, [Symbol]>) -> Cow<'static, [Symbol]> {
+  if !is_normalized(&symbols) {
+    Cow::Owned(normalize_vec(symbols.into_owned()))
+  } else {
+    symbols
+  }
+}
+
+
+thread_local! {
+  /// The interner of the connection whose messages are currently being
+  /// deserialized on this thread, if any.
+  static CURRENT_INTERNER: RefCell<Option<Interner>> = const { RefCell::new(None) };
+}
+
+
+/// The set of symbols shared by all [`InternedSymbol`] objects
+/// received over a single connection.
+///
+/// The interner is owned by the connection and seeded with the symbols
+/// subscribed to. Symbols are dropped from it once they are no longer
+/// subscribed to, unless a wildcard subscription is active.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Interner(Arc<StdMutex<HashSet<Arc<str>>>>);
+
+impl Interner {
+  /// Retrieve the shared allocation for `symbol`, creating it if
+  /// necessary.
+  fn intern(&self, symbol: &str) -> Arc<str> {
+    let mut symbols = self.0.lock().unwrap_or_else(|err| err.into_inner());
+    if let Some(symbol) = symbols.get(symbol) {
+      symbol.clone()
+    } else {
+      let symbol = Arc::<str>::from(symbol);
+      let _inserted = symbols.insert(symbol.clone());
+      symbol
+    }
+  }
+
+  /// Seed the interner with the symbols subscribed to as per `data`,
+  /// dropping all symbols no longer subscribed to.
+  fn update<C>(&self, data: &C)
+  where
+    C: Channels,
+  {
+    let fields = data.fields();
+    let mut symbols = self.0.lock().unwrap_or_else(|err| err.into_inner());
+    if !fields.iter().any(|symbols| matches!(symbols, Symbols::All)) {
+      // The lists are normalized, i.e., sorted, so that `contains` can
+      // use a binary search.
+      let () = symbols.retain(|symbol| fields.iter().any(|symbols| symbols.contains(&**symbol)));
+    }
+
+    for field in fields {
+      if let Symbols::List(list) = field {
+        for symbol in list.iter() {
+          if !symbols.contains(&**symbol) {
+            let _inserted = symbols.insert(Arc::from(&**symbol));
+          }
+        }
+      }
+    }
+  }
+
+  /// Run `f` with `self` being used for interning all
+  /// [`InternedSymbol`] objects deserialized on the current thread.
+  fn enter<R>(&self, f: impl FnOnce() -> R) -> R {
+    /// A guard restoring the previously current interner when dropped.
+    struct Guard(Option<Interner>);
+
+    impl Drop for Guard {
+      fn drop(&mut self) {
+        let previous = self.0.take();
+        let () = CURRENT_INTERNER.with(|current| *current.borrow_mut() = previous);
+      }
+    }
+
+    let previous = CURRENT_INTERNER.with(|current| current.replace(Some(self.clone())));
+    let _guard = Guard(previous);
+    f()
+  }
+}
+
+
+/// A symbol that shares its allocation with all other equal symbols
+/// received over the same connection.
+///
+/// This type can be used in place of `String` as the symbol type of
+/// [`Bar`] and [`Trade`] objects, e.g., by using
+/// `RealtimeData<IEX, DataMessage<Bar<InternedSymbol>, Quote, Trade<InternedSymbol>>>`.
+/// For high volume streams covering a limited number of distinct
+/// symbols, doing so avoids keeping a separate copy of the symbol
+/// around for every single message and makes cloning cheap.
+///
+/// Interned symbols are shared for as long as they are subscribed to
+/// and at most for the lifetime of the connection. Symbols deserialized
+/// outside of a realtime data stream are not interned.
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct InternedSymbol(Arc<str>);
+
+impl InternedSymbol {
+  /// Create an `InternedSymbol` with its own allocation.
+  #[inline]
+  pub fn new(symbol: &str) -> Self {
+    Self(Arc::from(symbol))
+  }
+
+  /// Check whether `self` and `other` share the same allocation.
+  #[inline]
+  pub fn ptr_eq(&self, other: &Self) -> bool {
+    Arc::ptr_eq(&self.0, &other.0)
+  }
+}
+
+impl Deref for InternedSymbol {
+  type Target = str;
+
+  #[inline]
+  fn deref(&self) -> &Self::Target {
+    &self.0
+  }
+}
+
+impl AsRef<str> for InternedSymbol {
+  #[inline]
+  fn as_ref(&self) -> &str {
+    &self.0
+  }
+}
+
+impl Display for InternedSymbol {
+  #[inline]
+  fn fmt(&self, fmt: &mut Formatter<'_>) -> FmtResult {
+    fmt.write_str(&self.0)
+  }
+}
+
+impl PartialEq<str> for InternedSymbol {
+  #[inline]
+  fn eq(&self, other: &str) -> bool {
+    &*self.0 == other
+  }
+}
+
+impl PartialEq<&str> for InternedSymbol {
+  #[inline]
+  fn eq(&self, other: &&str) -> bool {
+    &*self.0 == *other
+  }
+}
+
+impl<'de> Deserialize<'de> for InternedSymbol {
+  fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+  where
+    D: Deserializer<'de>,
+  {
+    struct InternVisitor;
+
+    impl<'de> Visitor<'de> for InternVisitor {
+      type Value = InternedSymbol;
+
+      fn expecting(&self, fmt: &mut Formatter<'_>) -> FmtResult {
+        fmt.write_str("a symbol string")
+      }
+
+      fn visit_str<E>(self, symbol: &str) -> Result<Self::Value, E>
+      where
+        E: serde::de::Error,
+      {
+        let symbol = CURRENT_INTERNER.with(|current| match &*current.borrow() {
+          Some(interner) => InternedSymbol(interner.intern(symbol)),
+          None => InternedSymbol::new(symbol),
+        });
+        Ok(symbol)
+      }
+    }
+
+    deserializer.deserialize_str(InternVisitor)
+  }
+}
+
+impl Serialize for InternedSymbol {
+  #[inline]
+  fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+  where
+    S: Serializer,
+  {
+    serializer.serialize_str(&self.0)
+  }
+}
+
+
+/// A trait for market data objects pertaining to a symbol.
+///
+/// This trait is implemented for the bar, quote, and trade types
+/// provided by this module and allows for accessing the symbol of
+/// [`Data`] objects irrespective of the concrete types in use.
+pub trait HasSymbol {
+  /// Retrieve the symbol the object pertains to.
+  fn symbol(&self) -> &str;
+}
+
+
+/// Aggregate data for an equity.
+///
+/// Prices and the volume are accepted as JSON numbers as well as
+/// strings containing numbers.
+///
+/// The symbol is represented as a `String` by default. See
+/// [`InternedSymbol`] for an alternative sharing allocations between
+/// bars.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Bar<Y = String> {
+  /// The bar's symbol.
+  #[serde(rename = "S")]
+  pub symbol: Y,
+  /// The bar's open price.
+  #[serde(rename = "o")]
+  pub open_price: Num,
+  /// The bar's high price.
+  #[serde(rename = "h")]
+  pub high_price: Num,
+  /// The bar's low price.
+  #[serde(rename = "l")]
+  pub low_price: Num,
+  /// The bar's close price.
+  #[serde(rename = "c")]
+  pub close_price: Num,
+  /// The bar's volume.
+  #[serde(rename = "v")]
+  pub volume: Num,
+  /// The bar's time stamp.
+  ///
+  /// Time stamps are parsed with the full precision provided by the
+  /// server, which may be nanoseconds. Besides RFC 3339 formatted
+  /// strings, integers denoting nanoseconds since the Unix epoch are
+  /// accepted.
+  #[serde(rename = "t", deserialize_with = "datetime_from_str_or_nanos")]
+  pub timestamp: DateTime<Utc>,
+}
+
+
+/// Convert a [`Num`] into an `f64`, yielding NaN if the value is not
+/// representable.
+#[inline]
+fn num_to_f64(num: &Num) -> f64 {
+  num.to_f64().unwrap_or(f64::NAN)
+}
+
+
+/// Lossy `f64` accessors for the bar's prices and volume.
+///
+/// These methods are meant for computations that operate on floating
+/// point values, such as indicators. The conversion is lossy: an `f64`
+/// cannot represent every decimal value exactly, so results may differ
+/// from the precise [`Num`] fields in the least significant digits.
+/// Values that cannot be represented as an `f64` at all are reported
+/// as NaN.
+impl<Y> Bar<Y> {
+  /// Retrieve the bar's open price as an `f64`.
+  #[inline]
+  pub fn open_f64(&self) -> f64 {
+    num_to_f64(&self.open_price)
+  }
+
+  /// Retrieve the bar's high price as an `f64`.
+  #[inline]
+  pub fn high_f64(&self) -> f64 {
+    num_to_f64(&self.high_price)
+  }
+
+  /// Retrieve the bar's low price as an `f64`.
+  #[inline]
+  pub fn low_f64(&self) -> f64 {
+    num_to_f64(&self.low_price)
+  }
+
+  /// Retrieve the bar's close price as an `f64`.
+  #[inline]
+  pub fn close_f64(&self) -> f64 {
+    num_to_f64(&self.close_price)
+  }
+
+  /// Retrieve the bar's volume as an `f64`.
+  #[inline]
+  pub fn volume_f64(&self) -> f64 {
+    num_to_f64(&self.volume)
+  }
+}
+
+
+impl<Y> HasSymbol for Bar<Y>
+where
+  Y: AsRef<str>,
+{
+  #[inline]
+  fn symbol(&self) -> &str {
+    self.symbol.as_ref()
+  }
+}
+
+
+/// A quote for an equity.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Quote {
+  /// The quote's symbol.
+  #[serde(rename = "S")]
+  pub symbol: String,
+  /// The bid's price.
+  #[serde(rename = "bp")]
+  pub bid_price: Num,
+  /// The bid's size.
+  #[serde(rename = "bs")]
+  pub bid_size: Num,
+  /// The code of the exchange the bid originated from, e.g., `P` for
+  /// NYSE Arca.
+  ///
+  /// Note that these single letter codes do not map onto the
+  /// [`Exchange`][crate::api::v2::asset::Exchange] type used for
+  /// assets and so are reported verbatim.
+  #[serde(rename = "bx", default)]
+  pub bid_exchange: String,
+  /// The ask's price.
+  #[serde(rename = "ap")]
+  pub ask_price: Num,
+  /// The ask's size.
+  #[serde(rename = "as")]
+  pub ask_size: Num,
+  /// The code of the exchange the ask originated from.
+  #[serde(rename = "ax", default)]
+  pub ask_exchange: String,
+  /// The quote's conditions.
+  #[serde(rename = "c", default)]
+  pub conditions: Vec<String>,
+  /// The tape the quote was reported on.
+  #[serde(rename = "z", default)]
+  pub tape: String,
+  /// The quote's time stamp.
+  ///
+  /// Time stamps are parsed with the full precision provided by the
+  /// server, which may be nanoseconds. Besides RFC 3339 formatted
+  /// strings, integers denoting nanoseconds since the Unix epoch are
+  /// accepted.
+  #[serde(rename = "t", deserialize_with = "datetime_from_str_or_nanos")]
+  pub timestamp: DateTime<Utc>,
+}
+
+impl HasSymbol for Quote {
+  #[inline]
+  fn symbol(&self) -> &str {
+    &self.symbol
+  }
+}
+
+
+/// A trade for an equity.
+///
+/// The symbol is represented as a `String` by default. See
+/// [`InternedSymbol`] for an alternative sharing allocations between
+/// trades.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Trade<Y = String> {
+  /// The trade's symbol.
+  #[serde(rename = "S")]
+  pub symbol: Y,
+  /// The trade's ID.
+  #[serde(rename = "i")]
+  pub trade_id: u64,
+  /// The trade's price.
+  #[serde(rename = "p")]
+  pub trade_price: Num,
+  /// The trade's size.
+  #[serde(rename = "s")]
+  pub trade_size: Num,
+  /// The trade's time stamp.
+  ///
+  /// Time stamps are parsed with the full precision provided by the
+  /// server, which may be nanoseconds. Besides RFC 3339 formatted
+  /// strings, integers denoting nanoseconds since the Unix epoch are
+  /// accepted.
+  #[serde(rename = "t", deserialize_with = "datetime_from_str_or_nanos")]
+  pub timestamp: DateTime<Utc>,
+}
+
+impl<Y> HasSymbol for Trade<Y>
+where
+  Y: AsRef<str>,
+{
+  #[inline]
+  fn symbol(&self) -> &str {
+    self.symbol.as_ref()
+  }
+}
+
+
+/// The limit up/limit down (LULD) price bands for an equity.
+///
+/// Orders priced outside of these bands cannot be executed while they
+/// are in effect.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Luld {
+  /// The symbol the bands apply to.
+  #[serde(rename = "S")]
+  pub symbol: String,
+  /// The upper price band.
+  #[serde(rename = "u")]
+  pub limit_up_price: Num,
+  /// The lower price band.
+  #[serde(rename = "d")]
+  pub limit_down_price: Num,
+  /// The indicator describing the state of the bands.
+  #[serde(rename = "i")]
+  pub indicator: String,
+  /// The tape the bands were reported on.
+  #[serde(rename = "z", default)]
+  pub tape: String,
+  /// The time stamp of the bands.
+  #[serde(rename = "t", deserialize_with = "datetime_from_str_or_nanos")]
+  pub timestamp: DateTime<Utc>,
+}
+
+
+/// A wrapper around a market data object that retains the raw JSON it
+/// was decoded from.
+///
+/// This type is meant for diagnosing changes to the wire format: it
+/// can be used in place of the bar (`B`), quote (`Q`), and trade (`T`)
+/// type parameters of [`DataMessage`], e.g.,
+/// `RealtimeData<IEX, DataMessage<Raw<Bar>, Raw<Quote>, Raw<Trade>>>`,
+/// in which case every data item carries the JSON object received next
+/// to the decoded value. Note that the message type tag is not part of the
+/// retained object and that its formatting is not preserved.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Raw<T> {
+  /// The decoded object.
+  pub parsed: T,
+  /// The JSON object as received, including fields not known to
+  /// `T`.
+  pub raw: JsonValue,
+}
+
+impl<'de, T> Deserialize<'de> for Raw<T>
+where
+  T: DeserializeOwned,
+{
+  fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+  where
+    D: Deserializer<'de>,
+  {
+    let raw = JsonValue::deserialize(deserializer)?;
+    let parsed = T::deserialize(&raw).map_err(D::Error::custom)?;
+    Ok(Self { parsed, raw })
+  }
+}
+
+impl<T> Serialize for Raw<T> {
+  fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+  where
+    S: Serializer,
+  {
+    self.raw.serialize(serializer)
+  }
+}
+
+
+/// Market data types using `f64` instead of [`Num`] for prices and
+/// sizes.
+///
+/// Parsing `f64` values is considerably cheaper than parsing decimals,
+/// but it comes at the cost of precision: not every decimal value can
+/// be represented exactly as an `f64`. The types in this module use
+/// the same wire format as their [`Num`] based counterparts and can be
+/// used in their stead as the `B`, `Q`, and `T` type parameters of
+/// [`DataMessage`], e.g., `RealtimeData<IEX, DataMessage<float::Bar,
+/// float::Quote, float::Trade>>`.
+///
+/// This module is only available with the `float-data` feature.
+#[cfg(feature = "float-data")]
+pub mod float {
+  use super::*;
+
+
+  /// Aggregate data for an equity, using `f64` values.
+  #[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+  pub struct Bar {
+    /// The bar's symbol.
+    #[serde(rename = "S")]
+    pub symbol: String,
+    /// The bar's open price.
+    #[serde(rename = "o")]
+    pub open_price: f64,
+    /// The bar's high price.
+    #[serde(rename = "h")]
+    pub high_price: f64,
+    /// The bar's low price.
+    #[serde(rename = "l")]
+    pub low_price: f64,
+    /// The bar's close price.
+    #[serde(rename = "c")]
+    pub close_price: f64,
+    /// The bar's volume.
+    #[serde(rename = "v")]
+    pub volume: f64,
+    /// The bar's time stamp.
+    #[serde(rename = "t", deserialize_with = "datetime_from_str_or_nanos")]
+    pub timestamp: DateTime<Utc>,
+  }
+
+  impl HasSymbol for Bar {
+    #[inline]
+    fn symbol(&self) -> &str {
+      &self.symbol
+    }
+  }
+
+
+  /// A quote for an equity, using `f64` values.
+  #[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+  pub struct Quote {
+    /// The quote's symbol.
+    #[serde(rename = "S")]
+    pub symbol: String,
+    /// The bid's price.
+    #[serde(rename = "bp")]
+    pub bid_price: f64,
+    /// The bid's size.
+    #[serde(rename = "bs")]
+    pub bid_size: f64,
+    /// The ask's price.
+    #[serde(rename = "ap")]
+    pub ask_price: f64,
+    /// The ask's size.
+    #[serde(rename = "as")]
+    pub ask_size: f64,
+    /// The quote's time stamp.
+    #[serde(rename = "t", deserialize_with = "datetime_from_str_or_nanos")]
+    pub timestamp: DateTime<Utc>,
+  }
+
+  impl HasSymbol for Quote {
+    #[inline]
+    fn symbol(&self) -> &str {
+      &self.symbol
+    }
+  }
+
+
+  /// A trade for an equity, using `f64` values.
+  #[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+  pub struct Trade {
+    /// The trade's symbol.
+    #[serde(rename = "S")]
+    pub symbol: String,
+    /// The trade's ID.
+    #[serde(rename = "i")]
+    pub trade_id: u64,
+    /// The trade's price.
+    #[serde(rename = "p")]
+    pub trade_price: f64,
+    /// The trade's size.
+    #[serde(rename = "s")]
+    pub trade_size: f64,
+    /// The trade's time stamp.
+    #[serde(rename = "t", deserialize_with = "datetime_from_str_or_nanos")]
+    pub timestamp: DateTime<Utc>,
+  }
+
+  impl HasSymbol for Trade {
+    #[inline]
+    fn symbol(&self) -> &str {
+      &self.symbol
+    }
+  }
+}
+
+
+/// An error as reported by the Alpaca Stream API.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize, ThisError)]
+#[error("{message} ({code})")]
+pub struct StreamApiError {
+  /// The error code being reported.
+  #[serde(rename = "code")]
+  pub code: u64,
+  /// A message providing more details about the error.
+  #[serde(rename = "msg")]
+  pub message: String,
+}
+
+
+/// A classification of errors reported by the Alpaca Stream API, as
+/// inferred from their code.
+#[derive(Clone, Debug, Eq, PartialEq, ThisError)]
+#[non_exhaustive]
+pub enum StreamError {
+  /// A message sent to the server had invalid syntax (400).
+  #[error("invalid syntax")]
+  InvalidSyntax(#[source] StreamApiError),
+  /// An operation was attempted before successful authentication
+  /// (401).
+  #[error("not authenticated")]
+  NotAuthenticated(#[source] StreamApiError),
+  /// The provided credentials were rejected (402).
+  #[error("authentication failed")]
+  AuthenticationFailed(#[source] StreamApiError),
+  /// The connection is already authenticated (403).
+  #[error("already authenticated")]
+  AlreadyAuthenticated(#[source] StreamApiError),
+  /// The maximum number of concurrent connections for the account was
+  /// exceeded (406).
+  #[error("connection limit exceeded")]
+  ConnectionLimitExceeded(#[source] StreamApiError),
+  /// A subscription request was rejected, because the symbol limit was
+  /// exceeded (405), the account lacks permission for the requested
+  /// data (409), or the subscription action was invalid (410).
+  #[error("invalid subscription")]
+  InvalidSubscription(#[source] StreamApiError),
+  /// The server encountered an internal error (500).
+  #[error("internal error")]
+  InternalError(#[source] StreamApiError),
+  /// An error with a code not known to this crate.
+  #[error(transparent)]
+  Other(StreamApiError),
+}
+
+impl StreamError {
+  /// Retrieve the error as originally reported by the Alpaca Stream
+  /// API, including its code and message.
+  #[inline]
+  pub fn api_error(&self) -> &StreamApiError {
+    match self {
+      Self::InvalidSyntax(error)
+      | Self::NotAuthenticated(error)
+      | Self::AuthenticationFailed(error)
+      | Self::AlreadyAuthenticated(error)
+      | Self::ConnectionLimitExceeded(error)
+      | Self::InvalidSubscription(error)
+      | Self::InternalError(error)
+      | Self::Other(error) => error,
+    }
+  }
+}
+
+impl From<StreamApiError> for StreamError {
+  fn from(error: StreamApiError) -> Self {
+    match error.code {
+      400 => Self::InvalidSyntax(error),
+      401 => Self::NotAuthenticated(error),
+      402 => Self::AuthenticationFailed(error),
+      403 => Self::AlreadyAuthenticated(error),
+      406 => Self::ConnectionLimitExceeded(error),
+      405 | 409 | 410 => Self::InvalidSubscription(error),
+      500 => Self::InternalError(error),
+      _ => Self::Other(error),
+    }
+  }
+}
+
+
+/// An owned and cloneable representation of the errors that may be
+/// encountered while streaming realtime data.
+///
+/// Contrary to [`JsonError`] and [`WebSocketError`], objects of this
+/// type can be cloned and freely sent across threads, which makes them
+/// suitable for aggregating errors, e.g., for reporting purposes.
+#[derive(Clone, Debug, Eq, PartialEq, ThisError)]
+#[non_exhaustive]
+pub enum OwnedStreamError {
+  /// An error reported by the websocket layer.
+  #[error("websocket error: {0}")]
+  WebSocket(String),
+  /// A message failed to decode.
+  #[error("{message}{}", json_position(*.line, *.column))]
+  Json {
+    /// A description of the error.
+    message: String,
+    /// The one-based line in the input at which the error occurred, or
+    /// zero if the error is not associated with a position.
+    line: usize,
+    /// The one-based column in the input at which the error occurred,
+    /// or zero if the error is not associated with a position.
+    column: usize,
+  },
+  /// An error reported by the Alpaca Stream API.
+  #[error(transparent)]
+  Api(StreamError),
+}
+
+
+/// Format the position of a JSON error the way `serde_json` does.
+fn json_position(line: usize, column: usize) -> String {
+  if line == 0 {
+    String::new()
+  } else {
+    format!(" at line {line} column {column}")
+  }
+}
+
+impl From<&WebSocketError> for OwnedStreamError {
+  #[inline]
+  fn from(error: &WebSocketError) -> Self {
+    Self::WebSocket(error.to_string())
+  }
+}
+
+impl From<&JsonError> for OwnedStreamError {
+  fn from(error: &JsonError) -> Self {
+    let message = error.to_string();
+    // `JsonError`'s `Display` implementation appends the position,
+    // which we report separately.
+    let suffix = json_position(error.line(), error.column());
+    let message = message
+      .strip_suffix(&suffix)
+      .map(str::to_string)
+      .unwrap_or(message);
+
+    Self::Json {
+      message,
+      line: error.line(),
+      column: error.column(),
+    }
+  }
+}
+
+impl From<StreamError> for OwnedStreamError {
+  #[inline]
+  fn from(error: StreamError) -> Self {
+    Self::Api(error)
+  }
+}
+
+
+/// An enum representing the different messages we may receive over our
+/// websocket channel.
+///
+/// The server sends these messages in the form of JSON arrays. Use
+/// [`classify_message`] to separate market data from control messages.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+#[serde(remote = "Self", tag = "T")]
+#[allow(clippy::large_enum_variant)]
+pub enum DataMessage<B = Bar, Q = Quote, T = Trade> {
+  /// A variant representing aggregate data for a given symbol.
+  #[serde(rename = "b")]
+  Bar(B),
+  /// A variant representing a quote for a given symbol.
+  #[serde(rename = "q")]
+  Quote(Q),
+  /// A variant representing a trade for a given symbol.
+  #[serde(rename = "t")]
+  Trade(T),
+  /// A variant representing daily aggregate data for a given symbol.
+  #[serde(rename = "d")]
+  DailyBar(B),
+  /// A variant representing aggregate data for a given symbol that
+  /// corrects a previously reported bar, e.g., due to late trades.
+  #[serde(rename = "u")]
+  UpdatedBar(B),
+  /// A variant representing limit up/limit down price bands for a
+  /// given symbol.
+  #[serde(rename = "l")]
+  Luld(Luld),
+  /// A control message describing the current list of subscriptions.
+  #[serde(rename = "subscription")]
+  Subscription(MarketData),
+  /// A control message indicating that the last operation was
+  /// successful.
+  #[serde(rename = "success")]
+  Success {
+    /// The message accompanying the success indication, e.g.,
+    /// `connected` or `authenticated`.
+    #[serde(rename = "msg", default)]
+    message: String,
+  },
+  /// An error reported by the Alpaca Stream API.
+  #[serde(rename = "error")]
+  Error(StreamApiError),
+  /// A message of a type not known to this crate, in its raw JSON
+  /// form (including the `"T"` tag).
+  #[serde(skip_serializing)]
+  Unknown(JsonValue),
+}
+
+
+/// The `"T"` tag of a [`DataMessage`].
+enum Tag {
+  Bar,
+  Quote,
+  Trade,
+  DailyBar,
+  UpdatedBar,
+  Luld,
+  Subscription,
+  Success,
+  Error,
+  /// A tag not known to this crate.
+  Unknown(String),
+}
+
+impl From<&str> for Tag {
+  fn from(tag: &str) -> Self {
+    match tag {
+      "b" => Self::Bar,
+      "q" => Self::Quote,
+      "t" => Self::Trade,
+      "d" => Self::DailyBar,
+      "u" => Self::UpdatedBar,
+      "l" => Self::Luld,
+      "subscription" => Self::Subscription,
+      "success" => Self::Success,
+      "error" => Self::Error,
+      tag => Self::Unknown(tag.to_string()),
+    }
+  }
+}
+
+impl<'de> Deserialize<'de> for Tag {
+  fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+  where
+    D: Deserializer<'de>,
+  {
+    struct TagVisitor;
+
+    impl<'de> Visitor<'de> for TagVisitor {
+      type Value = Tag;
+
+      fn expecting(&self, fmt: &mut Formatter<'_>) -> FmtResult {
+        fmt.write_str("a message type tag")
+      }
+
+      fn visit_str<E>(self, tag: &str) -> Result<Self::Value, E>
+      where
+        E: serde::de::Error,
+      {
+        Ok(Tag::from(tag))
+      }
+    }
+
+    deserializer.deserialize_str(TagVisitor)
+  }
+}
+
+
+/// A key of a [`DataMessage`] object, distinguishing the `"T"` tag
+/// from everything else.
+enum Key {
+  Tag,
+  Other(String),
+}
+
+impl<'de> Deserialize<'de> for Key {
+  fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+  where
+    D: Deserializer<'de>,
+  {
+    struct KeyVisitor;
+
+    impl<'de> Visitor<'de> for KeyVisitor {
+      type Value = Key;
+
+      fn expecting(&self, fmt: &mut Formatter<'_>) -> FmtResult {
+        fmt.write_str("a message field name")
+      }
+
+      fn visit_str<E>(self, key: &str) -> Result<Self::Value, E>
+      where
+        E: serde::de::Error,
+      {
+        if key == "T" {
+          Ok(Key::Tag)
+        } else {
+          Ok(Key::Other(key.to_string()))
+        }
+      }
+    }
+
+    deserializer.deserialize_str(KeyVisitor)
+  }
+}
+
+
+/// The payload of a [`DataMessage::Success`] message.
+#[derive(Deserialize)]
+struct SuccessPayload {
+  #[serde(rename = "msg", default)]
+  message: String,
+}
+
+
+impl<B, Q, T> DataMessage<B, Q, T> {
+  /// Deserialize the payload of a message of known type, i.e., the
+  /// message's object without the `"T"` tag.
+  fn deserialize_payload<'de, D>(tag: Tag, deserializer: D) -> Result<Self, D::Error>
+  where
+    D: Deserializer<'de>,
+    B: Deserialize<'de>,
+    Q: Deserialize<'de>,
+    T: Deserialize<'de>,
+  {
+    match tag {
+      Tag::Bar => B::deserialize(deserializer).map(Self::Bar),
+      Tag::Quote => Q::deserialize(deserializer).map(Self::Quote),
+      Tag::Trade => T::deserialize(deserializer).map(Self::Trade),
+      Tag::DailyBar => B::deserialize(deserializer).map(Self::DailyBar),
+      Tag::UpdatedBar => B::deserialize(deserializer).map(Self::UpdatedBar),
+      Tag::Luld => Luld::deserialize(deserializer).map(Self::Luld),
+      Tag::Subscription => MarketData::deserialize(deserializer).map(Self::Subscription),
+      Tag::Success => SuccessPayload::deserialize(deserializer).map(|payload| Self::Success {
+        message: payload.message,
+      }),
+      Tag::Error => StreamApiError::deserialize(deserializer).map(Self::Error),
+      Tag::Unknown(..) => unreachable!(),
+    }
+  }
+}
+
+
+/// A visitor for [`DataMessage`] objects.
+///
+/// Alpaca sends the `"T"` tag first, in which case the remainder of
+/// the object is deserialized directly into the tagged variant's
+/// payload. Only if the tag comes later (or is of a type we don't
+/// know) do we buffer the object.
+struct DataMessageVisitor<B, Q, T>(PhantomData<(B, Q, T)>);
+
+impl<'de, B, Q, T> Visitor<'de> for DataMessageVisitor<B, Q, T>
+where
+  B: Deserialize<'de>,
+  Q: Deserialize<'de>,
+  T: Deserialize<'de>,
+{
+  type Value = DataMessage<B, Q, T>;
+
+  fn expecting(&self, fmt: &mut Formatter<'_>) -> FmtResult {
+    fmt.write_str("a data message object")
+  }
+
+  fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+  where
+    A: MapAccess<'de>,
+  {
+    let mut object = JsonMap::new();
+
+    match map.next_key::<Key>()? {
+      Some(Key::Tag) => match map.next_value::<Tag>()? {
+        Tag::Unknown(tag) => {
+          let _prev = object.insert("T".to_string(), JsonValue::String(tag));
+        },
+        tag => return DataMessage::deserialize_payload(tag, MapAccessDeserializer::new(map)),
+      },
+      Some(Key::Other(key)) => {
+        let _prev = object.insert(key, map.next_value()?);
+      },
+      None => (),
+    }
+
+    while let Some((key, value)) = map.next_entry::<String, JsonValue>()? {
+      let _prev = object.insert(key, value);
+    }
+
+    match object.get("T").and_then(JsonValue::as_str).map(Tag::from) {
+      Some(Tag::Unknown(..)) | None => Ok(DataMessage::Unknown(JsonValue::Object(object))),
+      Some(tag) => {
+        let _tag = object.remove("T");
+        DataMessage::deserialize_payload(tag, JsonValue::Object(object)).map_err(A::Error::custom)
+      },
+    }
+  }
+}
+
+impl<'de, B, Q, T> Deserialize<'de> for DataMessage<B, Q, T>
+where
+  B: Deserialize<'de>,
+  Q: Deserialize<'de>,
+  T: Deserialize<'de>,
+{
+  fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+  where
+    D: Deserializer<'de>,
+  {
+    deserializer.deserialize_map(DataMessageVisitor(PhantomData))
+  }
+}
+
+impl<B, Q, T> Serialize for DataMessage<B, Q, T>
+where
+  B: Serialize,
+  Q: Serialize,
+  T: Serialize,
+{
+  #[inline]
+  fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+  where
+    S: Serializer,
+  {
+    match self {
+      Self::Unknown(value) => value.serialize(serializer),
+      _ => Self::serialize(self, serializer),
+    }
+  }
+}
+
+
+/// The cadence of an aggregate bar.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[non_exhaustive]
+pub enum BarKind {
+  /// A minute bar.
+  Minute,
+  /// A daily bar.
+  Daily,
+  /// A minute bar correcting one reported earlier, e.g., due to late
+  /// trades. It supersedes the earlier bar and does not represent a
+  /// new interval.
+  Updated,
+}
+
+
+/// A data item as received over our websocket channel.
+#[derive(Debug)]
+#[non_exhaustive]
+#[allow(clippy::large_enum_variant)]
+pub enum Data<B = Bar, Q = Quote, T = Trade> {
+  /// A variant representing aggregate data for a given symbol.
+  Bar {
+    /// The actual bar.
+    bar: B,
+    /// The cadence of the bar.
+    kind: BarKind,
+  },
+  /// A variant representing quote data for a given symbol.
+  Quote(Q),
+  /// A variant representing trade data for a given symbol.
+  Trade(T),
+  /// A variant representing limit up/limit down price bands for a
+  /// given symbol.
+  Luld(Luld),
+}
+
+impl Data {
+  /// Check whether this object is of the `Bar` variant, irrespective
+  /// of the bar's cadence.
+  #[inline]
+  pub fn is_bar(&self) -> bool {
+    matches!(self, Self::Bar { .. })
+  }
+
+  /// Check whether this object is of the `Quote` variant.
+  #[inline]
+  pub fn is_quote(&self) -> bool {
+    matches!(self, Self::Quote(..))
+  }
+
+  /// Check whether this object is of the `Trade` variant.
+  #[inline]
+  pub fn is_trade(&self) -> bool {
+    matches!(self, Self::Trade(..))
+  }
+
+  /// Check whether this object is of the `Luld` variant.
+  #[inline]
+  pub fn is_luld(&self) -> bool {
+    matches!(self, Self::Luld(..))
+  }
+
+  /// Check whether this object is of the `Bar` variant representing a
+  /// daily bar.
+  #[inline]
+  pub fn is_daily_bar(&self) -> bool {
+    matches!(
+      self,
+      Self::Bar {
+        kind: BarKind::Daily,
+        ..
+      }
+    )
+  }
+}
+
+impl<B, Q, T> Data<B, Q, T>
+where
+  B: HasSymbol,
+  Q: HasSymbol,
+  T: HasSymbol,
+{
+  /// Retrieve the symbol the data pertains to.
+  #[inline]
+  pub fn symbol(&self) -> &str {
+    match self {
+      Self::Bar { bar, .. } => bar.symbol(),
+      Self::Quote(quote) => quote.symbol(),
+      Self::Trade(trade) => trade.symbol(),
+      Self::Luld(luld) => &luld.symbol,
+    }
+  }
+}
+
+
+/// An enumeration of the supported control messages.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ControlMessage<C = MarketData> {
+  /// A control message describing the current list of subscriptions.
+  ///
+  /// The contained data is the server-authoritative set of active
+  /// subscriptions. While typically sent in response to a subscription
+  /// or unsubscription request, this message may also arrive
+  /// unsolicited in some cases and consumers of control messages should
+  /// always be prepared to handle it.
+  Subscription(C),
+  /// A control message indicating that the last operation was
+  /// successful.
+  Success {
+    /// The message accompanying the success indication, e.g.,
+    /// `connected` or `authenticated`.
+    message: String,
+  },
+  /// An error reported by the Alpaca Stream API.
+  Error(StreamApiError),
+  /// A message of a type not known to this crate, in its raw JSON
+  /// form.
+  ///
+  /// Note that messages of unknown type received over a realtime data
+  /// stream are discarded and never make it to the subscription.
+  Unknown(JsonValue),
+}
+
+
+/// A helper for logging the number of symbols per channel of a
+/// [`Channels`] object.
+struct CountsSummary<'c, C>(&'c C);
+
+impl<C> Display for CountsSummary<'_, C>
+where
+  C: Channels,
+{
+  fn fmt(&self, fmt: &mut Formatter<'_>) -> FmtResult {
+    for (index, (name, symbols)) in C::NAMES.iter().zip(self.0.fields()).enumerate() {
+      if index > 0 {
+        let () = fmt.write_str(", ")?;
+      }
+      let () = write!(fmt, "{name}: {}", symbols.count())?;
+    }
+    Ok(())
+  }
+}
+
+
+/// A helper for logging a concise summary of a [`ControlMessage`].
+struct ControlSummary<'c, C>(&'c ControlMessage<C>);
+
+impl<C> Display for ControlSummary<'_, C>
+where
+  C: Channels,
+{
+  fn fmt(&self, fmt: &mut Formatter<'_>) -> FmtResult {
+    match self.0 {
+      ControlMessage::Subscription(data) => write!(fmt, "subscription ({})", CountsSummary(data)),
+      ControlMessage::Success { message } => write!(fmt, "success: {message}"),
+      ControlMessage::Error(error) => write!(fmt, "error: {error}"),
+      ControlMessage::Unknown(value) => write!(fmt, "unknown message: {}", value["T"]),
+    }
+  }
+}
+
+
+/// Parse a single [`DataMessage`] from its raw JSON representation.
+///
+/// Contrary to the payload of a websocket message, which contains an
+/// array of messages, `data` is expected to hold exactly one JSON
+/// object. Surrounding whitespace is permitted, any other trailing
+/// data is rejected. Use [`parse_message_lenient`] for the latter.
+pub fn parse_message<B, Q, T>(data: &[u8]) -> Result<DataMessage<B, Q, T>, JsonError>
+where
+  DataMessage<B, Q, T>: DeserializeOwned,
+{
+  json_from_slice::<DataMessage<B, Q, T>>(data)
+}
+
+
+/// Parse a single [`DataMessage`] from its raw JSON representation,
+/// ignoring anything following the JSON object.
+///
+/// This function is meant for transports that may append additional
+/// data to a message, such as delimiters or framing information, which
+/// [`parse_message`] would reject.
+pub fn parse_message_lenient<B, Q, T>(data: &[u8]) -> Result<DataMessage<B, Q, T>, JsonError>
+where
+  DataMessage<B, Q, T>: DeserializeOwned,
+{
+  let mut deserializer = JsonDeserializer::from_slice(data);
+  // Note that we intentionally do not call `Deserializer::end` here, as
+  // it is what checks for trailing data.
+  DataMessage::<B, Q, T>::deserialize(&mut deserializer)
+}
+
+
+/// The result of classifying a [`DataMessage`].
+#[derive(Debug)]
+pub enum Classified<B = Bar, Q = Quote, T = Trade> {
+  /// The message contained market data.
+  Data(Data<B, Q, T>),
+  /// The message was a control message.
+  Control(ControlMessage),
+}
+
+
+/// Classify a [`DataMessage`] as either market data or a control
+/// message.
+///
+/// This is the mapping applied to messages received over a realtime
+/// data stream, made available for use outside of it, e.g., for
+/// decoding a recorded capture of messages.
+pub fn classify_message<B, Q, T>(message: DataMessage<B, Q, T>) -> Classified<B, Q, T> {
+  match message {
+    DataMessage::Bar(bar) => Classified::Data(Data::Bar {
+      bar,
+      kind: BarKind::Minute,
+    }),
+    DataMessage::Quote(quote) => Classified::Data(Data::Quote(quote)),
+    DataMessage::Trade(trade) => Classified::Data(Data::Trade(trade)),
+    DataMessage::DailyBar(bar) => Classified::Data(Data::Bar {
+      bar,
+      kind: BarKind::Daily,
+    }),
+    DataMessage::UpdatedBar(bar) => Classified::Data(Data::Bar {
+      bar,
+      kind: BarKind::Updated,
+    }),
+    DataMessage::Luld(luld) => Classified::Data(Data::Luld(luld)),
+    DataMessage::Subscription(data) => Classified::Control(ControlMessage::Subscription(data)),
+    DataMessage::Success { message } => Classified::Control(ControlMessage::Success { message }),
+    DataMessage::Error(error) => Classified::Control(ControlMessage::Error(error)),
+    DataMessage::Unknown(value) => Classified::Control(ControlMessage::Unknown(value)),
+  }
+}
+
+
+/// The kind of a message received over a realtime data stream.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum MessageKind {
+  /// An aggregate bar.
+  Bar,
+  /// A quote.
+  Quote,
+  /// A trade.
+  Trade,
+  /// A daily aggregate bar.
+  DailyBar,
+  /// An updated aggregate bar.
+  UpdatedBar,
+  /// Limit up/limit down price bands.
+  Luld,
+  /// Market data of a kind not covered by any of the other variants.
+  OtherData,
+  /// A subscription update.
+  Subscription,
+  /// A success message.
+  Success,
+  /// An error reported by the server.
+  Error,
+  /// A message of unknown type. Such messages are discarded.
+  Unknown,
+}
+
+impl MessageKind {
+  /// Check whether the kind is one of market data, as opposed to a
+  /// control message.
+  #[inline]
+  pub fn is_data(&self) -> bool {
+    matches!(
+      self,
+      Self::Bar
+        | Self::Quote
+        | Self::Trade
+        | Self::DailyBar
+        | Self::UpdatedBar
+        | Self::Luld
+        | Self::OtherData
+    )
+  }
+}
+
+
+/// A message as received over a realtime data stream.
+///
+/// [`DataMessage`] is the implementation used by default. A custom one
+/// can be used for handling message types not (yet) supported by this
+/// crate, e.g., those of beta features, by plugging it into
+/// [`RealtimeData`].
+pub trait StreamMessage {
+  /// The market data contained in messages, as yielded by the stream.
+  type Data;
+
+  /// The channels that can be subscribed to.
+  type Channels: Channels;
+
+  /// Determine the kind of the message.
+  ///
+  /// The kind is used for accounting purposes, for deciding which
+  /// messages to hold back while delivery is
+  /// [paused][Subscription::pause], and for discarding messages of
+  /// unknown type.
+  fn kind(&self) -> MessageKind;
+
+  /// Classify the message, converting it into the market data it
+  /// contains or, in case of a control message, into a
+  /// [`ControlMessage`].
+  fn classify(self) -> Result<Self::Data, ControlMessage<Self::Channels>>;
+
+  /// Retrieve a copy of the message as a [`ControlMessage`], if it is
+  /// one.
+  ///
+  /// This method is used for publishing control messages via
+  /// [`Subscription::control_stream`]. The default implementation
+  /// returns `None`, meaning that no control messages are published.
+  #[inline]
+  fn to_control(&self) -> Option<ControlMessage<Self::Channels>> {
+    None
+  }
+}
+
+impl<B, Q, T> StreamMessage for DataMessage<B, Q, T> {
+  type Data = Data<B, Q, T>;
+  type Channels = MarketData;
+
+  fn kind(&self) -> MessageKind {
+    match self {
+      Self::Bar(..) => MessageKind::Bar,
+      Self::Quote(..) => MessageKind::Quote,
+      Self::Trade(..) => MessageKind::Trade,
+      Self::DailyBar(..) => MessageKind::DailyBar,
+      Self::UpdatedBar(..) => MessageKind::UpdatedBar,
+      Self::Luld(..) => MessageKind::Luld,
+      Self::Subscription(..) => MessageKind::Subscription,
+      Self::Success { .. } => MessageKind::Success,
+      Self::Error(..) => MessageKind::Error,
+      Self::Unknown(..) => MessageKind::Unknown,
+    }
+  }
+
+  #[inline]
+  fn classify(self) -> Result<Self::Data, ControlMessage> {
+    match classify_message(self) {
+      Classified::Data(data) => Ok(data),
+      Classified::Control(control) => Err(control),
+    }
+  }
+
+  fn to_control(&self) -> Option<ControlMessage> {
+    match self {
+      Self::Subscription(data) => Some(ControlMessage::Subscription(data.clone())),
+      Self::Success { message } => Some(ControlMessage::Success {
+        message: message.clone(),
+      }),
+      Self::Error(error) => Some(ControlMessage::Error(error.clone())),
+      Self::Unknown(value) => Some(ControlMessage::Unknown(value.clone())),
+      Self::Bar(..)
+      | Self::Quote(..)
+      | Self::Trade(..)
+      | Self::DailyBar(..)
+      | Self::UpdatedBar(..)
+      | Self::Luld(..) => None,
+    }
+  }
+}
+
+
+/// A websocket message that we tried to parse.
+type ParsedMessage<M> = MessageResult<Result<M, JsonError>, WebSocketError>;
+
+impl<M> subscribe::Message for ParsedMessage<M>
+where
+  M: StreamMessage,
+{
+  type UserMessage = Result<Result<M::Data, JsonError>, WebSocketError>;
+  type ControlMessage = ControlMessage<M::Channels>;
+
+  fn classify(self) -> subscribe::Classification<Self::UserMessage, Self::ControlMessage> {
+    match self {
+      MessageResult::Ok(Ok(message)) => {
+        let kind = message.kind();
+        match message.classify() {
+          Ok(data) => {
+            trace!(kind = ?kind, "received market data");
+            subscribe::Classification::UserMessage(Ok(Ok(data)))
+          },
+          Err(control) => {
+            debug!(control = %ControlSummary(&control), "received control message");
+            subscribe::Classification::ControlMessage(control)
+          },
+        }
+      },
+      // JSON errors are directly passed through.
+      MessageResult::Ok(Err(err)) => subscribe::Classification::UserMessage(Ok(Err(err))),
+      // WebSocket errors are also directly pushed through.
+      MessageResult::Err(err) => subscribe::Classification::UserMessage(Err(err)),
+    }
+  }
+
+  #[inline]
+  fn is_error(user_message: &Self::UserMessage) -> bool {
+    is_error(user_message)
+  }
+}
+
+
+/// Parse a websocket message into the data messages it contains.
+fn parse_messages<M>(result: Result<wrap::Message, WebSocketError>) -> ParseResult<M>
+where
+  M: StreamMessage + DeserializeOwned,
+{
+  result.map(|message| {
+    match message {
+      wrap::Message::Text(string) => json_from_str::<Vec<M>>(&string),
+      wrap::Message::Binary(data) => json_from_slice::<Vec<M>>(&data),
+    }
+    .map(retain_known)
+  })
+}
+
+
+/// Replay market data previously recorded from a realtime data stream.
+///
+/// Each line read from `reader` is expected to contain the payload of
+/// a single websocket message as sent by the server, i.e., a JSON array
+/// of messages. Empty lines are skipped. Lines are parsed and
+/// classified the same way messages received over a live connection
+/// are: control messages are filtered out and the resulting stream
+/// yields the same items as the stream returned when connecting to
+/// [`RealtimeData`] does. I/O errors are reported as
+/// [`WebSocketError::Io`].
+///
+/// ```
+/// # use futures::StreamExt as _;
+/// # use apca::data::v2::stream::replay;
+/// # use apca::data::v2::stream::Data;
+/// # futures::executor::block_on(async {
+/// let recording = br#"[{"T":"success","msg":"authenticated"}]
+/// [{"T":"t","S":"SPY","i":1,"p":400.1,"s":10,"t":"2022-11-29T15:00:00Z"}]
+/// "#;
+/// let mut stream = replay::<_, _, _, _>(&recording[..]);
+/// let data: Data = stream.next().await.unwrap().unwrap().unwrap();
+/// assert!(data.is_trade());
+/// assert!(stream.next().await.is_none());
+/// # })
+/// ```
+#[allow(clippy::result_large_err)]
+pub fn replay<R, B, Q, T>(
+  reader: R,
+) -> impl FusedStream<Item = Result<Result<Data<B, Q, T>, JsonError>, WebSocketError>>
+where
+  R: AsyncBufRead + Unpin,
+  B: Unpin + DeserializeOwned,
+  Q: Unpin + DeserializeOwned,
+  T: Unpin + DeserializeOwned,
+{
+  let lines = reader
+    .lines()
+    .filter(|result| ready(!matches!(result, Ok(line) if line.trim().is_empty())))
+    .map(|result| result.map(wrap::Message::Text).map_err(WebSocketError::Io))
+    .map(parse_messages::<DataMessage<B, Q, T>>);
+
+  Unfold::new(lines)
+    .map(ParsedMessage::<DataMessage<B, Q, T>>::from)
+    .filter_map(|message| {
+      let item = match subscribe::Message::classify(message) {
+        subscribe::Classification::UserMessage(item) => Some(item),
+        subscribe::Classification::ControlMessage(..) => None,
+      };
+      ready(item)
+    })
+    .fuse()
+}
+
+
+/// Configuration of the connection used for streaming realtime data.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub struct StreamConfig {
+  /// The interval at which to send pings to the server to check
+  /// whether the connection is still alive. A value of `None` disables
+  /// the sending of pings.
+  ///
+  /// The interval also acts as the pong timeout: if no pong (or any
+  /// other message) has been received by the time the next ping is
+  /// due, the connection is considered dead and the stream reports an
+  /// error.
+  pub ping_interval: Option<Duration>,
+  /// Whether to report the offending payload along with errors for
+  /// messages that failed to decode.
+  ///
+  /// The payload is emitted as the `payload` field of a `tracing`
+  /// event at debug level, while the error itself is reported
+  /// unchanged. Doing so is helpful for diagnosing changes to the wire
+  /// format, but incurs an additional allocation for every such error.
+  /// Disabled by default.
+  pub payload_in_errors: bool,
+  /// The maximum number of market data messages to hold back while
+  /// delivery is paused using [`PauseMode::Buffer`].
+  ///
+  /// Messages are read from the underlying connection only while the
+  /// stream is being polled, i.e., apart from that buffer the stream
+  /// does not queue up data on its own. A consumer that can't keep up
+  /// with the rate at which data arrives hence causes data to pile up
+  /// in the operating system's socket buffers and, eventually, on the
+  /// server, which may then terminate the connection. While paused,
+  /// however, the stream keeps reading and, once the buffer is full,
+  /// the oldest buffered message is discarded for every new one. A
+  /// value of `None`, which is the default, means that the buffer is
+  /// unbounded.
+  pub buffer_capacity: Option<usize>,
+  /// The maximum amount of time to wait for the server to respond to a
+  /// control message, including the ones exchanged while connecting.
+  ///
+  /// The timeout is applied to the created [`Subscription`] as if set
+  /// via [`Subscription::set_timeout`]. A value of `None`, which is the
+  /// default, means to wait indefinitely.
+  pub control_timeout: Option<Duration>,
+}
+
+impl StreamConfig {
+  /// Set the interval at which to send pings to the server.
+  #[inline]
+  pub fn with_ping_interval(mut self, ping_interval: Option<Duration>) -> Self {
+    self.ping_interval = ping_interval;
+    self
+  }
+
+  /// Set whether to include the offending payload in decoding errors.
+  #[inline]
+  pub fn with_payload_in_errors(mut self, enable: bool) -> Self {
+    self.payload_in_errors = enable;
+    self
+  }
+
+  /// Set the maximum number of market data messages to buffer while
+  /// paused.
+  #[inline]
+  pub fn with_buffer_capacity(mut self, capacity: Option<usize>) -> Self {
+    self.buffer_capacity = capacity;
+    self
+  }
+
+  /// Set the maximum amount of time to wait for the server to respond
+  /// to a control message.
+  #[inline]
+  pub fn with_control_timeout(mut self, timeout: Option<Duration>) -> Self {
+    self.control_timeout = timeout;
+    self
+  }
+}
+
+impl Default for StreamConfig {
+  fn default() -> Self {
+    Self {
+      ping_interval: Some(PING_INTERVAL),
+      payload_in_errors: false,
+      buffer_capacity: None,
+      control_timeout: None,
+    }
+  }
+}
+
+
+/// Statistics about the messages received over a realtime data stream.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[non_exhaustive]
+pub struct StreamStats {
+  /// The number of bars received, including updated ones.
+  pub bars: u64,
+  /// The number of quotes received.
+  pub quotes: u64,
+  /// The number of trades received.
+  pub trades: u64,
+  /// The number of daily bars received.
+  pub daily_bars: u64,
+  /// The number of limit up/limit down messages received.
+  pub lulds: u64,
+  /// The number of success control messages received.
+  pub successes: u64,
+  /// The number of errors reported by the server.
+  pub errors: u64,
+}
+
+
+/// Counters of the messages received over a realtime data stream.
+#[derive(Debug)]
+struct Counters {
+  bars: AtomicU64,
+  quotes: AtomicU64,
+  trades: AtomicU64,
+  daily_bars: AtomicU64,
+  lulds: AtomicU64,
+  successes: AtomicU64,
+  errors: AtomicU64,
+  /// The point in time relative to which `last_data` is expressed.
+  base: Instant,
+  /// The time at which the most recent market data message was
+  /// received, as nanoseconds since `base` plus one, or zero if none
+  /// has been received yet.
+  last_data: AtomicU64,
+}
+
+impl Default for Counters {
+  fn default() -> Self {
+    Self {
+      bars: AtomicU64::default(),
+      quotes: AtomicU64::default(),
+      trades: AtomicU64::default(),
+      daily_bars: AtomicU64::default(),
+      lulds: AtomicU64::default(),
+      successes: AtomicU64::default(),
+      errors: AtomicU64::default(),
+      base: Instant::now(),
+      last_data: AtomicU64::default(),
+    }
+  }
+}
+
+impl Counters {
+  /// Account for the provided message.
+  fn count<M>(&self, message: &ParsedMessage<M>)
+  where
+    M: StreamMessage,
+  {
+    let kind = match message {
+      MessageResult::Ok(Ok(message)) => message.kind(),
+      MessageResult::Ok(Err(..)) | MessageResult::Err(..) => return,
+    };
+    if kind.is_data() {
+      // An offset of more than 584 years does not fit into a `u64`; we
+      // just saturate.
+      let nanos = u64::try_from(self.base.elapsed().as_nanos()).unwrap_or(u64::MAX - 1);
+      let () = self.last_data.store(nanos + 1, AtomicOrdering::Relaxed);
+    }
+
+    let counter = match kind {
+      MessageKind::Bar | MessageKind::UpdatedBar => &self.bars,
+      MessageKind::Quote => &self.quotes,
+      MessageKind::Trade => &self.trades,
+      MessageKind::DailyBar => &self.daily_bars,
+      MessageKind::Luld => &self.lulds,
+      MessageKind::Success => &self.successes,
+      MessageKind::Error => &self.errors,
+      MessageKind::OtherData | MessageKind::Subscription | MessageKind::Unknown => return,
+    };
+    let _prev = counter.fetch_add(1, AtomicOrdering::Relaxed);
+  }
+
+  /// Retrieve a snapshot of the current counts.
+  fn stats(&self) -> StreamStats {
+    StreamStats {
+      bars: self.bars.load(AtomicOrdering::Relaxed),
+      quotes: self.quotes.load(AtomicOrdering::Relaxed),
+      trades: self.trades.load(AtomicOrdering::Relaxed),
+      daily_bars: self.daily_bars.load(AtomicOrdering::Relaxed),
+      lulds: self.lulds.load(AtomicOrdering::Relaxed),
+      successes: self.successes.load(AtomicOrdering::Relaxed),
+      errors: self.errors.load(AtomicOrdering::Relaxed),
+    }
+  }
+
+  /// Retrieve the time at which the most recent market data message
+  /// was received.
+  fn last_data(&self) -> Option<Instant> {
+    match self.last_data.load(AtomicOrdering::Relaxed) {
+      0 => None,
+      nanos => Some(self.base + Duration::from_nanos(nanos - 1)),
+    }
+  }
+}
+
+
+/// State shared between a [`Counted`] stream and the associated
+/// [`Subscription`].
+#[derive(Debug, Default)]
+struct Shared<C> {
+  /// Counters of the messages received.
+  counters: Counters,
+  /// The number of senders in `control`, allowing for skipping the
+  /// lock when there are none.
+  subscribers: AtomicUsize,
+  /// The senders of all [`ControlStream`] objects created.
+  control: StdMutex<Vec<UnboundedSender<ControlMessage<C>>>>,
+}
+
+impl<C> Shared<C>
+where
+  C: Clone,
+{
+  /// Publish the provided message to all control streams, if it is a
+  /// control message.
+  fn publish<M>(&self, message: &ParsedMessage<M>)
+  where
+    M: StreamMessage<Channels = C>,
+  {
+    if let MessageResult::Ok(Ok(message)) = message {
+      if message.kind().is_data() || self.subscribers.load(AtomicOrdering::Relaxed) == 0 {
+        return
+      }
+
+      if let Some(control) = message.to_control() {
+        let mut senders = self.control.lock().unwrap_or_else(|err| err.into_inner());
+        // Get rid of senders whose streams have been dropped.
+        let () = senders.retain(|sender| sender.unbounded_send(control.clone()).is_ok());
+        let () = self
+          .subscribers
+          .store(senders.len(), AtomicOrdering::Relaxed);
+      }
+    }
+  }
+
+  /// Create a new stream of control messages.
+  fn control_stream(&self) -> ControlStream<C> {
+    let (sender, receiver) = unbounded();
+    let mut senders = self.control.lock().unwrap_or_else(|err| err.into_inner());
+    let () = senders.push(sender);
+    let () = self
+      .subscribers
+      .store(senders.len(), AtomicOrdering::Relaxed);
+    ControlStream(receiver)
+  }
+
+  /// Terminate all control streams.
+  fn close_control(&self) {
+    let mut senders = self.control.lock().unwrap_or_else(|err| err.into_inner());
+    let () = senders.clear();
+    let () = self.subscribers.store(0, AtomicOrdering::Relaxed);
+  }
+}
+
+
+/// A stream of the control messages received over a realtime data
+/// stream, as created by [`Subscription::control_stream`].
+///
+/// The stream ends once the associated realtime data stream ended.
+#[derive(Debug)]
+#[must_use = "streams do nothing unless polled"]
+pub struct ControlStream<C = MarketData>(UnboundedReceiver<ControlMessage<C>>);
+
+impl<C> FuturesStream for ControlStream<C> {
+  type Item = ControlMessage<C>;
+
+  #[inline]
+  fn poll_next(mut self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+    self.0.poll_next_unpin(ctx)
+  }
+}
+
+impl<C> FusedStream for ControlStream<C> {
+  #[inline]
+  fn is_terminated(&self) -> bool {
+    self.0.is_terminated()
+  }
+}
+
+
+/// A summary of the health of a realtime data stream, as reported by
+/// [`Subscription::health`].
+///
+/// It is meant to help distinguish between not receiving any market
+/// data because nothing is subscribed to and because there simply
+/// has not been any activity for the subscribed symbols.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub struct StreamHealth<N = SubscriptionCounts> {
+  /// Whether the connection has been authenticated successfully.
+  pub authenticated: bool,
+  /// Whether any market data is subscribed to.
+  pub subscribed: bool,
+  /// The number of symbols subscribed to per kind of market data.
+  pub subscribed_symbols: N,
+  /// The time at which market data was last received, if any.
+  pub last_data_at: Option<Instant>,
+}
+
+
+/// The ways in which the delivery of market data can be paused.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum PauseMode {
+  /// Discard all market data received while paused.
+  Drop,
+  /// Hold back market data received while paused and deliver it once
+  /// resumed.
+  ///
+  /// Note that buffered data is kept in memory, without bound unless
+  /// [`StreamConfig::buffer_capacity`] is set.
+  Buffer,
+}
+
+
+/// The delivery state shared between a [`Counted`] stream and the
+/// associated [`Subscription`].
+#[derive(Debug, Default)]
+struct Pause {
+  /// The current state, one of `RUNNING`, `DROPPING`, or `BUFFERING`.
+  state: AtomicU8,
+  /// The waker of the task last polling the stream, to be woken when
+  /// delivery is resumed.
+  waker: AtomicWaker,
+}
+
+impl Pause {
+  const RUNNING: u8 = 0;
+  const DROPPING: u8 = 1;
+  const BUFFERING: u8 = 2;
+
+  /// Pause delivery of market data using the provided mode.
+  fn pause(&self, mode: PauseMode) {
+    let state = match mode {
+      PauseMode::Drop => Self::DROPPING,
+      PauseMode::Buffer => Self::BUFFERING,
+    };
+    let () = self.state.store(state, AtomicOrdering::SeqCst);
+  }
+
+  /// Resume delivery of market data.
+  fn resume(&self) {
+    let () = self.state.store(Self::RUNNING, AtomicOrdering::SeqCst);
+    let () = self.waker.wake();
+  }
+
+  /// Retrieve the current state.
+  fn state(&self) -> u8 {
+    self.state.load(AtomicOrdering::SeqCst)
+  }
+}
+
+
+/// A wrapper around a stream of parsed messages that counts the
+/// messages passing through it and holds back market data while
+/// delivery is paused.
+#[derive(Debug)]
+#[doc(hidden)]
+#[must_use = "streams do nothing unless polled"]
+pub struct Counted<S, M, C> {
+  /// The wrapped stream & sink.
+  inner: S,
+  /// Whether the wrapped stream has ended.
+  done: bool,
+  /// The state shared with the associated subscription.
+  shared: Arc<Shared<C>>,
+  /// The shared pause state.
+  pause: Arc<Pause>,
+  /// The interner used for symbols deserialized from the wrapped
+  /// stream.
+  interner: Interner,
+  /// Market data held back while paused.
+  queue: VecDeque<M>,
+  /// The maximum number of messages to hold back, if any.
+  capacity: Option<usize>,
+}
+
+impl<S, M, C> Counted<S, M, C> {
+  /// Create a new `Counted` object wrapping the provided stream.
+  fn new(
+    inner: S,
+    shared: Arc<Shared<C>>,
+    pause: Arc<Pause>,
+    interner: Interner,
+    capacity: Option<usize>,
+  ) -> Self {
+    Self {
+      inner,
+      done: false,
+      shared,
+      pause,
+      interner,
+      queue: VecDeque::new(),
+      capacity,
+    }
+  }
+}
+
+impl<S, M> FuturesStream for Counted<S, ParsedMessage<M>, M::Channels>
+where
+  S: FuturesStream<Item = ParsedMessage<M>> + Unpin,
+  M: StreamMessage + Unpin,
+{
+  type Item = S::Item;
+
+  fn poll_next(mut self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+    let this = &mut *self;
+
+    loop {
+      let state = this.pause.state();
+      if state == Pause::RUNNING || this.done {
+        if let Some(message) = this.queue.pop_front() {
+          return Poll::Ready(Some(message))
+        }
+      }
+
+      if this.done {
+        return Poll::Ready(None)
+      }
+
+      let inner = &mut this.inner;
+      match this.interner.enter(|| inner.poll_next_unpin(ctx)) {
+        Poll::Ready(Some(message)) => {
+          let () = this.shared.counters.count(&message);
+          let () = this.shared.publish(&message);
+
+          let is_data = matches!(
+            &message,
+            MessageResult::Ok(Ok(message)) if message.kind().is_data()
+          );
+          match state {
+            Pause::DROPPING if is_data => continue,
+            Pause::BUFFERING if is_data => {
+              if this.capacity == Some(0) {
+                continue
+              }
+              if Some(this.queue.len()) == this.capacity {
+                let _message = this.queue.pop_front();
+              }
+              let () = this.queue.push_back(message);
+              continue
+            },
+            _ => return Poll::Ready(Some(message)),
+          }
+        },
+        Poll::Ready(None) => {
+          let () = this.shared.close_control();
+          // Deliver whatever we may have buffered before signaling the
+          // end of the stream.
+          this.done = true;
+        },
+        Poll::Pending => {
+          let () = this.pause.waker.register(ctx.waker());
+          // Delivery may have been resumed concurrently, in which case
+          // we may have buffered data to deliver.
+          if this.pause.state() == Pause::RUNNING && !this.queue.is_empty() {
+            continue
+          }
+          return Poll::Pending
+        },
+      }
+    }
+  }
+}
+
+impl<S, M, C, U> Sink<U> for Counted<S, M, C>
+where
+  S: Sink<U> + Unpin,
+  M: Unpin,
+{
+  type Error = S::Error;
+
+  fn poll_ready(mut self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+    self.inner.poll_ready_unpin(ctx)
+  }
+
+  fn start_send(mut self: Pin<&mut Self>, message: U) -> Result<(), Self::Error> {
+    self.inner.start_send_unpin(message)
+  }
+
+  fn poll_flush(mut self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+    self.inner.poll_flush_unpin(ctx)
+  }
+
+  fn poll_close(mut self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+    self.inner.poll_close_unpin(ctx)
+  }
+}
+
+
+/// A close frame, as sent by the server when closing the connection.
+///
+/// The code allows for distinguishing, say, a server going away (1001)
+/// from a policy violation (1008), such as the use of invalid
+/// credentials.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CloseFrame {
+  /// The status code indicating the reason for closing.
+  pub code: u16,
+  /// A human readable description of the reason for closing.
+  pub reason: String,
+}
+
+impl From<&WsCloseFrame<'_>> for CloseFrame {
+  fn from(frame: &WsCloseFrame<'_>) -> Self {
+    Self {
+      code: u16::from(frame.code),
+      reason: frame.reason.to_string(),
+    }
+  }
+}
+
+
+/// Retrieve the close frame recorded for a connection, if any.
+fn close_frame(connection: &Arc<ConnectionState>) -> Option<CloseFrame> {
+  connection.close_frame().as_ref().map(CloseFrame::from)
+}
+
+
+/// Deserialize a normalized list of symbols from a string.
+#[inline]
+fn normalized_from_str<'de, D>(deserializer: D) -> Result<Cow<'static, [Symbol]>, D::Error>
+where
+  D: Deserializer<'de>,
+{
+  Cow::<'static, [Symbol]>::deserialize(deserializer).map(normalize)
+}
+
+
+/// A type representing a normalized list of symbols.
+///
+/// Objects of this type are (de)serialized as flat JSON arrays of
+/// strings.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct SymbolList(#[serde(deserialize_with = "normalized_from_str")] Cow<'static, [Symbol]>);
+
+impl Deref for SymbolList {
+  type Target = [Symbol];
+
+  fn deref(&self) -> &Self::Target {
+    self.0.borrow()
+  }
+}
+
+impl SymbolList {
+  /// Create a `SymbolList` from symbols in arbitrary casing.
+  ///
+  /// All symbols are converted to upper case before normalization, so
+  /// that, for example, `aapl` and `AAPL` end up as a single `AAPL`
+  /// entry.
+  pub fn from_case_insensitive<I, S>(symbols: I) -> Self
+  where
+    I: IntoIterator<Item = S>,
+    S: Into<Symbol>,
+  {
+    let symbols = symbols
+      .into_iter()
+      .map(|symbol| {
+        let symbol = symbol.into();
+        if symbol.bytes().any(|b| b.is_ascii_lowercase()) {
+          Symbol::from(symbol.to_ascii_uppercase())
+        } else {
+          symbol
+        }
+      })
+      .collect::<Vec<_>>();
+
+    Self::from(symbols)
+  }
+
+  /// Create a `SymbolList` from a string of comma separated symbols,
+  /// such as `"SPY, QQQ,DIA"`.
+  ///
+  /// Surrounding whitespace is removed from each symbol and empty
+  /// entries are ignored, making this constructor suitable for parsing
+  /// watch lists provided via environment variables or command line
+  /// arguments.
+  pub fn from_csv_str(symbols: &str) -> Self {
+    let symbols = symbols
+      .split(',')
+      .map(str::trim)
+      .filter(|symbol| !symbol.is_empty())
+      .map(|symbol| Symbol::from(symbol.to_string()))
+      .collect::<Vec<_>>();
+
+    Self::from(symbols)
+  }
+
+  /// Create a `SymbolList` from a watch list read from `reader`.
+  ///
+  /// Symbols may be separated by commas, whitespace, or newlines. Empty
+  /// lines are skipped and everything following a `#` on a line is
+  /// treated as a comment.
+  pub fn from_reader<R>(reader: R) -> IoResult<Self>
+  where
+    R: Read,
+  {
+    let mut symbols = Vec::new();
+    for line in BufReader::new(reader).lines() {
+      let line = line?;
+      let line = match line.split_once('#') {
+        Some((line, _comment)) => line,
+        None => &line,
+      };
+
+      let () = symbols.extend(
+        line
+          .split(|c: char| c == ',' || c.is_whitespace())
+          .filter(|symbol| !symbol.is_empty())
+          .map(|symbol| Symbol::from(symbol.to_string())),
+      );
+    }
+
+    Ok(Self::from(symbols))
+  }
+}
+
+impl From<Cow<'static, [Symbol]>> for SymbolList {
+  #[inline]
+  fn from(symbols: Cow<'static, [Symbol]>) -> Self {
+    Self(normalize(symbols))
+  }
+}
+
+impl From<Vec<Symbol>> for SymbolList {
+  #[inline]
+  fn from(symbols: Vec<Symbol>) -> Self {
+    Self(Cow::Owned(normalize_vec(symbols)))
+  }
+}
+
+impl PartialEq<[Symbol]> for SymbolList {
+  #[inline]
+  fn eq(&self, other: &[Symbol]) -> bool {
+    self.deref() == other
+  }
+}
+
+impl PartialEq<Vec<Symbol>> for SymbolList {
+  #[inline]
+  fn eq(&self, other: &Vec<Symbol>) -> bool {
+    self.deref() == other.as_slice()
+  }
+}
+
+impl PartialEq<[&str]> for SymbolList {
+  #[inline]
+  fn eq(&self, other: &[&str]) -> bool {
+    self.len() == other.len() && self.iter().zip(other).all(|(lhs, rhs)| lhs == rhs)
+  }
+}
+
+impl<const N: usize> PartialEq<[&str; N]> for SymbolList {
+  #[inline]
+  fn eq(&self, other: &[&str; N]) -> bool {
+    self == other.as_slice()
+  }
+}
+
+impl From<&[Symbol]> for SymbolList {
+  #[inline]
+  fn from(symbols: &[Symbol]) -> Self {
+    Self::from(symbols.to_vec())
+  }
+}
+
+impl<S> FromIterator<S> for SymbolList
+where
+  S: Into<Symbol>,
+{
+  /// Create a normalized `SymbolList` from an iterator of symbols.
+  ///
+  /// Symbols are normalized once, after all of them have been
+  /// collected.
+  #[inline]
+  fn from_iter<I>(symbols: I) -> Self
+  where
+    I: IntoIterator<Item = S>,
+  {
+    Self::from(symbols.into_iter().map(S::into).collect::<Vec<_>>())
+  }
+}
+
+impl From<Vec<String>> for SymbolList {
+  #[inline]
+  fn from(symbols: Vec<String>) -> Self {
+    Self::from(
+      IntoIterator::into_iter(symbols)
+        .map(Symbol::from)
+        .collect::<Vec<_>>(),
+    )
+  }
+}
+
+impl<const N: usize> From<[&'static str; N]> for SymbolList {
+  #[inline]
+  fn from(symbols: [&'static str; N]) -> Self {
+    Self::from(
+      IntoIterator::into_iter(symbols)
+        .map(Symbol::from)
+        .collect::<Vec<_>>(),
+    )
+  }
+}
+
+
+mod symbols_all {
+  use super::*;
+
+  /// Serialize the [`Symbols::All`] variant.
+  pub(crate) fn serialize<S>(serializer: S) -> Result<S::Ok, S::Error>
+  where
+    S: Serializer,
+  {
+    let mut seq = serializer.serialize_seq(Some(1))?;
+    seq.serialize_element("*")?;
+    seq.end()
+  }
+}
+
+
+/// The number of symbols a [`Symbols`] object represents.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Count {
+  /// All symbols are represented.
+  All,
+  /// The given number of individual symbols is represented.
+  List(usize),
+}
+
+impl Display for Count {
+  fn fmt(&self, fmt: &mut Formatter<'_>) -> FmtResult {
+    match self {
+      Self::All => fmt.write_str("all"),
+      Self::List(count) => write!(fmt, "{count}"),
+    }
+  }
+}
+
+
+/// The number of symbols per kind of market data, as reported by
+/// [`Channels::counts`] for [`MarketData`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub struct SubscriptionCounts {
+  /// The number of aggregate bar symbols.
+  pub bars: Count,
+  /// The number of quote symbols.
+  pub quotes: Count,
+  /// The number of trade symbols.
+  pub trades: Count,
+  /// The number of daily aggregate bar symbols.
+  pub daily_bars: Count,
+  /// The number of limit up/limit down symbols.
+  pub lulds: Count,
+}
+
+
+/// An enumeration of symbols to subscribe to.
+///
+/// Objects of this type are (de)serialized as flat JSON arrays of
+/// strings, with [`Symbols::All`] being represented as `["*"]`.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum Symbols {
+  /// A variant representing all symbols.
+  #[serde(serialize_with = "symbols_all::serialize")]
+  All,
+  /// A list of symbols to work with.
+  List(SymbolList),
+}
+
+impl<'de> Deserialize<'de> for Symbols {
+  /// Deserialize a list of symbols.
+  ///
+  /// A list containing the wildcard `*` represents all symbols,
+  /// regardless of what other symbols it may contain.
+  fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+  where
+    D: Deserializer<'de>,
+  {
+    Vec::<Symbol>::deserialize(deserializer).map(Self::from_iter)
+  }
+}
+
+impl Symbols {
+  /// Check whether the object represents no symbols.
+  #[inline]
+  pub fn is_empty(&self) -> bool {
+    match self {
+      Self::List(list) => list.is_empty(),
+      Self::All => false,
+    }
+  }
+
+  /// Retrieve the number of symbols represented by this object.
+  ///
+  /// Contrary to the length of a list containing the wildcard symbol,
+  /// the count clearly distinguishes between a subscription to all
+  /// symbols and one to individual symbols.
+  #[inline]
+  pub fn count(&self) -> Count {
+    match self {
+      Self::All => Count::All,
+      Self::List(list) => Count::List(list.len()),
+    }
+  }
+
+  /// Retrieve the list of individual symbols, or `None` if `self`
+  /// represents all symbols.
+  #[inline]
+  pub fn as_list(&self) -> Option<&SymbolList> {
+    match self {
+      Self::All => None,
+      Self::List(list) => Some(list),
+    }
+  }
+
+  /// Check whether the provided symbol is covered, either by being
+  /// contained in the list of symbols or because `self` represents all
+  /// symbols.
+  ///
+  /// The symbol may be provided as a plain string or as a [`Symbol`].
+  #[inline]
+  pub fn contains<S>(&self, symbol: S) -> bool
+  where
+    S: AsRef<str>,
+  {
+    let symbol = symbol.as_ref();
+    match self {
+      Self::All => true,
+      Self::List(list) => list
+        .binary_search_by(|other| other.as_ref().cmp(symbol))
+        .is_ok(),
+    }
+  }
+
+  /// Check whether the provided symbol is covered, akin to
+  /// [`contains`][Self::contains], but ignoring ASCII case differences.
+  fn contains_ignore_case<S>(&self, symbol: S) -> bool
+  where
+    S: AsRef<str>,
+  {
+    let symbol = symbol.as_ref();
+    self.contains(symbol)
+      || matches!(
+        self,
+        Self::List(list) if list.iter().any(|other| other.eq_ignore_ascii_case(symbol))
+      )
+  }
+
+  /// Compute the symbols contained in `self` but not in `other`.
+  ///
+  /// The `All` variant is treated as a distinct entity, not as a
+  /// superset of any list of symbols, mirroring how the server keeps
+  /// track of subscriptions.
+  fn difference(&self, other: &Self) -> Self {
+    match (self, other) {
+      (Self::All, Self::All) => Self::default(),
+      (Self::All, Self::List(..)) => Self::All,
+      (Self::List(list), Self::All) => Self::List(list.clone()),
+      (Self::List(list), Self::List(other)) => {
+        let symbols = list
+          .iter()
+          .filter(|symbol| other.binary_search(*symbol).is_err())
+          .cloned()
+          .collect::<Vec<_>>();
+        Self::List(SymbolList::from(symbols))
+      },
+    }
+  }
+
+  /// Compute the union of the symbols in `self` and `other`.
+  ///
+  /// If either side represents all symbols, so does the result.
+  fn union(&self, other: &Self) -> Self {
+    match (self, other) {
+      (Self::All, _) | (_, Self::All) => Self::All,
+      (Self::List(list), Self::List(other)) => {
+        let symbols = list.iter().chain(other.iter()).cloned().collect::<Vec<_>>();
+        Self::List(SymbolList::from(symbols))
+      },
+    }
+  }
+}
+
+/// Comparisons against plain lists of symbols, which never compare
+/// equal to the `All` variant.
+impl PartialEq<[Symbol]> for Symbols {
+  #[inline]
+  fn eq(&self, other: &[Symbol]) -> bool {
+    matches!(self, Self::List(list) if list == other)
+  }
+}
+
+impl PartialEq<Vec<Symbol>> for Symbols {
+  #[inline]
+  fn eq(&self, other: &Vec<Symbol>) -> bool {
+    matches!(self, Self::List(list) if list == other)
+  }
+}
+
+impl PartialEq<[&str]> for Symbols {
+  #[inline]
+  fn eq(&self, other: &[&str]) -> bool {
+    matches!(self, Self::List(list) if list == other)
+  }
+}
+
+impl<const N: usize> PartialEq<[&str; N]> for Symbols {
+  #[inline]
+  fn eq(&self, other: &[&str; N]) -> bool {
+    matches!(self, Self::List(list) if list == other)
+  }
+}
+
+impl<S> FromIterator<S> for Symbols
+where
+  S: Into<Symbol>,
+{
+  /// Create a `Symbols` object from an iterator of symbols.
+  ///
+  /// If any of the symbols is the wildcard `*`, the result represents
+  /// all symbols. Otherwise it is a normalized list of the provided
+  /// symbols.
+  fn from_iter<I>(symbols: I) -> Self
+  where
+    I: IntoIterator<Item = S>,
+  {
+    let symbols = symbols.into_iter().map(S::into).collect::<Vec<Symbol>>();
+    if symbols.iter().any(|symbol| symbol == "*") {
+      Self::All
+    } else {
+      Self::List(SymbolList::from(symbols))
+    }
+  }
+}
+
+impl From<SymbolList> for Symbols {
+  /// Create a `Symbols` object from a list of symbols.
+  ///
+  /// If the list contains the wildcard `*`, the result represents all
+  /// symbols.
+  #[inline]
+  fn from(list: SymbolList) -> Self {
+    if list.iter().any(|symbol| symbol == "*") {
+      Self::All
+    } else {
+      Self::List(list)
+    }
+  }
+}
+
+impl Default for Symbols {
+  fn default() -> Self {
+    Self::List(SymbolList::from([]))
+  }
+}
+
+
+/// A set of channels that can be subscribed to over a realtime data
+/// stream, each holding the symbols to subscribe to.
+///
+/// [`MarketData`] is the set of channels offered by the stock data
+/// stream. Other streams, such as the one for crypto currencies, come
+/// with their own set.
+pub trait Channels:
+  Clone + Debug + Default + Eq + Send + Sync + Unpin + Serialize + DeserializeOwned + private::Sealed
+{
+  /// The number of symbols per channel, as reported by
+  /// [`counts`][Channels::counts].
+  type Counts: Clone + Copy + Debug + Eq;
+
+  /// The human readable names of the channels, in the order reported
+  /// by [`fields`][Channels::fields].
+  #[doc(hidden)]
+  const NAMES: &'static [&'static str];
+
+  /// Retrieve references to all fields.
+  #[doc(hidden)]
+  fn fields(&self) -> Vec<&Symbols>;
+
+  /// Retrieve mutable references to all fields, in the same order as
+  /// reported by [`fields`][Channels::fields].
+  #[doc(hidden)]
+  fn fields_mut(&mut self) -> Vec<&mut Symbols>;
+
+  /// Retrieve the number of symbols for each channel.
+  fn counts(&self) -> Self::Counts;
+
+  /// Check whether this object contains no market data at all.
+  #[inline]
+  fn is_empty(&self) -> bool {
+    self.fields().into_iter().all(Symbols::is_empty)
+  }
+
+  /// Compute the market data to subscribe to and to unsubscribe from,
+  /// respectively, in order to get from `other` to `self`.
+  ///
+  /// A common use case is to pass in the currently active
+  /// [`subscriptions`][Subscription::subscriptions], with `self` being
+  /// the desired target. [`Symbols::All`] is treated as a distinct
+  /// entity in this computation, i.e., moving from a list of symbols to
+  /// `All` results in the list being unsubscribed from, and vice versa.
+  fn diff(&self, other: &Self) -> (Self, Self) {
+    let mut subscribe = Self::default();
+    let mut unsubscribe = Self::default();
+    let fields = subscribe
+      .fields_mut()
+      .into_iter()
+      .zip(unsubscribe.fields_mut())
+      .zip(self.fields().into_iter().zip(other.fields()));
+
+    for ((subscribe, unsubscribe), (symbols, other)) in fields {
+      *subscribe = symbols.difference(other);
+      *unsubscribe = other.difference(symbols);
+    }
+    (subscribe, unsubscribe)
+  }
+
+  /// Remove symbols from `self` for which `other` contains a
+  /// subscription to all symbols.
+  #[doc(hidden)]
+  fn strip_covered(&mut self, other: &Self) {
+    for (symbols, other) in self.fields_mut().into_iter().zip(other.fields()) {
+      if let Symbols::All = other {
+        *symbols = Symbols::default();
+      }
+    }
+  }
+
+  /// Check whether any of the fields contains a subscription to all
+  /// symbols.
+  #[doc(hidden)]
+  fn has_wildcard(&self) -> bool {
+    self
+      .fields()
+      .into_iter()
+      .any(|symbols| matches!(symbols, Symbols::All))
+  }
+
+  /// Merge the market data of `other` into `self`.
+  ///
+  /// For each of the fields, the result is the union of the symbols
+  /// contained in `self` and `other`, collapsing to
+  /// [`Symbols::All`] if either side contains said variant.
+  fn merge(&mut self, other: &Self) {
+    for (symbols, other) in self.fields_mut().into_iter().zip(other.fields()) {
+      *symbols = symbols.union(other);
+    }
+  }
+
+  /// Split `self` into objects containing at most `size` symbols each.
+  ///
+  /// A subscription to all symbols counts as a single symbol.
+  #[doc(hidden)]
+  fn chunks(&self, size: usize) -> Vec<Self> {
+    debug_assert!(size > 0);
+
+    let mut chunks = Vec::new();
+    let mut chunk = Self::default();
+    let mut count = 0;
+
+    for (index, symbols) in self.fields().into_iter().enumerate() {
+      // `None` represents a subscription to all symbols.
+      let items = match symbols {
+        Symbols::All => vec![None],
+        Symbols::List(list) => list.iter().map(Some).collect(),
+      };
+
+      for item in items {
+        if count == size {
+          let () = chunks.push(std::mem::take(&mut chunk));
+          count = 0;
+        }
+
+        match (item, field_mut(&mut chunk, index)) {
+          (None, field) => *field = Symbols::All,
+          (Some(symbol), Symbols::List(list)) => list.0.to_mut().push(symbol.clone()),
+          (Some(..), Symbols::All) => unreachable!(),
+        }
+        count += 1;
+      }
+    }
+
+    if !chunk.is_empty() {
+      let () = chunks.push(chunk);
+    }
+    chunks
+  }
+}
+
+
+/// A type defining the market data a client intends to subscribe to.
+///
+/// Objects of this type can be serialized and deserialized, e.g., for
+/// persisting a desired set of subscriptions. Lists of symbols are
+/// normalized as part of deserialization, meaning that duplicate and
+/// out-of-order entries are handled gracefully.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct MarketData {
+  /// The aggregate bars to subscribe to.
+  #[serde(default, skip_serializing_if = "Symbols::is_empty")]
+  pub bars: Symbols,
+  /// The quotes to subscribe to.
+  #[serde(default, skip_serializing_if = "Symbols::is_empty")]
+  pub quotes: Symbols,
+  /// The trades to subscribe to.
+  #[serde(default, skip_serializing_if = "Symbols::is_empty")]
+  pub trades: Symbols,
+  /// The daily aggregate bars to subscribe to.
+  ///
+  /// Daily bars are reported separately from the minute bars
+  /// subscribed to via [`bars`][MarketData::bars], and they arrive as
+  /// [`Data::Bar`] of kind [`BarKind::Daily`].
+  #[serde(
+    default,
+    rename = "dailyBars",
+    skip_serializing_if = "Symbols::is_empty"
+  )]
+  pub daily_bars: Symbols,
+  /// The limit up/limit down price bands to subscribe to.
+  #[serde(default, skip_serializing_if = "Symbols::is_empty")]
+  pub lulds: Symbols,
+}
+
+impl MarketData {
+  /// Create a [`MarketDataBuilder`] for conveniently constructing a
+  /// `MarketData` object.
+  #[inline]
+  pub fn builder() -> MarketDataBuilder {
+    MarketDataBuilder::default()
+  }
+
+  /// Create a `MarketData` object subscribing to aggregate bars for
+  /// all symbols.
+  #[inline]
+  pub fn all_bars() -> Self {
+    Self {
+      bars: Symbols::All,
+      ..Default::default()
+    }
+  }
+
+  /// Create a `MarketData` object subscribing to quotes for all
+  /// symbols.
+  #[inline]
+  pub fn all_quotes() -> Self {
+    Self {
+      quotes: Symbols::All,
+      ..Default::default()
+    }
+  }
+
+  /// Create a `MarketData` object subscribing to trades for all
+  /// symbols.
+  #[inline]
+  pub fn all_trades() -> Self {
+    Self {
+      trades: Symbols::All,
+      ..Default::default()
+    }
+  }
+
+  /// Create a `MarketData` object subscribing to aggregate bars for the
+  /// provided symbols.
+  #[inline]
+  pub fn with_bars<S>(symbols: S) -> Self
+  where
+    S: Into<SymbolList>,
+  {
+    Self {
+      bars: Symbols::from(symbols.into()),
+      ..Default::default()
+    }
+  }
+
+  /// Create a `MarketData` object subscribing to quotes for the
+  /// provided symbols.
+  #[inline]
+  pub fn with_quotes<S>(symbols: S) -> Self
+  where
+    S: Into<SymbolList>,
+  {
+    Self {
+      quotes: Symbols::from(symbols.into()),
+      ..Default::default()
+    }
+  }
+
+  /// Create a `MarketData` object subscribing to trades for the
+  /// provided symbols.
+  #[inline]
+  pub fn with_trades<S>(symbols: S) -> Self
+  where
+    S: Into<SymbolList>,
+  {
+    Self {
+      trades: Symbols::from(symbols.into()),
+      ..Default::default()
+    }
+  }
+
+  /// A convenience function for setting the [`bars`][MarketData::bars]
+  /// member.
+  #[inline]
+  pub fn set_bars<S>(&mut self, symbols: S)
+  where
+    S: Into<SymbolList>,
+  {
+    self.bars = Symbols::from(symbols.into());
+  }
+
+  /// A convenience function for setting the [`quotes`][MarketData::quotes]
+  /// member.
+  #[inline]
+  pub fn set_quotes<S>(&mut self, symbols: S)
+  where
+    S: Into<SymbolList>,
+  {
+    self.quotes = Symbols::from(symbols.into());
+  }
+
+  /// A convenience function for setting the [`trades`][MarketData::trades]
+  /// member.
+  #[inline]
+  pub fn set_trades<S>(&mut self, symbols: S)
+  where
+    S: Into<SymbolList>,
+  {
+    self.trades = Symbols::from(symbols.into());
+  }
+
+  /// A convenience function for setting the
+  /// [`daily_bars`][MarketData::daily_bars] member.
+  #[inline]
+  pub fn set_daily_bars<S>(&mut self, symbols: S)
+  where
+    S: Into<SymbolList>,
+  {
+    self.daily_bars = Symbols::from(symbols.into());
+  }
+
+  /// A convenience function for setting the [`lulds`][MarketData::lulds]
+  /// member.
+  #[inline]
+  pub fn set_lulds<S>(&mut self, symbols: S)
+  where
+    S: Into<SymbolList>,
+  {
+    self.lulds = Symbols::from(symbols.into());
+  }
+}
+
+impl Channels for MarketData {
+  type Counts = SubscriptionCounts;
+
+  const NAMES: &'static [&'static str] = &["bars", "quotes", "trades", "daily bars", "lulds"];
+
+  fn fields(&self) -> Vec<&Symbols> {
+    vec![
+      &self.bars,
+      &self.quotes,
+      &self.trades,
+      &self.daily_bars,
+      &self.lulds,
+    ]
+  }
+
+  fn fields_mut(&mut self) -> Vec<&mut Symbols> {
+    vec![
+      &mut self.bars,
+      &mut self.quotes,
+      &mut self.trades,
+      &mut self.daily_bars,
+      &mut self.lulds,
+    ]
+  }
+
+  #[inline]
+  fn counts(&self) -> SubscriptionCounts {
+    SubscriptionCounts {
+      bars: self.bars.count(),
+      quotes: self.quotes.count(),
+      trades: self.trades.count(),
+      daily_bars: self.daily_bars.count(),
+      lulds: self.lulds.count(),
+    }
+  }
+}
+
+impl private::Sealed for MarketData {}
+
+
+/// Retrieve the field of a [`Channels`] object with the given index.
+fn field_mut<C>(data: &mut C, index: usize) -> &mut Symbols
+where
+  C: Channels,
+{
+  data.fields_mut().into_iter().nth(index).unwrap()
+}
+
+
+/// A builder for [`MarketData`] objects.
+///
+/// ```
+/// # use apca::data::v2::stream::MarketData;
+/// let data = MarketData::builder()
+///   .bars(["AAPL", "SPY"])
+///   .quotes(["VOO"])
+///   .build();
+/// # assert!(!data.bars.is_empty());
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct MarketDataBuilder {
+  /// The market data being built.
+  data: MarketData,
+}
+
+impl MarketDataBuilder {
+  /// Set the aggregate bars to subscribe to.
+  #[inline]
+  pub fn bars<S>(mut self, symbols: S) -> Self
+  where
+    S: Into<SymbolList>,
+  {
+    self.data.set_bars(symbols);
+    self
+  }
+
+  /// Set the quotes to subscribe to.
+  #[inline]
+  pub fn quotes<S>(mut self, symbols: S) -> Self
+  where
+    S: Into<SymbolList>,
+  {
+    self.data.set_quotes(symbols);
+    self
+  }
+
+  /// Set the trades to subscribe to.
+  #[inline]
+  pub fn trades<S>(mut self, symbols: S) -> Self
+  where
+    S: Into<SymbolList>,
+  {
+    self.data.set_trades(symbols);
+    self
+  }
+
+  /// Set the daily aggregate bars to subscribe to.
+  #[inline]
+  pub fn daily_bars<S>(mut self, symbols: S) -> Self
+  where
+    S: Into<SymbolList>,
+  {
+    self.data.set_daily_bars(symbols);
+    self
+  }
+
+  /// Set the limit up/limit down price bands to subscribe to.
+  #[inline]
+  pub fn lulds<S>(mut self, symbols: S) -> Self
+  where
+    S: Into<SymbolList>,
+  {
+    self.data.set_lulds(symbols);
+    self
+  }
+
+  /// Build the final [`MarketData`] object.
+  #[inline]
+  pub fn build(self) -> MarketData {
+    self.data
+  }
+}
+
+
+/// A control message "request" sent over a websocket channel.
+#[derive(Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[doc(hidden)]
+#[serde(tag = "action")]
+pub enum Request<'d, C = MarketData>
+where
+  C: Clone,
+{
+  /// A control message indicating whether or not we were authenticated
+  /// successfully.
+  #[serde(rename = "auth")]
+  Authenticate {
+    #[serde(rename = "key")]
+    key_id: Cow<'d, str>,
+    #[serde(rename = "secret")]
+    secret: Cow<'d, str>,
+  },
+  /// A control message subscribing the client to receive updates for
+  /// the provided symbols.
+  #[serde(rename = "subscribe")]
+  Subscribe(Cow<'d, C>),
+  /// A control message unsubscribing the client from receiving updates
+  /// for the provided symbols.
+  #[serde(rename = "unsubscribe")]
+  Unsubscribe(Cow<'d, C>),
+}
+
+
+/// The kind of control message expected in response to a request.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Response {
+  /// A success message is expected.
+  Success,
+  /// A subscription message is expected.
+  Subscription,
+  /// Any control message is accepted as the response.
+  Any,
+}
+
+impl Response {
+  /// Check whether `control` constitutes the expected response.
+  fn matches<C>(self, control: &ControlMessage<C>) -> bool {
+    matches!(
+      (self, control),
+      (Self::Any, _)
+        | (_, ControlMessage::Error(..))
+        | (Self::Success, ControlMessage::Success { .. })
+        | (Self::Subscription, ControlMessage::Subscription(..))
+    )
+  }
+}
+
+
+/// The number of symbols to subscribe to with a single request when
+/// subscribing via [`Subscription::subscribe_with_deadline`].
+const SUBSCRIBE_CHUNK_SIZE: usize = 1000;
+
+
+/// The outcome of a [`Subscription::subscribe_with_deadline`] or
+/// [`SubscriptionPool::subscribe`] operation.
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct SubscribeOutcome<C = MarketData> {
+  /// The market data the server acknowledged subscribing to.
+  pub acknowledged: C,
+  /// The market data for which no subscription was acknowledged,
+  /// either because the deadline was hit before the corresponding
+  /// request completed or because it or a previous request failed.
+  pub pending: C,
+  /// The market data the server omitted from its acknowledgement of
+  /// the requests issued, e.g., because of invalid or restricted
+  /// symbols.
+  pub rejected: C,
+  /// The error that caused the operation to stop prematurely, if any.
+  pub error: Option<Error>,
+}
+
+
+/// A subscription allowing certain control operations pertaining
+/// a real time market data stream.
+///
+/// # Notes
+/// - in order for any [`subscribe`][Subscription::subscribe] or
+///   [`unsubscribe`][Subscription::unsubscribe] operation to resolve,
+///   the associated [`MessageStream`] stream needs to be polled;
+///   consider using the [`drive`] function for that purpose
+/// - if a timeout is configured via
+///   [`set_timeout`][Subscription::set_timeout] and an operation timed
+///   out, the server's response may still arrive at a later point; the
+///   connection should be considered unusable in this case and be
+///   re-established
+/// - operations changing subscriptions take `&mut self` and so at most
+///   one request is in flight at any time, each awaiting the server's
+///   acknowledgement before the next one can be issued; to share a
+///   subscription among multiple tasks, wrap it in a
+///   [`SharedSubscription`]
+/// - should an operation be abandoned before the server responded,
+///   e.g., because its future was dropped or it timed out, any further
+///   operation fails with an error until the outstanding response has
+///   been received over the associated stream
+#[derive(Debug)]
+pub struct Subscription<S, M = DataMessage>
+where
+  M: StreamMessage,
+{
+  /// Our internally used subscription object for sending control
+  /// messages.
+  subscription: subscribe::Subscription<S, ParsedMessage<M>, wrap::Message>,
+  /// The currently active individual market data subscriptions.
+  subscriptions: M::Channels,
+  /// A shareable copy of the active subscriptions.
+  state: SubscriptionState<M::Channels>,
+  /// The maximum amount of time to wait for the server to respond to a
+  /// control message.
+  timeout: Option<Duration>,
+  /// The state shared with the associated stream.
+  shared: Arc<Shared<M::Channels>>,
+  /// The pause state shared with the associated stream.
+  pause: Arc<Pause>,
+  /// The symbol interner shared with the associated stream.
+  interner: Interner,
+  /// The kind of response expected for the control request in flight,
+  /// if any.
+  in_flight: Option<Response>,
+  /// The control messages received over the associated stream, used
+  /// for detecting the arrival of the response to an abandoned
+  /// request.
+  responses: ControlStream<M::Channels>,
+  /// Whether the connection has been authenticated successfully.
+  authenticated: bool,
+  /// The last control message received in response to an operation.
+  last_control: Option<ControlMessage<M::Channels>>,
+  /// The market data omitted by the server from its acknowledgement of
+  /// the most recent subscribe request.
+  rejected: M::Channels,
+  /// The generation of the connection the subscription belongs to.
+  generation: u64,
+  /// The state of the underlying connection.
+  connection: Arc<ConnectionState>,
+}
+
+impl<S, M> Subscription<S, M>
+where
+  M: StreamMessage,
+{
+  /// Create a `Subscription` object wrapping the `websocket_util` based one.
+  #[inline]
+  fn new(
+    subscription: subscribe::Subscription<S, ParsedMessage<M>, wrap::Message>,
+    shared: Arc<Shared<M::Channels>>,
+    pause: Arc<Pause>,
+    interner: Interner,
+  ) -> Self {
+    let responses = shared.control_stream();
+    Self {
+      subscription,
+      subscriptions: M::Channels::default(),
+      state: SubscriptionState::default(),
+      timeout: None,
+      shared,
+      pause,
+      interner,
+      in_flight: None,
+      responses,
+      authenticated: false,
+      last_control: None,
+      rejected: M::Channels::default(),
+      generation: 0,
+      connection: Arc::<ConnectionState>::default(),
+    }
+  }
+
+  /// Remember the control message contained in `response`, if any.
+  fn record_control(&mut self, response: &Option<Result<ControlMessage<M::Channels>, ()>>) {
+    if let Some(Ok(control)) = response {
+      self.last_control = Some(control.clone());
+    }
+  }
+
+  /// Set the maximum amount of time to wait for the server to respond
+  /// to a control message, such as a subscription request.
+  ///
+  /// A value of `None`, which is the default, means to wait
+  /// indefinitely.
+  #[inline]
+  pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+    self.timeout = timeout
+  }
+
+  /// Retrieve statistics about the messages received over the
+  /// associated stream so far.
+  ///
+  /// Note that messages are accounted for when they are read from the
+  /// underlying connection, which only happens while the associated
+  /// stream is being polled.
+  #[inline]
+  pub fn stats(&self) -> StreamStats {
+    self.shared.counters.stats()
+  }
+
+  /// Create a stream of the control messages received from now on.
+  ///
+  /// All control messages are reported, including responses to
+  /// requests issued through this object, unsolicited subscription
+  /// updates, and errors reported by the server. This allows for
+  /// supervising the connection independently of the consumption of
+  /// market data. Note, though, that control messages are only received
+  /// while the associated realtime data stream is being polled.
+  #[inline]
+  pub fn control_stream(&self) -> ControlStream<M::Channels> {
+    self.shared.control_stream()
+  }
+
+  /// Retrieve a summary of the health of the associated stream.
+  ///
+  /// As is the case for [`stats`][Self::stats], the time at which
+  /// market data was last received only advances while the stream is
+  /// being polled.
+  #[inline]
+  pub fn health(&self) -> StreamHealth<<M::Channels as Channels>::Counts> {
+    StreamHealth {
+      authenticated: self.authenticated,
+      subscribed: !self.subscriptions.is_empty(),
+      subscribed_symbols: self.subscriptions.counts(),
+      last_data_at: self.shared.counters.last_data(),
+    }
+  }
+
+  /// Retrieve the generation of the connection the subscription
+  /// belongs to.
+  ///
+  /// A newly established connection is of generation zero. Each
+  /// connection replacing a lost one by means of
+  /// [`RealtimeData::reconnect`] or an [`AutoSubscription`] is of the
+  /// next higher generation. Use [`WithGeneration`] to tag items
+  /// received over a connection with its generation.
+  #[inline]
+  pub fn generation(&self) -> u64 {
+    self.generation
+  }
+
+  /// Retrieve the close frame sent by the server when closing the
+  /// connection, if any.
+  ///
+  /// The close frame is only available once the associated stream has
+  /// read it, i.e., typically after the stream has ended.
+  #[inline]
+  pub fn close_frame(&self) -> Option<CloseFrame> {
+    close_frame(&self.connection)
+  }
+
+  /// Actively probe the liveness of the connection by sending a
+  /// websocket ping and awaiting the corresponding pong.
+  ///
+  /// This check works independently of whether market data is
+  /// currently flowing. [`Error::PongTimeout`] is reported if no pong
+  /// arrives within `timeout`, if provided, and
+  /// [`Error::ConnectionEnded`] if the connection ends in the meantime.
+  /// Note that the ping is sent and the pong received over the
+  /// associated stream, which hence needs to be polled concurrently,
+  /// e.g., by means of [`drive`].
+  pub async fn ping(&mut self, timeout: Option<Duration>) -> Result<(), Error> {
+    let connection = &self.connection;
+    let pongs = connection.request_ping();
+    let pong = poll_fn(|ctx| connection.poll_pong(ctx, pongs));
+
+    match with_timeout(timeout, pong).await {
+      Some(true) => Ok(()),
+      Some(false) => Err(Error::ConnectionEnded),
+      None => Err(Error::PongTimeout),
+    }
+  }
+
+  /// Pause the delivery of market data over the associated stream.
+  ///
+  /// Contrary to unsubscribing, pausing does not involve the server
+  /// and is instant to reverse. Control messages continue to be
+  /// processed and the connection is kept alive while paused, as long
+  /// as the stream is being polled. Market data received in the
+  /// meantime is either dropped or buffered, depending on `mode`.
+  #[inline]
+  pub fn pause(&self, mode: PauseMode) {
+    self.pause.pause(mode)
+  }
+
+  /// Resume the delivery of market data after a previous
+  /// [`pause`][Self::pause].
+  #[inline]
+  pub fn resume(&self) {
+    self.pause.resume()
+  }
+
+  /// Check whether the delivery of market data is currently paused.
+  #[inline]
+  pub fn is_paused(&self) -> bool {
+    self.pause.state() != Pause::RUNNING
+  }
+
+  /// Check whether the connection has been authenticated successfully.
+  ///
+  /// Connections established via
+  /// [`Client::subscribe`][crate::Client::subscribe] are authenticated
+  /// as part of connecting.
+  #[inline]
+  pub fn is_authenticated(&self) -> bool {
+    self.authenticated
+  }
+
+  /// Retrieve the last control message received in response to an
+  /// operation, if any.
+  ///
+  /// This method is meant for diagnostic purposes, e.g., to inspect
+  /// the server's response after an operation failed. Control messages
+  /// arriving while no operation is in progress are not captured.
+  #[inline]
+  pub fn last_control(&self) -> Option<&ControlMessage<M::Channels>> {
+    self.last_control.as_ref()
+  }
+
+  /// Inquire the currently active individual market data subscriptions.
+  #[inline]
+  pub fn subscriptions(&self) -> &M::Channels {
+    &self.subscriptions
+  }
+
+  /// Retrieve the market data requested as part of the most recent
+  /// [`subscribe`][Subscription::subscribe] operation that the server
+  /// omitted from its acknowledgement.
+  ///
+  /// The server does not report symbols it refuses to subscribe to,
+  /// e.g., because they are invalid or restricted, but merely leaves
+  /// them out of its confirmation. This method allows for detecting
+  /// such symbols, for example, to prune them from a watch list.
+  #[inline]
+  pub fn rejected(&self) -> &M::Channels {
+    &self.rejected
+  }
+
+  /// Retrieve an owned copy of the currently active individual market
+  /// data subscriptions.
+  ///
+  /// Contrary to [`subscriptions`][Self::subscriptions], the result is
+  /// not tied to the lifetime of `self`, making it suitable for, say,
+  /// recording the subscription state before a reconnect.
+  #[inline]
+  pub fn snapshot(&self) -> M::Channels {
+    self.subscriptions.clone()
+  }
+
+  /// Compute the market data subscriptions that would be in effect
+  /// after successfully subscribing to `data`, without sending
+  /// anything to the server.
+  ///
+  /// Together with [`Channels::counts`], this method can be used to
+  /// check a subscription against symbol limits before issuing it.
+  /// Note that the server may still reject individual symbols, in
+  /// which case the actual outcome will be a subset of the preview.
+  #[inline]
+  pub fn preview_subscribe(&self, data: &M::Channels) -> M::Channels {
+    let mut preview = self.subscriptions.clone();
+    let () = preview.merge(data);
+    preview
+  }
+
+  /// Retrieve a handle for observing the active market data
+  /// subscriptions.
+  ///
+  /// The handle reflects subsequent changes made through this
+  /// `Subscription`, but does not allow for making any itself.
+  #[inline]
+  pub fn state(&self) -> SubscriptionState<M::Channels> {
+    self.state.clone()
+  }
+
+  /// Serialize the currently active market data subscriptions into
+  /// JSON, in the format used by the Alpaca Stream API.
+  ///
+  /// This method is mostly meant for logging purposes.
+  #[inline]
+  pub fn subscriptions_json(&self) -> Result<String, JsonError> {
+    to_json(&self.subscriptions)
+  }
+}
+
+impl<S, M> Subscription<S, M>
+where
+  M: StreamMessage<Channels = MarketData>,
+{
+  /// Check whether bars for the provided symbol are subscribed to,
+  /// either individually or by means of a subscription to all symbols.
+  ///
+  /// The symbol is compared ignoring ASCII case differences.
+  #[inline]
+  pub fn is_subscribed_bars<Y>(&self, symbol: Y) -> bool
+  where
+    Y: AsRef<str>,
+  {
+    self.subscriptions.bars.contains_ignore_case(symbol)
+  }
+
+  /// Check whether quotes for the provided symbol are subscribed to,
+  /// either individually or by means of a subscription to all symbols.
+  ///
+  /// The symbol is compared ignoring ASCII case differences.
+  #[inline]
+  pub fn is_subscribed_quotes<Y>(&self, symbol: Y) -> bool
+  where
+    Y: AsRef<str>,
+  {
+    self.subscriptions.quotes.contains_ignore_case(symbol)
+  }
+
+  /// Check whether trades for the provided symbol are subscribed to,
+  /// either individually or by means of a subscription to all symbols.
+  ///
+  /// The symbol is compared ignoring ASCII case differences.
+  #[inline]
+  pub fn is_subscribed_trades<Y>(&self, symbol: Y) -> bool
+  where
+    Y: AsRef<str>,
+  {
+    self.subscriptions.trades.contains_ignore_case(symbol)
+  }
+}
+
+impl<S, M> Subscription<S, M>
+where
+  S: Sink<wrap::Message> + Unpin,
+  M: StreamMessage,
+{
+  /// Mark a control request expecting a response of the given kind as
+  /// being in flight.
+  ///
+  /// Requests are only ever issued through `&mut self`, but a request
+  /// whose future got dropped may still be awaiting its response.
+  /// Sending another one in this state would cause the outstanding
+  /// response to be mistaken for the new one's.
+  #[allow(clippy::result_large_err)]
+  fn begin_request(&mut self, response: Response) -> Result<(), Error> {
+    // Check the control messages received since the last request was
+    // issued for the response to it, if it is still outstanding.
+    loop {
+      match self.responses.next().now_or_never() {
+        Some(Some(control)) => {
+          if matches!(self.in_flight, Some(expected) if expected.matches(&control)) {
+            self.in_flight = None;
+          }
+        },
+        // The stream has ended and no response is going to arrive.
+        Some(None) => {
+          self.in_flight = None;
+          break
+        },
+        None => break,
+      }
+    }
+
+    if self.in_flight.is_some() {
+      return Err(Error::Str(
+        "a previous control request is still awaiting a response".into(),
+      ))
+    }
+    self.in_flight = Some(response);
+    Ok(())
+  }
+
+  /// Mark the control request in flight as done, because either its
+  /// response arrived, sending it failed, or we gave up waiting.
+  #[inline]
+  fn end_request(&mut self) {
+    self.in_flight = None;
+  }
+
+  /// Wait for the server to report the connection as established.
+  ///
+  /// The Alpaca Stream API greets every client with a `connected`
+  /// success message, prior to authentication. This method consumes
+  /// this message and reports an error if the first control message
+  /// received is anything else. Note that connections established via
+  /// [`Client::subscribe`][crate::Client::subscribe] have already
+  /// received this message.
+  pub async fn wait_connected(&mut self) -> Result<(), Error> {
+    let () = self.begin_request(Response::Success)?;
+    let message = with_timeout(self.timeout, self.subscription.read()).await;
+    let () = self.end_request();
+    let message = match message {
+      Some(message) => message,
+      None => return Err(Error::Str("connected message timed out".into())),
+    };
+    let () = self.record_control(&message);
+
+    match message {
+      Some(Ok(ControlMessage::Success { message })) if message == "connected" => Ok(()),
+      Some(Ok(_)) => Err(Error::Str(
+        "server responded with unexpected initial message".into(),
+      )),
+      Some(Err(())) => Err(Error::Str("failed to read connected message".into())),
+      None => Err(Error::Str(
+        "stream was closed before connected message was received".into(),
+      )),
+    }
+  }
+
+  /// Authenticate the connection using Alpaca credentials.
+  ///
+  /// Note that connections established via
+  /// [`Client::subscribe`][crate::Client::subscribe] are already
+  /// authenticated. This method is meant for cases where
+  /// (re-)authentication has to happen explicitly, for example as part
+  /// of custom reconnect logic.
+  pub async fn authenticate(
+    &mut self,
+    key_id: &str,
+    secret: &str,
+  ) -> Result<Result<(), Error>, S::Error> {
+    let request: Request<'_, M::Channels> = Request::Authenticate {
+      key_id: key_id.into(),
+      secret: secret.into(),
+    };
+    debug!("sending authentication request");
+    let json = match to_json(&request) {
+      Ok(json) => json,
+      Err(err) => return Ok(Err(Error::Json(err))),
+    };
+    if let Err(err) = self.begin_request(Response::Success) {
+      return Ok(Err(err))
+    }
+    let message = wrap::Message::Text(json);
+    let response = with_timeout(self.timeout, self.subscription.send(message)).await;
+    let () = self.end_request();
+    let response = match response {
+      Some(response) => response?,
+      None => return Ok(Err(Error::Str("authentication timed out".into()))),
+    };
+    let () = self.record_control(&response);
+
+    let result = match response {
+      Some(response) => match response {
+        Ok(ControlMessage::Success { message }) if message == "authenticated" => Ok(Ok(())),
+        Ok(ControlMessage::Success { message }) => Ok(Err(Error::Str(
+          format!("server responded with unexpected success message: {message}").into(),
+        ))),
+        Ok(ControlMessage::Subscription(..)) => Ok(Err(Error::Str(
+          "server responded with unexpected subscription message".into(),
+        ))),
+        Ok(ControlMessage::Error(error)) => Ok(Err(Error::Stream(error.into()))),
+        Ok(ControlMessage::Unknown(..)) => Ok(Err(Error::Str(
+          "server responded with unexpected message".into(),
+        ))),
+        Err(()) => Ok(Err(Error::Str("failed to authenticate with server".into()))),
+      },
+      None => Ok(Err(Error::Str(
+        "stream was closed before authorization message was received".into(),
+      ))),
+    };
+
+    self.authenticated = matches!(result, Ok(Ok(())));
+    result
+  }
+
+  /// Authenticate the connection using the credentials contained in
+  /// the provided [`ApiInfo`].
+  ///
+  /// This method is a convenience wrapper around
+  /// [`authenticate`][Self::authenticate], allowing for credentials to
+  /// be configured in a single place for both the Trading API and
+  /// market data streaming.
+  #[inline]
+  pub async fn authenticate_with(
+    &mut self,
+    api_info: &ApiInfo,
+  ) -> Result<Result<(), Error>, S::Error> {
+    self.authenticate(&api_info.key_id, &api_info.secret).await
+  }
+
+  /// Handle sending of a subscribe or unsubscribe request.
+  ///
+  /// On success, the subscriptions as acknowledged by the server are
+  /// returned.
+  async fn subscribe_unsubscribe(
+    &mut self,
+    request: &Request<'_, M::Channels>,
+  ) -> Result<Result<M::Channels, Error>, S::Error> {
+    if let Request::Subscribe(data) | Request::Unsubscribe(data) = request {
+      debug!(
+        action = if matches!(request, Request::Subscribe(..)) {
+          "subscribe"
+        } else {
+          "unsubscribe"
+        },
+        symbols = %CountsSummary(data.as_ref()),
+        "sending subscription request"
+      );
+    }
+
+    let json = match to_json(request) {
+      Ok(json) => json,
+      Err(err) => return Ok(Err(Error::Json(err))),
+    };
+    if let Err(err) = self.begin_request(Response::Subscription) {
+      return Ok(Err(err))
+    }
+    let message = wrap::Message::Text(json);
+    let response = with_timeout(self.timeout, self.subscription.send(message)).await;
+    let () = self.end_request();
+    let response = match response {
+      Some(response) => response?,
+      None => return Ok(Err(Error::Str("subscription request timed out".into()))),
+    };
+    let () = self.record_control(&response);
+
+    match response {
+      Some(response) => match response {
+        Ok(ControlMessage::Subscription(data)) => {
+          self.subscriptions = data.clone();
+          let () = self.state.set(&data);
+          let () = self.interner.update(&data);
+          Ok(Ok(data))
+        },
+        Ok(ControlMessage::Error(error)) => {
+          let error = StreamError::from(error);
+          match (request, &error) {
+            // The server does not tell us which symbols it took issue
+            // with, but a request containing a wildcard rejected due
+            // to an insufficient subscription is most likely the
+            // result of the account's data plan not permitting it.
+            (Request::Subscribe(data), StreamError::InvalidSubscription(api_error))
+              if api_error.code == 409 && data.has_wildcard() =>
+            {
+              Ok(Err(Error::WildcardNotPermitted(error)))
+            },
+            _ => Ok(Err(Error::Stream(error))),
+          }
+        },
+        Ok(_) => Ok(Err(Error::Str(
+          "server responded with unexpected message".into(),
+        ))),
+        Err(()) => Ok(Err(Error::Str("failed to adjust subscription".into()))),
+      },
+      None => Ok(Err(Error::Str(
+        "stream was closed before subscription confirmation message was received".into(),
+      ))),
+    }
+  }
+
+  /// Subscribe to the provided market data.
+  ///
+  /// Contained in `subscribe` are the *additional* symbols to subscribe
+  /// to. Use the [`unsubscribe`][Self::unsubscribe] method to
+  /// unsubscribe from receiving data for certain symbols.
+  ///
+  /// Only symbols not already subscribed to, either individually or by
+  /// means of a subscription to all symbols, are sent to the server. If
+  /// there are none, this method returns immediately without any
+  /// communication taking place.
+  ///
+  /// On success, the market data subscribed to after the operation, as
+  /// reported by the server, is returned. Symbols the server did not
+  /// accept are absent from it.
+  #[inline]
+  pub async fn subscribe(
+    &mut self,
+    subscribe: &M::Channels,
+  ) -> Result<Result<M::Channels, Error>, S::Error> {
+    let (mut subscribe, _) = subscribe.diff(&self.subscriptions);
+    let () = subscribe.strip_covered(&self.subscriptions);
+    self.rejected = M::Channels::default();
+    if subscribe.is_empty() {
+      return Ok(Ok(self.subscriptions.clone()))
+    }
+
+    let request = Request::Subscribe(Cow::Borrowed(&subscribe));
+    let result = self.subscribe_unsubscribe(&request).await?;
+    if let Ok(subscribed) = &result {
+      let (rejected, _) = subscribe.diff(subscribed);
+      self.rejected = rejected;
+    }
+    Ok(result)
+  }
+
+  /// Subscribe to the provided market data, in chunks, until a
+  /// deadline is hit.
+  ///
+  /// Contrary to [`subscribe`][Self::subscribe], symbols are sent to
+  /// the server in multiple requests, each covering a limited number
+  /// of them. Once `deadline` is reached, no further requests are
+  /// issued and the outcome reports which market data was acknowledged
+  /// up to this point and which is still pending.
+  ///
+  /// If the deadline hits while a request is in flight, the server's
+  /// response may still arrive at a later point. Similar to an
+  /// operation timing out, the connection should be considered
+  /// unusable in this case and be re-established.
+  pub async fn subscribe_with_deadline(
+    &mut self,
+    subscribe: &M::Channels,
+    deadline: Instant,
+  ) -> Result<SubscribeOutcome<M::Channels>, S::Error> {
+    let (mut subscribe, _) = subscribe.diff(&self.subscriptions);
+    let () = subscribe.strip_covered(&self.subscriptions);
+
+    let mut outcome = SubscribeOutcome {
+      acknowledged: M::Channels::default(),
+      pending: M::Channels::default(),
+      rejected: M::Channels::default(),
+      error: None,
+    };
+    let deadline = TokioInstant::from_std(deadline);
+    let mut chunks = subscribe.chunks(SUBSCRIBE_CHUNK_SIZE).into_iter();
+
+    for chunk in chunks.by_ref() {
+      let request = Request::Subscribe(Cow::Borrowed(&chunk));
+      let result = match timeout_at(deadline, self.subscribe_unsubscribe(&request)).await {
+        Ok(result) => result?,
+        Err(..) => {
+          let () = outcome.pending.merge(&chunk);
+          break
+        },
+      };
+
+      match result {
+        Ok(subscribed) => {
+          let (rejected, _) = chunk.diff(&subscribed);
+          let (accepted, _) = chunk.diff(&rejected);
+          let () = outcome.acknowledged.merge(&accepted);
+          let () = outcome.rejected.merge(&rejected);
+        },
+        Err(error) => {
+          let () = outcome.pending.merge(&chunk);
+          outcome.error = Some(error);
+          break
+        },
+      }
+    }
+
+    let () = chunks.for_each(|chunk| outcome.pending.merge(&chunk));
+    Ok(outcome)
+  }
+
+  /// Unsubscribe from receiving market data for the provided symbols.
+  ///
+  /// Subscriptions of market data for symbols other than the ones
+  /// provided to this function are left untouched. Symbols not
+  /// currently subscribed to are not sent to the server and, if none of
+  /// the provided ones are active, this method returns immediately.
+  ///
+  /// On success, the market data still subscribed to, as reported by
+  /// the server, is returned.
+  #[inline]
+  pub async fn unsubscribe(
+    &mut self,
+    unsubscribe: &M::Channels,
+  ) -> Result<Result<M::Channels, Error>, S::Error> {
+    let (inactive, _) = unsubscribe.diff(&self.subscriptions);
+    let (active, _) = unsubscribe.diff(&inactive);
+    if active.is_empty() {
+      return Ok(Ok(self.subscriptions.clone()))
+    }
+
+    let request = Request::Unsubscribe(Cow::Owned(active));
+    self.subscribe_unsubscribe(&request).await
+  }
+
+  /// Replace the active market data subscriptions with `target`.
+  ///
+  /// The difference between the active subscriptions and `target` is
+  /// computed (see [`Channels::diff`]) and market data no longer
+  /// desired is unsubscribed from, before subscribing to what is
+  /// missing. Each of the two requests is only sent if it is non-empty,
+  /// meaning that no round trip to the server happens if the active
+  /// subscriptions already match `target`.
+  ///
+  /// The tracked [`subscriptions`][Self::subscriptions] always reflect
+  /// what the server acknowledged last. Should the unsubscribe request
+  /// fail, they are left unchanged; should the subscribe request fail,
+  /// they reflect the state after unsubscribing.
+  pub async fn set_subscriptions(
+    &mut self,
+    target: &M::Channels,
+  ) -> Result<Result<M::Channels, Error>, S::Error> {
+    let (subscribe, unsubscribe) = target.diff(&self.subscriptions);
+
+    if !unsubscribe.is_empty() {
+      let request = Request::Unsubscribe(Cow::Owned(unsubscribe));
+      if let Err(err) = self.subscribe_unsubscribe(&request).await? {
+        return Ok(Err(err))
+      }
+    }
+
+    if !subscribe.is_empty() {
+      let request = Request::Subscribe(Cow::Owned(subscribe));
+      if let Err(err) = self.subscribe_unsubscribe(&request).await? {
+        return Ok(Err(err))
+      }
+    }
+
+    Ok(Ok(self.subscriptions.clone()))
+  }
+
+  /// Unsubscribe from receiving market data for all symbols currently
+  /// subscribed to.
+  ///
+  /// If there are no active subscriptions, this method is a no-op
+  /// and no request is sent to the server.
+  pub async fn clear(&mut self) -> Result<Result<M::Channels, Error>, S::Error> {
+    if self.subscriptions.is_empty() {
+      return Ok(Ok(M::Channels::default()))
+    }
+
+    let unsubscribe = self.subscriptions.clone();
+    let request = Request::Unsubscribe(Cow::Owned(unsubscribe));
+    self.subscribe_unsubscribe(&request).await
+  }
+
+  /// Send a raw message over the websocket connection and wait for
+  /// the next control message.
+  ///
+  /// This method is an escape hatch for sending messages not modeled
+  /// by this crate. It bypasses subscription tracking entirely: the
+  /// message is sent as-is and any effect it has on the active
+  /// subscriptions is not reflected in
+  /// [`subscriptions`][Self::subscriptions]. Furthermore, the next
+  /// control message received, whatever it may be, is reported as the
+  /// response. If the server does not respond to the message, this
+  /// method only resolves once some other control message arrives (or
+  /// once the configured timeout expires).
+  pub async fn send_raw(
+    &mut self,
+    message: wrap::Message,
+  ) -> Result<Result<ControlMessage<M::Channels>, Error>, S::Error> {
+    if let Err(err) = self.begin_request(Response::Any) {
+      return Ok(Err(err))
+    }
+    let response = with_timeout(self.timeout, self.subscription.send(message)).await;
+    let () = self.end_request();
+    let response = match response {
+      Some(response) => response?,
+      None => return Ok(Err(Error::Str("raw request timed out".into()))),
+    };
+    let () = self.record_control(&response);
+
+    match response {
+      Some(Ok(control)) => Ok(Ok(control)),
+      Some(Err(())) => Ok(Err(Error::Str(
+        "failed to receive response to raw request".into(),
+      ))),
+      None => Ok(Err(Error::Str(
+        "stream was closed before response was received".into(),
+      ))),
+    }
+  }
+
+  /// Unsubscribe from all market data and close the connection.
+  ///
+  /// Unsubscribing explicitly, instead of merely dropping the
+  /// connection, allows the server to free up resources associated
+  /// with it right away. Afterwards, a websocket close frame is sent to
+  /// the server, irrespective of whether unsubscribing succeeded. The
+  /// first error encountered is reported. As is the case for
+  /// [`ping`][Self::ping], the close frame is sent over the associated
+  /// stream, which hence needs to be polled concurrently, e.g., by
+  /// means of [`drive`]. The stream ends once the server acknowledged
+  /// the close.
+  pub async fn close(mut self) -> Result<Result<(), Error>, S::Error> {
+    let result = self.clear().await;
+
+    let connection = &self.connection;
+    let () = connection.request_close();
+    let closed = poll_fn(|ctx| connection.poll_close(ctx));
+    let closed = with_timeout(self.timeout, closed).await;
+
+    match (result?, closed) {
+      (Err(err), _) => Ok(Err(err)),
+      (Ok(_data), Some(_sent)) => Ok(Ok(())),
+      (Ok(_data), None) => Ok(Err(Error::Str("close timed out".into()))),
+    }
+  }
+}
+
+impl<S, M> Subscription<S, M>
+where
+  S: Sink<wrap::Message> + Unpin,
+  M: StreamMessage<Channels = MarketData>,
+{
+  /// Subscribe to aggregate bars for all symbols.
+  #[inline]
+  pub async fn subscribe_all_bars(&mut self) -> Result<Result<MarketData, Error>, S::Error> {
+    self.subscribe(&MarketData::all_bars()).await
+  }
+
+  /// Subscribe to aggregate bars for the provided symbols.
+  ///
+  /// This method is a shorthand for [`subscribe`][Self::subscribe]
+  /// with a [`MarketData`] object containing only said symbols.
+  #[inline]
+  pub async fn subscribe_bars<Y>(
+    &mut self,
+    symbols: Y,
+  ) -> Result<Result<MarketData, Error>, S::Error>
+  where
+    Y: Into<SymbolList>,
+  {
+    self.subscribe(&MarketData::with_bars(symbols)).await
+  }
+
+  /// Subscribe to quotes for the provided symbols.
+  ///
+  /// This method is a shorthand for [`subscribe`][Self::subscribe]
+  /// with a [`MarketData`] object containing only said symbols.
+  #[inline]
+  pub async fn subscribe_quotes<Y>(
+    &mut self,
+    symbols: Y,
+  ) -> Result<Result<MarketData, Error>, S::Error>
+  where
+    Y: Into<SymbolList>,
+  {
+    self.subscribe(&MarketData::with_quotes(symbols)).await
+  }
+
+  /// Subscribe to trades for the provided symbols.
+  ///
+  /// This method is a shorthand for [`subscribe`][Self::subscribe]
+  /// with a [`MarketData`] object containing only said symbols.
+  #[inline]
+  pub async fn subscribe_trades<Y>(
+    &mut self,
+    symbols: Y,
+  ) -> Result<Result<MarketData, Error>, S::Error>
+  where
+    Y: Into<SymbolList>,
+  {
+    self.subscribe(&MarketData::with_trades(symbols)).await
+  }
+
+  /// Unsubscribe from aggregate bars for the provided symbols.
+  ///
+  /// This method is a shorthand for [`unsubscribe`][Self::unsubscribe]
+  /// with a [`MarketData`] object containing only said symbols.
+  #[inline]
+  pub async fn unsubscribe_bars<Y>(
+    &mut self,
+    symbols: Y,
+  ) -> Result<Result<MarketData, Error>, S::Error>
+  where
+    Y: Into<SymbolList>,
+  {
+    self.unsubscribe(&MarketData::with_bars(symbols)).await
+  }
+
+  /// Unsubscribe from quotes for the provided symbols.
+  ///
+  /// This method is a shorthand for [`unsubscribe`][Self::unsubscribe]
+  /// with a [`MarketData`] object containing only said symbols.
+  #[inline]
+  pub async fn unsubscribe_quotes<Y>(
+    &mut self,
+    symbols: Y,
+  ) -> Result<Result<MarketData, Error>, S::Error>
+  where
+    Y: Into<SymbolList>,
+  {
+    self.unsubscribe(&MarketData::with_quotes(symbols)).await
+  }
+
+  /// Unsubscribe from trades for the provided symbols.
+  ///
+  /// This method is a shorthand for [`unsubscribe`][Self::unsubscribe]
+  /// with a [`MarketData`] object containing only said symbols.
+  #[inline]
+  pub async fn unsubscribe_trades<Y>(
+    &mut self,
+    symbols: Y,
+  ) -> Result<Result<MarketData, Error>, S::Error>
+  where
+    Y: Into<SymbolList>,
+  {
+    self.unsubscribe(&MarketData::with_trades(symbols)).await
+  }
+
+  /// Restrict the bar subscription to exactly the provided symbols.
+  ///
+  /// This method is mostly useful for narrowing a subscription to bars
+  /// for all symbols down to an explicit list: the server treats the
+  /// two as unrelated, so the subscription to all symbols has to be
+  /// removed first, before subscribing to the individual symbols. This
+  /// transition involves two round trips to the server and no bars are
+  /// delivered in between. Bars for symbols currently subscribed to
+  /// individually but not contained in `symbols` are unsubscribed from
+  /// as well.
+  ///
+  /// The tracked [`subscriptions`][Self::subscriptions] only reflect
+  /// the explicit list once both requests succeeded. Should the
+  /// second request fail, they reflect what the server acknowledged
+  /// last, i.e., no bars being subscribed to.
+  pub async fn restrict_bars<Y>(
+    &mut self,
+    symbols: Y,
+  ) -> Result<Result<MarketData, Error>, S::Error>
+  where
+    Y: Into<SymbolList>,
+  {
+    let mut target = self.subscriptions.clone();
+    target.bars = Symbols::from(symbols.into());
+    self.set_subscriptions(&target).await
+  }
+}
+
+
+impl<S, M> Subscription<S, M>
+where
+  S: Sink<wrap::Message, Error = WebSocketError> + Unpin,
+  M: StreamMessage,
+  M::Data: Debug,
+{
+  /// Subscribe to the provided market data and wait for the first
+  /// [`Data`] item to arrive over the associated stream.
+  ///
+  /// This method is a convenience for scripts, examples, and smoke
+  /// tests. It drives the provided `stream`, which is assumed to be
+  /// associated with this `Subscription`, until the subscription
+  /// request has been acknowledged and then returns the next market
+  /// data item received. Control messages do not surface on the stream
+  /// and are hence never returned. Note that the item is not
+  /// necessarily related to `data`: it may just as well belong to a
+  /// subscription that was active before. Contrary to [`drive`], data
+  /// arriving while the request is in flight is not discarded; the
+  /// first such item is returned instead.
+  pub async fn subscribe_and_recv<St>(
+    &mut self,
+    stream: &mut St,
+    data: &M::Channels,
+  ) -> Result<M::Data, Error>
+  where
+    St: FusedStream<Item = UserMessage<M>> + Unpin,
+  {
+    let mut subscribe = self.subscribe(data).boxed_local();
+    let mut received = None;
+
+    let result = loop {
+      match select(subscribe, stream.next()).await {
+        Either::Left((result, _next)) => break result,
+        Either::Right((message, pending)) => {
+          subscribe = pending;
+          match message {
+            Some(Ok(Ok(data))) => {
+              if received.is_none() {
+                received = Some(data);
+              }
+            },
+            Some(message) => return Err(stream_error(message)),
+            None => {
+              return Err(Error::Str(
+                "stream was closed before subscription confirmation message was received".into(),
+              ))
+            },
+          }
+        },
+      }
+    };
+    let _data = result??;
+
+    if let Some(data) = received {
+      return Ok(data)
+    }
+
+    match stream.next().await {
+      Some(Ok(Ok(data))) => Ok(data),
+      Some(Ok(Err(err))) => Err(Error::Json(err)),
+      Some(Err(err)) => Err(Error::WebSocket(err)),
+      None => Err(Error::Str(
+        "stream was closed before market data was received".into(),
+      )),
+    }
+  }
+}
+
+
+/// A cloneable handle to a [`Subscription`], for changing subscriptions
+/// from multiple tasks.
+///
+/// Operations performed through any of the handles are serialized:
+/// a request is only sent once the one issued before it has been
+/// acknowledged by the server (or failed). Hence, concurrent callers
+/// can never observe or cause a partially updated subscription state.
+/// As is the case for [`Subscription`], the associated stream needs to
+/// be polled for any operation to resolve.
+#[derive(Debug)]
+pub struct SharedSubscription<S, M = DataMessage>(Arc<Mutex<Subscription<S, M>>>)
+where
+  M: StreamMessage;
+
+impl<S, M> SharedSubscription<S, M>
+where
+  M: StreamMessage,
+{
+  /// Create a `SharedSubscription` from a [`Subscription`].
+  #[inline]
+  pub fn new(subscription: Subscription<S, M>) -> Self {
+    Self(Arc::new(Mutex::new(subscription)))
+  }
+
+  /// Acquire exclusive access to the wrapped [`Subscription`].
+  ///
+  /// Other handles wait for the returned guard to be dropped before
+  /// they can issue further requests. Use this method to perform
+  /// multiple operations without any other task interleaving.
+  #[inline]
+  pub async fn lock(&self) -> MutexGuard<'_, Subscription<S, M>> {
+    self.0.lock().await
+  }
+
+  /// Retrieve an owned copy of the currently active individual market
+  /// data subscriptions.
+  #[inline]
+  pub async fn snapshot(&self) -> M::Channels {
+    self.lock().await.snapshot()
+  }
+}
+
+
+/// A cloneable, read-only view of the market data subscriptions of a
+/// [`Subscription`].
+///
+/// Contrary to a [`SharedSubscription`], this handle does not provide
+/// access to the subscription itself and reading the state never waits
+/// for an operation in progress to finish. It is updated whenever the
+/// server acknowledges a change.
+#[derive(Clone, Debug, Default)]
+pub struct SubscriptionState<C = MarketData>(Arc<StdMutex<C>>);
+
+impl<C> SubscriptionState<C>
+where
+  C: Clone,
+{
+  /// Replace the tracked market data.
+  fn set(&self, data: &C) {
+    *self.0.lock().unwrap_or_else(|err| err.into_inner()) = data.clone();
+  }
+
+  /// Retrieve an owned copy of the currently active individual market
+  /// data subscriptions.
+  #[inline]
+  pub fn snapshot(&self) -> C {
+    self.0.lock().unwrap_or_else(|err| err.into_inner()).clone()
+  }
+}
+
+impl<S, M> SharedSubscription<S, M>
+where
+  S: Sink<wrap::Message> + Unpin,
+  M: StreamMessage,
+{
+  /// Subscribe to the provided market data.
+  ///
+  /// Please refer to [`Subscription::subscribe`] for details.
+  #[inline]
+  pub async fn subscribe(
+    &self,
+    subscribe: &M::Channels,
+  ) -> Result<Result<M::Channels, Error>, S::Error> {
+    self.lock().await.subscribe(subscribe).await
+  }
+
+  /// Unsubscribe from receiving market data for the provided symbols.
+  ///
+  /// Please refer to [`Subscription::unsubscribe`] for details.
+  #[inline]
+  pub async fn unsubscribe(
+    &self,
+    unsubscribe: &M::Channels,
+  ) -> Result<Result<M::Channels, Error>, S::Error> {
+    self.lock().await.unsubscribe(unsubscribe).await
+  }
+}
+
+impl<S, M> Clone for SharedSubscription<S, M>
+where
+  M: StreamMessage,
+{
+  #[inline]
+  fn clone(&self) -> Self {
+    Self(self.0.clone())
+  }
+}
+
+impl<S, M> From<Subscription<S, M>> for SharedSubscription<S, M>
+where
+  M: StreamMessage,
+{
+  #[inline]
+  fn from(subscription: Subscription<S, M>) -> Self {
+    Self::new(subscription)
+  }
+}
+
+
+type ParseResult<M> = Result<Result<Vec<M>, JsonError>, WebSocketError>;
+type ParseFn<M> = fn(Result<wrap::Message, WebSocketError>) -> ParseResult<M>;
+type MapFn<M> = fn(Result<Result<M, JsonError>, WebSocketError>) -> ParsedMessage<M>;
+type Stream<M, IO = MaybeTlsStream<TcpStream>> = Counted<
+  Map<Unfold<Map<Wrapper<Monitor<WebSocketStream<IO>>>, ParseFn<M>>, M, JsonError>, MapFn<M>>,
+  ParsedMessage<M>,
+  <M as StreamMessage>::Channels,
+>;
+type Connection<M, IO = MaybeTlsStream<TcpStream>> = (
+  Fuse<MessageStream<SplitStream<Stream<M, IO>>, ParsedMessage<M>>>,
+  Subscription<SplitSink<Stream<M, IO>, wrap::Message>, M>,
+);
+
+
+/// A type used for requesting a subscription to real time market
+/// data.
+///
+/// The bar (`B`), quote (`Q`), and trade (`T`) types used can be
+/// overwritten to extend/customize the default types ([`Bar`],
+/// [`Quote`], and [`Trade`], respectively) that are provided by the
+/// library. Going one step further, the message type (`M`) can be
+/// replaced as well, with any type implementing [`StreamMessage`], in
+/// order to handle messages not supported by [`DataMessage`]. In this
+/// case the stream yields items of the associated
+/// [`Data`][StreamMessage::Data] type and the bar, quote, and trade
+/// types are irrelevant.
+///
+/// Connecting yields a stream and a [`Subscription`]. The latter is
+/// used for changing subscriptions, while the former is a
+/// [`Stream`][FuturesStream] of
+/// `Result<Result<Data<B, Q, T>, JsonError>, WebSocketError>` items.
+/// Control messages are handled internally and never surface on the
+/// stream, meaning that it can be consumed directly, with all the
+/// usual stream combinators at one's disposal:
+/// ```no_run
+/// # use apca::data::v2::stream::drive;
+/// # use apca::data::v2::stream::Data;
+/// # use apca::data::v2::stream::MarketData;
+/// # use apca::data::v2::stream::RealtimeData;
+/// # use apca::data::v2::stream::IEX;
+/// # use apca::ApiInfo;
+/// # use apca::Client;
+/// # use futures::future::ready;
+/// # use futures::FutureExt as _;
+/// # use futures::StreamExt as _;
+/// # async fn example() {
+/// let client = Client::new(ApiInfo::from_env().unwrap());
+/// let (mut stream, mut subscription) =
+///   client.subscribe::<RealtimeData<IEX>>().await.unwrap();
+///
+/// let data = MarketData::builder().bars(["SPY"]).build();
+/// let subscribe = subscription.subscribe(&data).boxed();
+/// let _data = drive(subscribe, &mut stream).await.unwrap().unwrap().unwrap();
+///
+/// let mut bars = stream.filter_map(|result| {
+///   ready(match result {
+///     Ok(Ok(Data::Bar { bar, .. })) => Some(bar),
+///     _ => None,
+///   })
+/// });
+///
+/// while let Some(bar) = bars.next().await {
+///   println!("{bar:?}");
+/// }
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct RealtimeData<S, M = DataMessage> {
+  /// Phantom data to make sure that we "use" `S` and `M`.
+  _phantom: PhantomData<(S, M)>,
+}
+
+impl<S, M> RealtimeData<S, M>
+where
+  S: Source,
+  M: StreamMessage + Send + Unpin + Debug + DeserializeOwned,
+  M::Data: Send + Debug,
+{
+  /// Determine the URL to connect to.
+  fn url(api_info: &ApiInfo) -> Result<Url, Error> {
+    let url = match S::source() {
+      SourceVariant::PathComponent(component) => {
+        let mut url = api_info.data_stream_base_url.clone();
+        url.set_path(&format!("v2/{}", component));
+        url
+      },
+      SourceVariant::Path(path) => {
+        let mut url = api_info.data_stream_base_url.clone();
+        url.set_path(path);
+        url
+      },
+      SourceVariant::Url(url) => Url::parse(&url)?,
+    };
+    Ok(url)
+  }
+
+  /// Perform the initial handshake with the server over an already
+  /// connected websocket stream.
+  async fn handshake<IO>(
+    stream: Wrapper<Monitor<WebSocketStream<IO>>>,
+    connection: Arc<ConnectionState>,
+    api_info: &ApiInfo,
+    config: StreamConfig,
+  ) -> Result<Connection<M, IO>, Error>
+  where
+    IO: AsyncRead + AsyncWrite + Send + Unpin,
+  {
+    fn parse_with_payload<M>(result: Result<wrap::Message, WebSocketError>) -> ParseResult<M>
+    where
+      M: StreamMessage + DeserializeOwned,
+    {
+      fn with_payload(err: JsonError, payload: &[u8]) -> JsonError {
+        debug!(
+          error = display(&err),
+          payload = display(String::from_utf8_lossy(payload)),
+          "failed to decode message"
+        );
+        err
+      }
+
+      result.map(|message| {
+        match message {
+          wrap::Message::Text(string) => {
+            json_from_str::<Vec<M>>(&string).map_err(|err| with_payload(err, string.as_bytes()))
+          },
+          wrap::Message::Binary(data) => {
+            json_from_slice::<Vec<M>>(&data).map_err(|err| with_payload(err, &data))
+          },
+        }
+        .map(retain_known)
+      })
+    }
+
+    let ApiInfo { key_id, secret, .. } = api_info;
+
+    let parse = if config.payload_in_errors {
+      parse_with_payload::<M> as ParseFn<_>
+    } else {
+      parse_messages::<M> as ParseFn<_>
+    };
+
+    let shared = Arc::<Shared<_>>::default();
+    let pause = Arc::<Pause>::default();
+    let interner = Interner::default();
+    let stream = Unfold::new(stream.map(parse)).map(MessageResult::from as MapFn<M>);
+    let stream = Counted::new(
+      stream,
+      shared.clone(),
+      pause.clone(),
+      interner.clone(),
+      config.buffer_capacity,
+    );
+    let (send, recv) = stream.split();
+    let (stream, subscription) = subscribe::subscribe(recv, send);
+    let mut stream = stream.fuse();
+    let mut subscription = Subscription::new(subscription, shared, pause, interner);
+    subscription.connection = connection;
+    let () = subscription.set_timeout(config.control_timeout);
+
+    let connect = subscription.wait_connected().boxed();
+    let () = drive(connect, &mut stream).await.map_err(stream_error)??;
+
+    let authenticate = subscription.authenticate(key_id, secret).boxed();
+    let () = drive(authenticate, &mut stream)
+      .await
+      .map_err(stream_error)???;
+
+    Ok((stream, subscription))
+  }
+
+  /// Establish a connection for streaming real time market data using
+  /// the provided configuration.
+  pub async fn connect_configured(
+    api_info: &ApiInfo,
+    config: StreamConfig,
+  ) -> Result<Connection<M>, Error> {
+    let url = Self::url(api_info)?;
+    let (stream, connection) = connect(&url, config.ping_interval).await?;
+    Self::handshake(stream, connection, api_info, config).await
+  }
+
+  /// Establish an authenticated connection for streaming real time
+  /// market data, using credentials and URLs retrieved from the
+  /// environment.
+  ///
+  /// Please refer to [`ApiInfo::from_env`] for the environment
+  /// variables used.
+  pub async fn connect_from_env(config: StreamConfig) -> Result<Connection<M>, Error> {
+    let api_info = ApiInfo::from_env()?;
+    Self::connect_configured(&api_info, config).await
+  }
+
+  /// Re-establish a connection for streaming real time market data,
+  /// retrying with backoff according to `policy`.
+  ///
+  /// `previous` is the subscription of the connection that was lost.
+  /// Once connected and authenticated, the new connection is
+  /// subscribed to the market data `previous` was subscribed to.
+  /// Failing to do so counts as a failed attempt as well. Errors that
+  /// retrying cannot fix, such as authentication failures, are
+  /// reported right away. The new connection's
+  /// [`generation`][Subscription::generation] is one higher than that
+  /// of `previous`.
+  pub async fn reconnect<P>(
+    api_info: &ApiInfo,
+    config: StreamConfig,
+    policy: &ReconnectPolicy,
+    previous: &Subscription<P, M>,
+  ) -> Result<Connection<M>, Error> {
+    let subscriptions = previous.snapshot();
+    let generation = previous.generation().wrapping_add(1);
+
+    policy
+      .retry(|| async {
+        let (mut stream, mut subscription) = Self::connect_configured(api_info, config).await?;
+        let subscribe = subscription.subscribe(&subscriptions).boxed();
+        let _data = drive(subscribe, &mut stream)
+          .await
+          .map_err(stream_error)???;
+        subscription.generation = generation;
+        Ok((stream, subscription))
+      })
+      .await
+  }
+
+  /// Establish a connection for streaming real time market data over
+  /// a user-provided stream.
+  ///
+  /// This constructor can be used to run the protocol over a transport
+  /// not natively supported by the crate, such as a Unix domain socket
+  /// or an already established TLS stream. The websocket handshake as
+  /// well as authentication are performed on `stream`, with the URL
+  /// inferred from `api_info` and the source `S` as usual.
+  pub async fn connect_with<IO>(
+    stream: IO,
+    api_info: &ApiInfo,
+    config: StreamConfig,
+  ) -> Result<Connection<M, IO>, Error>
+  where
+    IO: AsyncRead + AsyncWrite + Send + Unpin,
+  {
+    let url = Self::url(api_info)?;
+    let (stream, connection) = connect_with(&url, stream, config.ping_interval).await?;
+    Self::handshake(stream, connection, api_info, config).await
+  }
+}
+
+#[async_trait]
+impl<S, M> Subscribable for RealtimeData<S, M>
+where
+  S: Source,
+  M: StreamMessage + Send + Unpin + Debug + DeserializeOwned,
+  M::Data: Send + Debug,
+{
+  type Input = ApiInfo;
+  type Subscription = Subscription<SplitSink<Stream<M>, wrap::Message>, M>;
+  type Stream = Fuse<MessageStream<SplitStream<Stream<M>>, ParsedMessage<M>>>;
+
+  async fn connect(api_info: &Self::Input) -> Result<(Self::Stream, Self::Subscription), Error> {
+    Self::connect_configured(api_info, StreamConfig::default()).await
+  }
+}
+
+
+#[cfg(test)]
+pub(crate) mod test {
+  use super::*;
+
+  use futures::channel::mpsc::unbounded;
+  use futures::channel::mpsc::UnboundedReceiver;
+  use futures::channel::mpsc::UnboundedSender;
+
+
+  /// A sink standing in for the sending half of a websocket connection.
+  ///
+  /// Each request sent over the sink is handed to a user-provided
+  /// function, the result of which is fed back into the associated
+  /// message stream, emulating the server's response.
+  pub(crate) struct MockSink<B, Q, T, F> {
+    /// The function responding to requests.
+    respond: F,
+    /// The sender used for feeding back responses.
+    sender: UnboundedSender<ParsedMessage<DataMessage<B, Q, T>>>,
+  }
+
+  impl<B, Q, T, F> Sink<wrap::Message> for MockSink<B, Q, T, F>
+  where
+    F: FnMut(Request<'_>) -> Vec<DataMessage<B, Q, T>> + Unpin,
+  {
+    type Error = WebSocketError;
+
+    fn poll_ready(self: Pin<&mut Self>, _ctx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+      Poll::Ready(Ok(()))
+    }
+
+    fn start_send(self: Pin<&mut Self>, message: wrap::Message) -> Result<(), Self::Error> {
+      let this = Pin::get_mut(self);
+      let request = match &message {
+        wrap::Message::Text(string) => json_from_str::<Request<'_>>(string),
+        wrap::Message::Binary(data) => json_from_slice::<Request<'_>>(data),
+      }
+      .unwrap();
+
+      for response in (this.respond)(request) {
+        // The receiving end may be gone already, at which point nobody
+        // cares about the response anymore.
+        let _result = this.sender.unbounded_send(MessageResult::Ok(Ok(response)));
+      }
+      Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _ctx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+      Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _ctx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+      Poll::Ready(Ok(()))
+    }
+  }
+
+
+  /// A handle for injecting synthetic messages into a mock stream.
+  pub(crate) struct Injector<B, Q, T>(UnboundedSender<ParsedMessage<DataMessage<B, Q, T>>>);
+
+  impl<B, Q, T> Injector<B, Q, T> {
+    /// Push a message into the stream, as if it had been received from
+    /// the server.
+    pub(crate) fn push(&self, message: DataMessage<B, Q, T>) {
+      let () = self
+        .0
+        .unbounded_send(MessageResult::Ok(Ok(message)))
+        .unwrap();
+    }
+
+    /// Close the stream, as if the server had terminated the
+    /// connection.
+    pub(crate) fn close(&self) {
+      let () = self.0.close_channel();
+    }
+  }
+
+
+  type MockMessage<B, Q, T> = ParsedMessage<DataMessage<B, Q, T>>;
+  type MockStream<B, Q, T> =
+    Counted<UnboundedReceiver<MockMessage<B, Q, T>>, MockMessage<B, Q, T>, MarketData>;
+  type MockConnection<B, Q, T, F> = (
+    Fuse<MessageStream<MockStream<B, Q, T>, MockMessage<B, Q, T>>>,
+    Subscription<MockSink<B, Q, T, F>, DataMessage<B, Q, T>>,
+    Injector<B, Q, T>,
+  );
+
+
+  /// Create a stream and subscription pair operating on in-memory
+  /// channels instead of a websocket connection.
+  ///
+  /// Requests sent by the subscription are answered by `respond`.
+  /// Arbitrary additional messages can be pushed into the stream by
+  /// means of the returned [`Injector`]. The stream ends once both the
+  /// injector and the subscription have been dropped.
+  pub(crate) fn mock_subscription<B, Q, T, F>(respond: F) -> MockConnection<B, Q, T, F>
+  where
+    B: Unpin,
+    Q: Unpin,
+    T: Unpin,
+    F: FnMut(Request<'_>) -> Vec<DataMessage<B, Q, T>> + Unpin,
+  {
+    let (sender, receiver) = unbounded();
+    let sink = MockSink {
+      respond,
+      sender: sender.clone(),
+    };
+    let shared = Arc::<Shared<_>>::default();
+    let pause = Arc::<Pause>::default();
+    let interner = Interner::default();
+    let receiver = Counted::new(
+      receiver,
+      shared.clone(),
+      pause.clone(),
+      interner.clone(),
+      None,
+    );
+    let (stream, subscription) = subscribe::subscribe(receiver, sink);
+    let subscription = Subscription::new(subscription, shared, pause, interner);
+    (stream.fuse(), subscription, Injector(sender))
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use std::cell::Cell;
+  use std::cell::RefCell;
+  use std::collections::BTreeSet;
+  use std::io::ErrorKind;
+  use std::rc::Rc;
+  use std::str::FromStr;
+  use std::time::Duration;
+
+  use chrono::DateTime;
+
+  use futures::channel::oneshot;
+  use futures::future::join;
+  use futures::TryStreamExt as _;
+
+  use serial_test::serial;
+
+  use serde_json::from_str as json_from_str;
+
+  use test_log::test;
+
+  use tokio::io::duplex;
+  use tokio::spawn;
+  use tokio::time::sleep;
+  use tokio::time::timeout;
+
+  use tungstenite::accept_async;
+
+  use websocket_util::test::WebSocketStream;
+  use websocket_util::tungstenite::Message;
+
+  use crate::api::API_BASE_URL;
+  use crate::data::v2::stream::test::mock_subscription;
+  use crate::websocket::test::mock_stream;
+  use crate::websocket::test::KEY_ID;
+  use crate::websocket::test::SECRET;
+  use crate::Client;
+
+
+  pub(crate) const CONN_RESP: &str = r#"[{"T":"success","msg":"connected"}]"#;
+  // TODO: Until we can interpolate more complex expressions using
+  //       `std::format` in a const context we have to hard code the
+  //       values of `crate::websocket::test::KEY_ID` and
+  //       `crate::websocket::test::SECRET` here.
+  pub(crate) const AUTH_REQ: &str =
+    r#"{"action":"auth","key":"USER12345678","secret":"justletmein"}"#;
+  pub(crate) const AUTH_RESP: &str = r#"[{"T":"success","msg":"authenticated"}]"#;
+  const SUB_REQ: &str = r#"{"action":"subscribe","bars":["AAPL","VOO"]}"#;
+  const SUB_RESP: &str = r#"[{"T":"subscription","bars":["AAPL","VOO"]}]"#;
+  const UNSUB_REQ: &str = r#"{"action":"unsubscribe","bars":["AAPL","VOO"]}"#;
+  const UNSUB_RESP: &str = r#"[{"T":"subscription","bars":[],"quotes":[],"trades":[]}]"#;
+  pub(crate) const BAR_RESP: &str = r#"[{"T":"b","S":"AAPL","o":142.61,"h":142.67,"l":142.56,"c":142.66,"v":1832,"t":"2022-11-29T15:12:00Z"},{"T":"b","S":"VOO","o":363.3,"h":363.36,"l":363.25,"c":363.31,"v":2165,"t":"2022-11-29T15:12:00Z"}]"#;
+  const SUB_ALL_REQ: &str = r#"{"action":"subscribe","bars":["*"]}"#;
+  const SUB_ALL_RESP: &str = r#"[{"T":"subscription","bars":["*"]}]"#;
+  const SUB_ERR_REQ: &str = r#"{"action":"subscribe","quotes":["AAPL"]}"#;
+  const SUB_ERR_RESP: &str = r#"[{"T":"error","code":400,"msg":"invalid syntax"}]"#;
+
+
+  /// Test that the [`Symbols::is_empty`] method works as expected.
+  #[test]
+  fn symbols_is_empty() {
+    assert!(!Symbols::All.is_empty());
+    assert!(!Symbols::List(SymbolList::from(["SPY"])).is_empty());
+    assert!(Symbols::List(SymbolList::from([])).is_empty());
+  }
+
+  /// Check that we can compute the difference between two
+  /// [`MarketData`] objects.
+  #[test]
+  fn market_data_diff() {
+    let mut current = MarketData::default();
+    current.set_bars(["AAPL", "SPY"]);
+    current.set_trades(["MSFT"]);
+    current.quotes = Symbols::All;
+
+    let mut target = MarketData::default();
+    target.set_bars(["SPY", "XLK"]);
+    target.set_quotes(["VOO"]);
+    target.trades = Symbols::All;
+
+    let (subscribe, unsubscribe) = target.diff(&current);
+
+    let mut expected = MarketData::default();
+    expected.set_bars(["XLK"]);
+    expected.set_quotes(["VOO"]);
+    expected.trades = Symbols::All;
+    assert_eq!(subscribe, expected);
+
+    let mut expected = MarketData::default();
+    expected.set_bars(["AAPL"]);
+    expected.quotes = Symbols::All;
+    expected.set_trades(["MSFT"]);
+    assert_eq!(unsubscribe, expected);
+
+    let (subscribe, unsubscribe) = target.diff(&target);
+    assert_eq!(subscribe, MarketData::default());
+    assert_eq!(unsubscribe, MarketData::default());
+  }
+
+  /// Check that we can merge two [`MarketData`] objects.
+  #[test]
+  fn market_data_merge() {
+    let mut data = MarketData::default();
+    data.set_bars(["SPY", "AAPL"]);
+    data.set_trades(["MSFT"]);
+
+    let mut other = MarketData::default();
+    other.set_bars(["XLK", "SPY"]);
+    other.quotes = Symbols::All;
+
+    data.merge(&other);
+
+    let mut expected = MarketData::default();
+    expected.set_bars(["AAPL", "SPY", "XLK"]);
+    expected.quotes = Symbols::All;
+    expected.set_trades(["MSFT"]);
+    assert_eq!(data, expected);
+  }
+
+  /// Check that we can build a [`MarketData`] object using a
+  /// [`MarketDataBuilder`].
+  #[test]
+  fn build_market_data() {
+    let data = MarketData::builder()
+      .bars(["SPY", "AAPL"])
+      .trades(vec!["MSFT".to_string()])
+      .build();
+
+    let mut expected = MarketData::default();
+    expected.set_bars(["AAPL", "SPY"]);
+    expected.set_trades(["MSFT"]);
+    assert_eq!(data, expected);
+    assert!(data.quotes.is_empty());
+  }
+
+  /// Check that sub-millisecond precision of time stamps is preserved
+  /// for all kinds of market data.
+  #[test]
+  fn parse_nanosecond_timestamps() {
+    let response = r#"[
+      {"T":"b","S":"SPY","o":415.05,"h":415.1,"l":414.98,"c":415.0,"v":123,"t":"2022-11-29T15:12:00.123456789Z"},
+      {"T":"q","S":"SPY","bp":415.0,"bs":1,"ap":415.1,"as":2,"t":"2022-11-29T15:12:00.123456789Z"},
+      {"T":"t","S":"SPY","i":1,"p":415.0,"s":10,"t":"2022-11-29T15:12:00.123456788Z"},
+      {"T":"t","S":"SPY","i":2,"p":415.1,"s":10,"t":"2022-11-29T15:12:00.123456789Z"}
+    ]"#;
+    let messages = json_from_str::<Vec<DataMessage>>(response).unwrap();
+    let timestamps = messages
+      .iter()
+      .map(|message| match message {
+        DataMessage::Bar(bar) => bar.timestamp,
+        DataMessage::Quote(quote) => quote.timestamp,
+        DataMessage::Trade(trade) => trade.timestamp,
+        message => panic!("unexpected message: {message:?}"),
+      })
+      .collect::<Vec<_>>();
+
+    assert_eq!(timestamps[0].timestamp_subsec_nanos(), 123456789);
+    assert_eq!(timestamps[1].timestamp_subsec_nanos(), 123456789);
+    assert_eq!(timestamps[2].timestamp_subsec_nanos(), 123456788);
+    // Trades within the same millisecond stay ordered.
+    assert!(timestamps[2] < timestamps[3]);
+
+    // Precision is also retained when serializing again.
+    let json = to_json(&messages).unwrap();
+    let messages = json_from_str::<Vec<DataMessage>>(&json).unwrap();
+    match &messages[2] {
+      DataMessage::Trade(trade) => assert_eq!(trade.timestamp, timestamps[2]),
+      message => panic!("unexpected message: {message:?}"),
+    }
+  }
+
+  /// Check that we can validate symbols.
+  #[test]
+  fn validate_symbols() {
+    assert_eq!(try_symbol("AAPL").unwrap(), "AAPL");
+    assert_eq!(try_symbol("BRK.B").unwrap(), "BRK.B");
+    assert_eq!(try_symbol("BTC/USD".to_string()).unwrap(), "BTC/USD");
+
+    assert_eq!(try_symbol("").unwrap_err(), SymbolError::Empty);
+    assert_eq!(
+      try_symbol("AA PL").unwrap_err(),
+      SymbolError::InvalidCharacter {
+        symbol: "AA PL".to_string(),
+        character: ' ',
+      }
+    );
+    assert_eq!(
+      try_symbol("SPY\n").unwrap_err().to_string(),
+      r#"symbol "SPY\n" contains invalid character '\n'"#
+    );
+  }
+
+  /// Check that we can deserialize and serialize the
+  /// [`DataMessage::Bar`] variant.
+  #[test]
+  fn serialize_deserialize_bar() {
+    let json = r#"{
+  "T": "b",
+  "S": "SPY",
+  "o": 388.985,
+  "h": 389.13,
+  "l": 388.975,
+  "c": 389.12,
+  "v": 49378,
+  "t": "2021-02-22T19:15:00Z"
+}"#;
+
+    let message = json_from_str::<DataMessage>(json).unwrap();
+    let bar = match &message {
+      DataMessage::Bar(bar) => bar,
+      _ => panic!("Decoded unexpected message variant: {message:?}"),
+    };
+    assert_eq!(bar.symbol, "SPY");
+    assert_eq!(bar.open_price, Num::new(388985, 1000));
+    assert_eq!(bar.high_price, Num::new(38913, 100));
+    assert_eq!(bar.low_price, Num::new(388975, 1000));
+    assert_eq!(bar.close_price, Num::new(38912, 100));
+    assert_eq!(bar.volume, Num::from(49378));
+    assert_eq!(
+      bar.timestamp,
+      DateTime::<Utc>::from_str("2021-02-22T19:15:00Z").unwrap()
+    );
+
+    assert_eq!(
+      json_from_str::<DataMessage>(&to_json(&message).unwrap()).unwrap(),
+      message
+    );
+  }
+
+  /// Check that we can deserialize a [`Bar`] with prices and volume
+  /// provided as strings, as some re-encoding gateways emit them.
+  #[test]
+  fn deserialize_bar_with_quoted_numbers() {
+    let json = r#"{
+  "T": "b",
+  "S": "SPY",
+  "o": "388.985",
+  "h": "389.13",
+  "l": 388.975,
+  "c": "389.12",
+  "v": "49378",
+  "t": "2021-02-22T19:15:00Z"
+}"#;
+
+    let bar = match json_from_str::<DataMessage>(json).unwrap() {
+      DataMessage::Bar(bar) => bar,
+      message => panic!("Decoded unexpected message variant: {message:?}"),
+    };
+    assert_eq!(bar.open_price, Num::new(388985, 1000));
+    assert_eq!(bar.high_price, Num::new(38913, 100));
+    assert_eq!(bar.low_price, Num::new(388975, 1000));
+    assert_eq!(bar.close_price, Num::new(38912, 100));
+    assert_eq!(bar.volume, Num::from(49378));
+  }
+
+  /// Check that we can serialize and deserialize the
+  /// [`DataMessage::Quote`] variant.
+  #[test]
+  fn serialize_deserialize_quote() {
+    let json: &str = r#"{
+  "T": "q",
+  "S": "NVDA",
+  "bx": "P",
+  "bp": 258.8,
+  "bs": 2,
+  "ax": "A",
+  "ap": 259.99,
+  "as": 5,
+  "c": [
+      "R"
+  ],
+  "z": "C",
+  "t": "2022-01-18T23:09:42.151875584Z"
+}"#;
+
+    let message = json_from_str::<DataMessage>(json).unwrap();
+    let quote = match &message {
+      DataMessage::Quote(quote) => quote,
+      _ => panic!("Decoded unexpected message variant: {message:?}"),
+    };
+    assert_eq!(quote.symbol, "NVDA");
+    assert_eq!(quote.bid_price, Num::new(2588, 10));
+    assert_eq!(quote.bid_size, Num::from(2));
+    assert_eq!(quote.ask_price, Num::new(25999, 100));
+    assert_eq!(quote.ask_size, Num::from(5));
+
+    assert_eq!(
+      quote.timestamp,
+      DateTime::<Utc>::from_str("2022-01-18T23:09:42.151875584Z").unwrap()
+    );
+
+    assert_eq!(
+      json_from_str::<DataMessage>(&to_json(&message).unwrap()).unwrap(),
+      message
+    );
+  }
+
+
+  /// Check that we can deserialize a [`Quote`] with all its fields,
+  /// keeping the bid and ask sides apart.
+  #[test]
+  fn deserialize_quote() {
+    let json = r#"{
+  "T": "q",
+  "S": "AAPL",
+  "bx": "V",
+  "bp": 147.05,
+  "bs": 3,
+  "ax": "Q",
+  "ap": 147.12,
+  "as": 7,
+  "c": ["R", "F"],
+  "z": "C",
+  "t": "2022-10-11T14:30:00.123456789Z"
+}"#;
+
+    let quote = match json_from_str::<DataMessage>(json).unwrap() {
+      DataMessage::Quote(quote) => quote,
+      message => panic!("Decoded unexpected message variant: {message:?}"),
+    };
+    assert_eq!(quote.symbol, "AAPL");
+    assert_eq!(quote.bid_exchange, "V");
+    assert_eq!(quote.bid_price, Num::new(14705, 100));
+    assert_eq!(quote.bid_size, Num::from(3));
+    assert_eq!(quote.ask_exchange, "Q");
+    assert_eq!(quote.ask_price, Num::new(14712, 100));
+    assert_eq!(quote.ask_size, Num::from(7));
+    assert_eq!(quote.conditions, vec!["R", "F"]);
+    assert_eq!(quote.tape, "C");
+    assert_eq!(
+      quote.timestamp,
+      DateTime::<Utc>::from_str("2022-10-11T14:30:00.123456789Z").unwrap()
+    );
+  }
+
+  /// Check that time stamps provided as nanoseconds since the Unix
+  /// epoch can be decoded.
+  #[test]
+  fn deserialize_nanosecond_timestamps() {
+    let expected = DateTime::<Utc>::from_str("2022-10-11T14:30:00.123456789Z").unwrap();
+
+    let json = r#"{"T":"t","S":"SPY","i":1,"p":357.1,"s":10,"t":1665498600123456789}"#;
+    let trade = match json_from_str::<DataMessage>(json).unwrap() {
+      DataMessage::Trade(trade) => trade,
+      message => panic!("Decoded unexpected message variant: {message:?}"),
+    };
+    assert_eq!(trade.timestamp, expected);
+
+    let json =
+      r#"{"T":"q","S":"SPY","bp":357.05,"bs":3,"ap":357.12,"as":7,"t":1665498600123456789}"#;
+    let quote = match json_from_str::<DataMessage>(json).unwrap() {
+      DataMessage::Quote(quote) => quote,
+      message => panic!("Decoded unexpected message variant: {message:?}"),
+    };
+    assert_eq!(quote.timestamp, expected);
+
+    let json = r#"{"T":"t","S":"SPY","i":1,"p":357.1,"s":10,"t":"yesterday"}"#;
+    assert!(json_from_str::<DataMessage>(json).is_err());
+  }
+
+  /// A quote for an equity.
+  #[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+  struct DetailedQuote {
+    /// The quote's symbol.
+    #[serde(rename = "S")]
+    symbol: String,
+    /// The bid's price.
+    #[serde(rename = "bp")]
+    bid_price: Num,
+    /// The bid's size.
+    #[serde(rename = "bs")]
+    bid_size: Num,
+    /// The bid's exchange code.
+    #[serde(rename = "bx")]
+    bid_exchange_code: String,
+    /// The ask's price.
+    #[serde(rename = "ap")]
+    ask_price: Num,
+    /// The ask's size.
+    #[serde(rename = "as")]
+    ask_size: Num,
+    /// The ask's exchange code.
+    #[serde(rename = "ax")]
+    ask_exchange_code: String,
+    /// The trade's size.
+    #[serde(rename = "s")]
+    trade_size: Num,
+    /// The quote's time stamp.
+    #[serde(rename = "t")]
+    timestamp: DateTime<Utc>,
+    /// The quote's conditions.
+    #[serde(rename = "c")]
+    quote_conditions: Vec<String>,
+    /// The tape.
+    #[serde(rename = "z")]
+    tape: String,
+  }
+
+  /// Check that we can serialize and deserialize the
+  /// [`DataMessage::Quote`] variant with a custom quote type.
+  #[test]
+  fn serialize_deserialize_custom_quote() {
+    let json: &str = r#"{
+  "T": "q",
+  "S": "NVDA",
+  "bx": "P",
+  "bp": 258.8,
+  "bs": 2,
+  "s": 3,
+  "ax": "A",
+  "ap": 259.99,
+  "as": 5,
+  "c": [
+      "R"
+  ],
+  "z": "C",
+  "t": "2022-01-18T23:09:42.151875584Z"
+}"#;
+
+    let message = json_from_str::<DataMessage<Bar, DetailedQuote, Trade>>(json).unwrap();
+    let quote = match &message {
+      DataMessage::Quote(quote) => quote,
+      _ => panic!("Decoded unexpected message variant: {message:?}"),
+    };
+    assert_eq!(quote.symbol, "NVDA");
+    assert_eq!(quote.bid_price, Num::new(2588, 10));
+    assert_eq!(quote.bid_size, Num::from(2));
+    assert_eq!(quote.bid_exchange_code, "P");
+    assert_eq!(quote.ask_price, Num::new(25999, 100));
+    assert_eq!(quote.ask_size, Num::from(5));
+    assert_eq!(quote.ask_exchange_code, "A");
+    assert_eq!(quote.trade_size, Num::from(3));
+
+    assert_eq!(
+      quote.timestamp,
+      DateTime::<Utc>::from_str("2022-01-18T23:09:42.151875584Z").unwrap()
+    );
+
+    assert_eq!(
+      json_from_str::<DataMessage<Bar, DetailedQuote, Trade>>(&to_json(&message).unwrap()).unwrap(),
+      message
+    );
+  }
+
+  /// Check that we can serialize and deserialize the
+  /// [`DataMessage::Trade`] variant.
+  #[test]
+  fn serialize_deserialize_trade() {
+    let json: &str = r#"{
+  "T": "t",
+  "i": 96921,
+  "S": "AAPL",
+  "x": "D",
+  "p": 126.55,
+  "s": 1,
+  "t": "2021-02-22T15:51:44.208Z",
+  "c": ["@", "I"],
+  "z": "C"
+}"#;
+
+    let message = json_from_str::<DataMessage>(json).unwrap();
+    let trade = match &message {
+      DataMessage::Trade(trade) => trade,
+      _ => panic!("Decoded unexpected message variant: {message:?}"),
+    };
+    assert_eq!(trade.symbol, "AAPL");
+    assert_eq!(trade.trade_id, 96921);
+    assert_eq!(trade.trade_price, Num::new(12655, 100));
+    assert_eq!(trade.trade_size, Num::from(1));
+
+    assert_eq!(
+      trade.timestamp,
+      DateTime::<Utc>::from_str("2021-02-22T15:51:44.208Z").unwrap()
+    );
+
+    assert_eq!(
+      json_from_str::<DataMessage>(&to_json(&message).unwrap()).unwrap(),
+      message
+    );
+  }
+
+
+  /// A trade for an equity.
+  #[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+  struct DetailedTrade {
+    /// The trade's symbol.
+    #[serde(rename = "S")]
+    symbol: String,
+    /// The trade's ID.
+    #[serde(rename = "i")]
+    trade_id: u64,
+    /// The trade's price.
+    #[serde(rename = "p")]
+    trade_price: Num,
+    /// The trade's size.
+    #[serde(rename = "s")]
+    trade_size: u64,
+    /// The trade's conditions.
+    #[serde(rename = "c")]
+    conditions: Vec<String>,
+    /// The trade's time stamp.
+    #[serde(rename = "t")]
+    timestamp: DateTime<Utc>,
+    /// The trade's exchange.
+    #[serde(rename = "x")]
+    exchange: String,
+    /// The trade's tape.
+    #[serde(rename = "z")]
+    tape: String,
+    /// The trade's update, may be "canceled", "corrected", or
+    /// "incorrect".
+    #[serde(rename = "u", default)]
+    update: Option<String>,
+  }
+
+  /// Check that we can serialize and deserialize the
+  /// [`DataMessage::Trade`] variant with a custom trade type.
+  #[test]
+  fn serialize_deserialize_custom_trade() {
+    let json: &str = r#"{
+  "T": "t",
+  "i": 96921,
+  "S": "AAPL",
+  "x": "D",
+  "p": 126.55,
+  "s": 1,
+  "t": "2021-02-22T15:51:44.208Z",
+  "c": ["@", "I"],
+  "z": "C",
+  "u": "corrected"
+}"#;
+
+    let message = json_from_str::<DataMessage<Bar, Quote, DetailedTrade>>(json).unwrap();
+    let trade = match &message {
+      DataMessage::Trade(trade) => trade,
+      _ => panic!("Decoded unexpected message variant: {message:?}"),
+    };
+    assert_eq!(trade.symbol, "AAPL");
+    assert_eq!(trade.trade_id, 96921);
+    assert_eq!(trade.trade_price, Num::new(12655, 100));
+    assert_eq!(trade.trade_size, 1);
+
+    assert_eq!(
+      trade.timestamp,
+      DateTime::<Utc>::from_str("2021-02-22T15:51:44.208Z").unwrap()
+    );
+
+    assert_eq!(trade.conditions, vec!["@", "I"]);
+    assert_eq!(trade.tape, "C");
+    assert_eq!(trade.update, Some("corrected".to_string()));
+
+    assert_eq!(
+      json_from_str::<DataMessage<Bar, Quote, DetailedTrade>>(&to_json(&message).unwrap()).unwrap(),
+      message
+    );
+  }
+
+  /// Check that `Raw` retains the JSON object a data item was decoded
+  /// from, including unknown fields.
+  #[test]
+  fn deserialize_raw() {
+    let json = r#"{"T":"t","S":"SPY","i":1,"p":400.1,"s":10,"t":"2022-11-29T15:00:00Z","x":"V"}"#;
+    let message = json_from_str::<DataMessage<Raw<Bar>, Raw<Quote>, Raw<Trade>>>(json).unwrap();
+    let trade = match &message {
+      DataMessage::Trade(trade) => trade,
+      _ => panic!("Decoded unexpected message variant: {message:?}"),
+    };
+    assert_eq!(trade.parsed.symbol, "SPY");
+    assert_eq!(trade.parsed.trade_id, 1);
+    assert_eq!(trade.raw["x"], "V");
+    assert_eq!(trade.raw["p"], 400.1);
+    assert_eq!(trade.raw.get("T"), None);
+
+    assert_eq!(
+      json_from_str::<DataMessage<Raw<Bar>, Raw<Quote>, Raw<Trade>>>(&to_json(&message).unwrap())
+        .unwrap(),
+      message
+    );
+  }
+
+  /// Check that we can serialize and deserialize the
+  /// [`DataMessage::Success`] variant.
+  #[test]
+  fn serialize_deserialize_success() {
+    let json = r#"{"T":"success","msg":"authenticated"}"#;
+    let message = json_from_str::<DataMessage>(json).unwrap();
+    let () = match message {
+      DataMessage::Success { ref message } => assert_eq!(message, "authenticated"),
+      _ => panic!("Decoded unexpected message variant: {message:?}"),
+    };
+
+    assert_eq!(
+      json_from_str::<DataMessage>(&to_json(&message).unwrap()).unwrap(),
+      message
+    );
+  }
+
+  /// Check that we can classify data messages without a stream.
+  #[test]
+  fn classify_data_messages() {
+    let messages = json_from_str::<Vec<DataMessage>>(BAR_RESP).unwrap();
+    for message in messages {
+      assert!(matches!(
+        classify_message(message),
+        Classified::Data(Data::Bar { .. })
+      ));
+    }
+
+    let messages = json_from_str::<Vec<DataMessage>>(SUB_ERR_RESP).unwrap();
+    for message in messages {
+      assert!(matches!(
+        classify_message(message),
+        Classified::Control(ControlMessage::Error(..))
+      ));
+    }
+  }
+
+  /// Check that we classify errors reported by the stream API based on
+  /// their code.
+  #[test]
+  fn classify_stream_errors() {
+    let error = |code| StreamApiError {
+      code,
+      message: "error".to_string(),
+    };
+
+    assert!(matches!(
+      StreamError::from(error(400)),
+      StreamError::InvalidSyntax(..)
+    ));
+    assert!(matches!(
+      StreamError::from(error(401)),
+      StreamError::NotAuthenticated(..)
+    ));
+    assert!(matches!(
+      StreamError::from(error(402)),
+      StreamError::AuthenticationFailed(..)
+    ));
+    assert!(matches!(
+      StreamError::from(error(403)),
+      StreamError::AlreadyAuthenticated(..)
+    ));
+    assert!(matches!(
+      StreamError::from(error(406)),
+      StreamError::ConnectionLimitExceeded(..)
+    ));
+    for code in [405, 409, 410] {
+      assert!(matches!(
+        StreamError::from(error(code)),
+        StreamError::InvalidSubscription(..)
+      ));
+    }
+    assert!(matches!(
+      StreamError::from(error(500)),
+      StreamError::InternalError(..)
+    ));
+    assert_eq!(
+      StreamError::from(error(999)),
+      StreamError::Other(error(999))
+    );
+  }
+
+  /// Check that we can deserialize messages of unknown type into the
+  /// [`DataMessage::Unknown`] variant.
+  #[test]
+  fn serialize_deserialize_unknown() {
+    let json = r#"{"T":"x","foo":42}"#;
+    let message = json_from_str::<DataMessage>(json).unwrap();
+    let value = match &message {
+      DataMessage::Unknown(value) => value,
+      _ => panic!("Decoded unexpected message variant: {message:?}"),
+    };
+    assert_eq!(value["T"], "x");
+    assert_eq!(to_json(&message).unwrap(), json);
+
+    assert!(matches!(
+      classify_message(message),
+      Classified::Control(ControlMessage::Unknown(..))
+    ));
+
+    // A malformed message of known type should still fail to decode.
+    let json = r#"{"T":"b","S":"AAPL"}"#;
+    let _err = json_from_str::<DataMessage>(json).unwrap_err();
+  }
+
+  /// Check that we can deserialize messages whose `"T"` tag is not
+  /// the first field.
+  #[test]
+  fn deserialize_late_tag() {
+    let json = r#"{"S":"AAPL","o":1,"h":2,"l":1,"c":2,"v":10,"t":"2021-02-22T19:15:00Z","T":"b"}"#;
+    let message = json_from_str::<DataMessage>(json).unwrap();
+    let bar = match message {
+      DataMessage::Bar(bar) => bar,
+      _ => panic!("Decoded unexpected message variant: {message:?}"),
+    };
+    assert_eq!(bar.symbol, "AAPL");
+
+    let json = r#"{"msg":"authenticated","T":"success"}"#;
+    let message = json_from_str::<DataMessage>(json).unwrap();
+    assert_eq!(
+      message,
+      DataMessage::Success {
+        message: "authenticated".to_string()
+      }
+    );
+
+    let json = r#"{"foo":42,"T":"x"}"#;
+    let message = json_from_str::<DataMessage>(json).unwrap();
+    assert!(matches!(message, DataMessage::Unknown(..)));
+
+    let json = r#"{"foo":42}"#;
+    let message = json_from_str::<DataMessage>(json).unwrap();
+    assert!(matches!(message, DataMessage::Unknown(..)));
+  }
+
+  /// Check that a websocket message containing a batch of data
+  /// messages is decoded into all of them, in order.
+  #[test]
+  fn parse_message_batch() {
+    let json = r#"[
+      {"T":"b","S":"AAPL","o":1,"h":2,"l":1,"c":2,"v":10,"t":"2021-02-22T19:15:00Z"},
+      {"T":"success","msg":"authenticated"}
+    ]"#;
+    let message = wrap::Message::Text(json.to_string());
+    let messages = parse_messages::<DataMessage>(Ok(message)).unwrap().unwrap();
+    assert_eq!(messages.len(), 2);
+    assert!(matches!(&messages[0], DataMessage::Bar(bar) if bar.symbol == "AAPL"));
+    assert!(matches!(
+      &messages[1],
+      DataMessage::Success { message } if message == "authenticated"
+    ));
+
+    let message = wrap::Message::Binary(json.as_bytes().to_vec());
+    let binary = parse_messages::<DataMessage>(Ok(message)).unwrap().unwrap();
+    assert_eq!(binary, messages);
+  }
+
+  /// Check that we can serialize and deserialize the
+  /// [`DataMessage::Error`] variant.
+  #[test]
+  fn serialize_deserialize_error() {
+    let json = r#"{"T":"error","code":400,"msg":"invalid syntax"}"#;
+    let message = json_from_str::<DataMessage>(json).unwrap();
+    let error = match &message {
+      DataMessage::Error(error) => error,
+      _ => panic!("Decoded unexpected message variant: {message:?}"),
+    };
+
+    assert_eq!(error.code, 400);
+    assert_eq!(error.message, "invalid syntax");
+
+    assert_eq!(
+      json_from_str::<DataMessage>(&to_json(&message).unwrap()).unwrap(),
+      message
+    );
+
+    let json = r#"{"T":"error","code":500,"msg":"internal error"}"#;
+    let message = json_from_str::<DataMessage>(json).unwrap();
+    let error = match &message {
+      DataMessage::Error(error) => error,
+      _ => panic!("Decoded unexpected message variant: {message:?}"),
+    };
+
+    assert_eq!(error.code, 500);
+    assert_eq!(error.message, "internal error");
+
+    assert_eq!(
+      json_from_str::<DataMessage>(&to_json(&message).unwrap()).unwrap(),
+      message
+    );
+  }
+
+  /// Check that we can serialize and deserialize the
+  /// [`Request::Authenticate`] variant properly.
+  #[test]
+  fn serialize_deserialize_authentication_request() {
+    let request = Request::Authenticate {
+      key_id: "KEY-ID".into(),
+      secret: "SECRET-KEY".into(),
+    };
+    let json = to_json(&request).unwrap();
+    let expected = r#"{"action":"auth","key":"KEY-ID","secret":"SECRET-KEY"}"#;
+    assert_eq!(json, expected);
+    assert_eq!(json_from_str::<Request<'_>>(&json).unwrap(), request);
+  }
+
+  /// Check that we can serialize and deserialize the
+  /// [`Request::Subscribe`] variant properly.
+  #[test]
+  fn serialize_deserialize_subscribe_request() {
+    let mut data = MarketData::default();
+    data.set_bars(["AAPL", "VOO"]);
+    let request = Request::Subscribe(Cow::Borrowed(&data));
+
+    let json = to_json(&request).unwrap();
+    let expected = r#"{"action":"subscribe","bars":["AAPL","VOO"]}"#;
+    assert_eq!(json, expected);
+    assert_eq!(json_from_str::<Request<'_>>(&json).unwrap(), request);
+  }
+
+  /// Check that minute and daily bars can be subscribed to in a single
+  /// request, using separate channels.
+  #[test(tokio::test)]
+  async fn subscribe_bars_and_daily_bars() {
+    let mut respond = respond();
+    let (mut stream, mut subscription, _injector) = mock_subscription(move |request| {
+      let json = to_json(&request).unwrap();
+      let expected = r#"{"action":"subscribe","bars":["SPY"],"dailyBars":["AAPL","MSFT"]}"#;
+      assert_eq!(json, expected);
+      respond(request)
+    });
+
+    let data = MarketData::builder()
+      .bars(["SPY"])
+      .daily_bars(["MSFT", "AAPL"])
+      .build();
+    let subscribe = subscription.subscribe(&data).boxed_local();
+    let subscribed = drive(subscribe, &mut stream)
+      .await
+      .unwrap()
+      .unwrap()
+      .unwrap();
+    assert_eq!(subscribed, data);
+    assert_eq!(subscribed.bars, ["SPY"]);
+    assert_eq!(subscribed.daily_bars, ["AAPL", "MSFT"]);
+
+    let json = r#"{"T":"d","S":"AAPL","o":142.61,"h":148.02,"l":141.96,"c":147.67,"v":1832,"t":"2022-11-29T05:00:00Z"}"#;
+    let message = json_from_str::<DataMessage>(json).unwrap();
+    assert!(matches!(
+      classify_message(message),
+      Classified::Data(Data::Bar {
+        kind: BarKind::Daily,
+        ..
+      })
+    ));
+  }
+
+  /// Check that updated bars are decoded and classified as such.
+  #[test]
+  fn classify_updated_bar() {
+    let json = r#"{"T":"u","S":"AAPL","o":142.61,"h":148.02,"l":141.96,"c":147.67,"v":1832,"t":"2022-11-29T15:00:00Z"}"#;
+    let message = json_from_str::<DataMessage>(json).unwrap();
+    assert!(matches!(&message, DataMessage::UpdatedBar(..)));
+    assert_eq!(message.kind(), MessageKind::UpdatedBar);
+
+    let data = match classify_message(message) {
+      Classified::Data(data) => data,
+      classified => panic!("unexpected classification: {classified:?}"),
+    };
+    assert!(data.is_bar());
+    assert!(!data.is_daily_bar());
+    assert_eq!(data.symbol(), "AAPL");
+    assert!(matches!(
+      data,
+      Data::Bar {
+        kind: BarKind::Updated,
+        ..
+      }
+    ));
+  }
+
+  /// Check that interned symbols share their allocation.
+  #[test]
+  fn deserialize_interned_symbols() {
+    type Message = DataMessage<Bar<InternedSymbol>, Quote, Trade<InternedSymbol>>;
+
+    let json = r#"[
+      {"T":"b","S":"SPY","o":388.985,"h":389.13,"l":388.975,"c":389.12,"v":49378,"t":"2021-02-22T19:15:00Z"},
+      {"T":"t","S":"SPY","i":1,"x":"V","p":414.97,"s":100,"t":"2022-03-11T14:31:23.4Z"},
+      {"T":"b","S":"AAPL","o":388.985,"h":389.13,"l":388.975,"c":389.12,"v":49378,"t":"2021-02-22T19:15:00Z"}
+    ]"#;
+    let interner = Interner::default();
+    let () = interner.update(&MarketData::with_bars(["SPY"]));
+    let seeded = InternedSymbol(interner.intern("SPY"));
+
+    let messages = interner.enter(|| json_from_str::<Vec<Message>>(json).unwrap());
+    let symbols = messages
+      .iter()
+      .map(|message| match message {
+        DataMessage::Bar(bar) => bar.symbol.clone(),
+        DataMessage::Trade(trade) => trade.symbol.clone(),
+        message => panic!("unexpected message: {message:?}"),
+      })
+      .collect::<Vec<_>>();
+
+    assert_eq!(symbols[0], "SPY");
+    assert_eq!(symbols[2], "AAPL");
+    assert!(symbols[0].ptr_eq(&symbols[1]));
+    assert!(!symbols[0].ptr_eq(&symbols[2]));
+    assert!(symbols[0].ptr_eq(&seeded));
+    assert!(!symbols[0].ptr_eq(&InternedSymbol::new("SPY")));
+
+    // Outside of the interner's scope symbols are not shared.
+    let message = json_from_str::<Message>(&to_json(&messages[0]).unwrap()).unwrap();
+    match message {
+      DataMessage::Bar(bar) => assert!(!bar.symbol.ptr_eq(&symbols[0])),
+      message => panic!("unexpected message: {message:?}"),
+    }
+
+    // Symbols no longer subscribed to are dropped from the interner.
+    let () = interner.update(&MarketData::with_bars(["AAPL"]));
+    assert!(!symbols[0].ptr_eq(&InternedSymbol(interner.intern("SPY"))));
+    assert!(symbols[2].ptr_eq(&InternedSymbol(interner.intern("AAPL"))));
+
+    let json = to_json(&messages[0]).unwrap();
+    assert!(json.contains(r#""S":"SPY""#));
+    assert_eq!(json_from_str::<Message>(&json).unwrap(), messages[0]);
+  }
+
+  /// Check that we can parse a single message from raw bytes, both
+  /// strictly and leniently.
+  #[test]
+  fn parse_single_message() {
+    let json =
+      br#"{"T":"t","S":"SPY","i":1,"x":"V","p":414.97,"s":100,"t":"2022-03-11T14:31:23.4Z"}"#;
+    let message = parse_message::<Bar, Quote, Trade>(json).unwrap();
+    assert_eq!(message.kind(), MessageKind::Trade);
+
+    let mut padded = json.to_vec();
+    padded.extend_from_slice(b" \n");
+    assert!(parse_message::<Bar, Quote, Trade>(&padded).is_ok());
+
+    let mut trailing = json.to_vec();
+    trailing.extend_from_slice(b"\x1e{}");
+    assert!(parse_message::<Bar, Quote, Trade>(&trailing).is_err());
+    let message = parse_message_lenient::<Bar, Quote, Trade>(&trailing).unwrap();
+    assert_eq!(message.kind(), MessageKind::Trade);
+
+    assert!(parse_message_lenient::<Bar, Quote, Trade>(b"").is_err());
+  }
+
+  /// Check that we can deserialize and classify a limit up/limit down
+  /// message.
+  #[test]
+  fn classify_luld() {
+    let json = r#"{"T":"l","S":"AAPL","u":135.49,"d":122.59,"i":"B","t":"2021-07-05T13:32:21.567812Z","z":"C"}"#;
+    let message = json_from_str::<DataMessage>(json).unwrap();
+    assert_eq!(message.kind(), MessageKind::Luld);
+
+    let data = match classify_message(message) {
+      Classified::Data(data) => data,
+      classified => panic!("unexpected classification: {classified:?}"),
+    };
+    assert!(data.is_luld());
+    assert_eq!(data.symbol(), "AAPL");
+
+    let luld = match data {
+      Data::Luld(luld) => luld,
+      data => panic!("unexpected data: {data:?}"),
+    };
+    assert_eq!(luld.limit_up_price, Num::new(13549, 100));
+    assert_eq!(luld.limit_down_price, Num::new(12259, 100));
+    assert_eq!(luld.indicator, "B");
+    assert_eq!(luld.tape, "C");
+    assert_eq!(
+      luld.timestamp,
+      DateTime::<Utc>::from_str("2021-07-05T13:32:21.567812Z").unwrap()
+    );
+  }
+
+  /// Check that limit up/limit down symbols are included in
+  /// subscription requests.
+  #[test]
+  fn serialize_lulds_subscription() {
+    let data = MarketData::default();
+    let json = to_json(&Request::Subscribe(Cow::Borrowed(&data))).unwrap();
+    assert_eq!(json, r#"{"action":"subscribe"}"#);
+
+    let mut data = MarketData::default();
+    data.set_lulds(["AAPL"]);
+    let json = to_json(&Request::Subscribe(Cow::Borrowed(&data))).unwrap();
+    assert_eq!(json, r#"{"action":"subscribe","lulds":["AAPL"]}"#);
+    assert_eq!(data.counts().lulds, data.lulds.count());
+  }
+
+  /// Check that we can serialize and deserialize the
+  /// [`Request::Subscribe`] variant properly.
+  #[test]
+  fn serialize_deserialize_unsubscribe_request() {
+    let mut data = MarketData::default();
+    data.set_bars(["VOO"]);
+    let request = Request::Unsubscribe(Cow::Borrowed(&data));
+
+    let json = to_json(&request).unwrap();
+    let expected = r#"{"action":"unsubscribe","bars":["VOO"]}"#;
+    assert_eq!(json, expected);
+    assert_eq!(json_from_str::<Request<'_>>(&json).unwrap(), request);
+  }
+
+  /// Check that we can correctly deserialize a `SymbolList` object.
+  #[test]
+  fn deserialize_symbol_list() {
+    let json = r#"["AAPL","XLK","SPY"]"#;
+    let list = json_from_str::<SymbolList>(json).unwrap();
+    let expected = SymbolList::from(["AAPL", "SPY", "XLK"]);
+    assert_eq!(list, expected);
+  }
+
+  /// Check that we can deserialize market data into `f64` based
+  /// types.
+  #[cfg(feature = "float-data")]
+  #[test]
+  fn deserialize_float_data() {
+    let response = r#"[
+      {"T":"b","S":"SPY","o":415.05,"h":415.1,"l":414.98,"c":415.0,"v":123,"t":"2022-11-29T15:12:00Z"},
+      {"T":"q","S":"SPY","bp":415.0,"bs":1,"ap":415.1,"as":2,"t":"2022-11-29T15:12:00Z"},
+      {"T":"t","S":"SPY","i":1,"p":415.02,"s":10,"t":"2022-11-29T15:12:00Z"}
+    ]"#;
+    let messages =
+      json_from_str::<Vec<DataMessage<float::Bar, float::Quote, float::Trade>>>(response).unwrap();
+
+    match &messages[0] {
+      DataMessage::Bar(bar) => {
+        assert_eq!(bar.open_price, 415.05);
+        assert_eq!(bar.volume, 123.0);
+      },
+      message => panic!("unexpected message: {message:?}"),
+    }
+    match &messages[1] {
+      DataMessage::Quote(quote) => assert_eq!(quote.ask_price, 415.1),
+      message => panic!("unexpected message: {message:?}"),
+    }
+    match &messages[2] {
+      DataMessage::Trade(trade) => assert_eq!(trade.trade_price, 415.02),
+      message => panic!("unexpected message: {message:?}"),
+    }
+
+    let json = to_json(&messages).unwrap();
+    let parsed = json_from_str::<Vec<DataMessage<float::Bar, float::Quote, float::Trade>>>(&json);
+    assert_eq!(parsed.unwrap().len(), 3);
+
+    // Time stamps may also be provided as nanoseconds since the Unix
+    // epoch.
+    let response = r#"[
+      {"T":"b","S":"SPY","o":415.05,"h":415.1,"l":414.98,"c":415.0,"v":123,"t":1669734720000000000},
+      {"T":"q","S":"SPY","bp":415.0,"bs":1,"ap":415.1,"as":2,"t":1669734720000000000},
+      {"T":"t","S":"SPY","i":1,"p":415.02,"s":10,"t":1669734720000000000}
+    ]"#;
+    let expected = DateTime::<Utc>::from_str("2022-11-29T15:12:00Z").unwrap();
+    let messages =
+      json_from_str::<Vec<DataMessage<float::Bar, float::Quote, float::Trade>>>(response).unwrap();
+    let timestamps = messages
+      .iter()
+      .map(|message| match message {
+        DataMessage::Bar(bar) => bar.timestamp,
+        DataMessage::Quote(quote) => quote.timestamp,
+        DataMessage::Trade(trade) => trade.timestamp,
+        message => panic!("unexpected message: {message:?}"),
+      })
+      .collect::<Vec<_>>();
+    assert_eq!(timestamps, [expected; 3]);
+  }
+
+  /// Check that we can retrieve a bar's prices and volume as `f64`.
+  #[test]
+  fn bar_f64_accessors() {
+    let bar = Bar {
+      symbol: "SPY".to_string(),
+      open_price: Num::new(41505, 100),
+      high_price: Num::new(4151, 10),
+      low_price: Num::new(41498, 100),
+      close_price: Num::from(415),
+      volume: Num::from(12345),
+      timestamp: DateTime::from_str("2021-02-22T19:15:00Z").unwrap(),
+    };
+
+    assert_eq!(bar.open_f64(), 415.05);
+    assert_eq!(bar.high_f64(), 415.1);
+    assert_eq!(bar.low_f64(), 414.98);
+    assert_eq!(bar.close_f64(), 415.0);
+    assert_eq!(bar.volume_f64(), 12345.0);
+  }
+
+  /// Check that we can collect symbols into normalized `SymbolList`
+  /// and `Symbols` objects.
+  #[test]
+  fn collect_symbols() {
+    let symbols = ["VOO", "AAPL", "VOO", "MSFT"]
+      .into_iter()
+      .map(Symbol::from)
+      .collect::<SymbolList>();
+    assert_eq!(symbols, SymbolList::from(["AAPL", "MSFT", "VOO"]));
+
+    let symbols = ["VOO", "AAPL", "AAPL"]
+      .into_iter()
+      .map(Symbol::from)
+      .collect::<Symbols>();
+    assert_eq!(symbols, Symbols::List(SymbolList::from(["AAPL", "VOO"])));
+
+    let symbols = ["VOO", "*", "AAPL"]
+      .into_iter()
+      .map(Symbol::from)
+      .collect::<Symbols>();
+    assert_eq!(symbols, Symbols::All);
+
+    let symbols = Vec::<Symbol>::new().into_iter().collect::<Symbols>();
+    assert_eq!(symbols, Symbols::default());
+
+    // Owned strings can be collected without converting them first.
+    let watchlist = ["SPY".to_string(), "AAPL".to_string()];
+    let symbols = watchlist
+      .iter()
+      .cloned()
+      .chain(["MSFT".to_string()])
+      .collect::<SymbolList>();
+    assert_eq!(symbols, ["AAPL", "MSFT", "SPY"]);
+
+    let symbols = ["XLK", "SPY"].into_iter().collect::<SymbolList>();
+    assert_eq!(symbols, ["SPY", "XLK"]);
+
+    let symbols = [
+      Symbol::from("XLK"),
+      Symbol::from("SPY"),
+      Symbol::from("XLK"),
+    ];
+    assert_eq!(SymbolList::from(&symbols[..]), ["SPY", "XLK"]);
+    assert_eq!(SymbolList::from(symbols.to_vec()), ["SPY", "XLK"]);
+  }
+
+  /// Check that normalizing an owned list of symbols reuses the
+  /// underlying buffer.
+  #[test]
+  fn normalize_reuses_buffer() {
+    let symbols = vec![Symbol::from("SPY"), "MSFT".into(), "MSFT".into()];
+    let ptr = symbols.as_ptr();
+    let list = SymbolList::from(symbols);
+    assert_eq!(list.as_ptr(), ptr);
+    assert_eq!(list.deref(), [Cow::from("MSFT"), "SPY".into()]);
+  }
+
+  /// Check that we can serialize and deserialize a [`MarketData`]
+  /// object.
+  #[test]
+  fn serialize_deserialize_market_data() {
+    let mut data = MarketData::all_quotes();
+    data.set_bars(["SPY", "AAPL"]);
+    data.set_trades(["MSFT"]);
+
+    let json = to_json(&data).unwrap();
+    assert_eq!(json_from_str::<MarketData>(&json).unwrap(), data);
+
+    // Lists of symbols should be normalized and missing fields
+    // default to no symbols.
+    let json = r#"{"bars":["SPY","AAPL","SPY"],"trades":["*"]}"#;
+    let data = json_from_str::<MarketData>(json).unwrap();
+    let mut expected = MarketData::all_trades();
+    expected.set_bars(["AAPL", "SPY"]);
+    assert_eq!(data, expected);
+  }
+
+  /// Check that a list of symbols containing the wildcard is
+  /// deserialized as representing all symbols.
+  #[test]
+  fn deserialize_symbols_with_wildcard() {
+    let symbols = json_from_str::<Symbols>(r#"["*"]"#).unwrap();
+    assert_eq!(symbols, Symbols::All);
+
+    let symbols = json_from_str::<Symbols>(r#"["AAPL","*"]"#).unwrap();
+    assert_eq!(symbols, Symbols::All);
+
+    let symbols = json_from_str::<Symbols>(r#"["SPY","AAPL"]"#).unwrap();
+    assert_eq!(symbols, Symbols::List(SymbolList::from(["AAPL", "SPY"])));
+  }
+
+  /// Check that we can inspect `Symbols` objects using `Symbol` values
+  /// as well as plain strings.
+  #[test]
+  fn symbols_lookup() {
+    let symbols = Symbols::List(SymbolList::from(["AAPL", "MSFT"]));
+    assert_eq!(symbols.as_list().unwrap(), &["AAPL", "MSFT"]);
+    assert!(symbols.contains("AAPL"));
+    assert!(symbols.contains(Symbol::from("MSFT")));
+    assert!(symbols.contains(String::from("MSFT")));
+    assert!(!symbols.contains(Symbol::from("SPY")));
+
+    assert_eq!(Symbols::All.as_list(), None);
+    assert!(Symbols::All.contains(Symbol::from("SPY")));
+  }
+
+  /// Check that `SymbolList` and `Symbols` objects serialize as flat
+  /// JSON arrays and can be deserialized from them.
+  #[test]
+  fn serialize_deserialize_symbols() {
+    let list = SymbolList::from(["AAPL", "MSFT"]);
+    let json = to_json(&list).unwrap();
+    assert_eq!(json, r#"["AAPL","MSFT"]"#);
+    assert_eq!(json_from_str::<SymbolList>(&json).unwrap(), list);
+
+    let symbols = Symbols::List(list);
+    let json = to_json(&symbols).unwrap();
+    assert_eq!(json, r#"["AAPL","MSFT"]"#);
+    assert_eq!(json_from_str::<Symbols>(&json).unwrap(), symbols);
+
+    let json = to_json(&Symbols::All).unwrap();
+    assert_eq!(json, r#"["*"]"#);
+    assert_eq!(json_from_str::<Symbols>(&json).unwrap(), Symbols::All);
+  }
+
+  /// Check that we can create a `SymbolList` from symbols in arbitrary
+  /// casing.
+  #[test]
+  fn symbol_list_from_case_insensitive() {
+    let list = SymbolList::from_case_insensitive(["aapl", "AAPL", "Spy", "btc/usd"]);
+    let expected = SymbolList::from(["AAPL", "BTC/USD", "SPY"]);
+    assert_eq!(list, expected);
+  }
+
+  /// Check invariants of symbol normalization on a large number of
+  /// pseudo-randomly generated symbol lists.
+  ///
+  /// This test is a poor man's property based test: we use a fixed seed
+  /// to keep it deterministic.
+  #[test]
+  fn normalize_properties() {
+    const POOL: [&str; 8] = ["*", "AAPL", "BTC/USD", "MSFT", "SPY", "VOO", "aapl", ""];
+
+    // A simple xorshift generator, sufficient for our needs.
+    let mut state = 0x2545_f491_4f6c_dd1d_u64;
+    let mut next = move || {
+      state ^= state << 13;
+      state ^= state >> 7;
+      state ^= state << 17;
+      state
+    };
+
+    for _ in 0..1000 {
+      let len = (next() % 12) as usize;
+      let symbols = (0..len)
+        .map(|_| Symbol::from(POOL[(next() % POOL.len() as u64) as usize]))
+        .collect::<Vec<_>>();
+
+      let normalized = normalize(Cow::Owned(symbols.clone()));
+      assert!(is_normalized(&normalized), "{symbols:?}");
+      assert_eq!(normalize(normalized.clone()), normalized);
+
+      let expected = symbols.iter().collect::<BTreeSet<_>>();
+      let actual = normalized.iter().collect::<BTreeSet<_>>();
+      assert_eq!(actual, expected);
+
+      let collected = symbols.iter().cloned().collect::<Symbols>();
+      if symbols.iter().any(|symbol| symbol == "*") {
+        assert_eq!(collected, Symbols::All);
+      } else {
+        assert_eq!(collected, Symbols::List(SymbolList::from(symbols)));
+      }
+    }
+  }
+
+  /// Check that we can create a `SymbolList` from a string of comma
+  /// separated symbols.
+  #[test]
+  fn symbol_list_from_csv_str() {
+    let list = SymbolList::from_csv_str(" SPY, QQQ,,DIA ,SPY,");
+    assert_eq!(list, ["DIA", "QQQ", "SPY"]);
+
+    assert!(SymbolList::from_csv_str("").is_empty());
+    assert!(SymbolList::from_csv_str(" , ").is_empty());
+  }
+
+  /// Check that we can read a `SymbolList` from a watch list.
+  #[test]
+  fn symbol_list_from_reader() {
+    let watchlist = b"# Index funds\nSPY, QQQ\n\nDIA  IWM # small caps\n  AAPL\r\nSPY,\n#MSFT\n";
+    let list = SymbolList::from_reader(&watchlist[..]).unwrap();
+    assert_eq!(list, ["AAPL", "DIA", "IWM", "QQQ", "SPY"]);
+
+    assert!(SymbolList::from_reader(&b""[..]).unwrap().is_empty());
+
+    let err = SymbolList::from_reader(&b"SPY\n\xff\n"[..]).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+  }
+
+  /// Check that we can normalize `Symbol` slices.
+  #[test]
+  fn normalize_subscriptions() {
+    let subscriptions = [];
+    assert!(is_normalized(&subscriptions));
+
+    let subscriptions = ["SPY".into()];
+    assert!(is_normalized(&subscriptions));
+
+    let subscriptions = ["MSFT".into(), "SPY".into()];
+    assert!(is_normalized(&subscriptions));
+
+    let subscriptions = ["MSFT".into(), "MSFT".into(), "SPY".into()];
+    assert!(!is_normalized(&subscriptions));
+
+    let mut subscriptions = Cow::from(vec!["SPY".into(), "MSFT".into()]);
+    assert!(!is_normalized(&subscriptions));
+    subscriptions = normalize(subscriptions);
+    assert!(is_normalized(&subscriptions));
+
+    let expected = [Cow::from("MSFT"), "SPY".into()];
+    assert_eq!(subscriptions.borrow(), expected);
+
+    let mut subscriptions = Cow::from(vec!["SPY".into(), "MSFT".into(), "MSFT".into()]);
+    assert!(!is_normalized(&subscriptions));
+    subscriptions = normalize(subscriptions);
+    assert!(is_normalized(&subscriptions));
+
+    assert_eq!(subscriptions.borrow(), expected);
+  }
+
+  /// Check that we can compare `SymbolList` and `Symbols` objects
+  /// against plain lists of symbols.
+  #[test]
+  fn compare_symbols_with_lists() {
+    let list = SymbolList::from(["SPY", "AAPL"]);
+    assert_eq!(list, ["AAPL", "SPY"]);
+    assert_eq!(list, ["AAPL", "SPY"][..]);
+    assert_eq!(list, vec![Symbol::from("AAPL"), Symbol::from("SPY")]);
+    assert_eq!(list, [Symbol::from("AAPL"), Symbol::from("SPY")][..]);
+    assert_ne!(list, ["SPY", "AAPL"]);
+    assert_ne!(list, ["AAPL"]);
+
+    let mut data = MarketData::default();
+    data.set_bars(["MSFT", "AAPL"]);
+    assert_eq!(data.bars, ["AAPL", "MSFT"]);
+    assert_eq!(data.quotes, []);
+    assert_ne!(MarketData::all_bars().bars, ["*"]);
+
+    // The wildcard is normalized to `Symbols::All`, no matter how the
+    // list of symbols is provided.
+    data.set_quotes(["SPY", "*"]);
+    assert_eq!(data.quotes, Symbols::All);
+    assert_eq!(MarketData::with_trades(["*"]).trades, Symbols::All);
+    assert_eq!(Symbols::from(SymbolList::from(["*"])), Symbols::All);
+  }
+
+  /// Check that we can correctly handle a successful subscription
+  /// without pushing actual data.
+  #[test(tokio::test)]
+  async fn authenticate_and_subscribe() {
+    async fn test(mut stream: WebSocketStream) -> Result<(), WebSocketError> {
+      stream.send(Message::Text(CONN_RESP.to_string())).await?;
+      // Authentication.
+      assert_eq!(
+        stream.next().await.unwrap()?,
+        Message::Text(AUTH_REQ.to_string()),
+      );
+      stream.send(Message::Text(AUTH_RESP.to_string())).await?;
+
+      // Subscription.
+      assert_eq!(
+        stream.next().await.unwrap()?,
+        Message::Text(SUB_REQ.to_string()),
+      );
+      stream.send(Message::Text(SUB_RESP.to_string())).await?;
+      stream.send(Message::Close(None)).await?;
+      Ok(())
+    }
+
+    let (mut stream, mut subscription) =
+      mock_stream::<RealtimeData<IEX>, _, _>(test).await.unwrap();
+
+    let mut data = MarketData::default();
+    data.set_bars(["AAPL", "VOO"]);
+
+    let subscribe = subscription.subscribe(&data).boxed_local();
+    let _data = drive(subscribe, &mut stream)
+      .await
+      .unwrap()
+      .unwrap()
+      .unwrap();
+
+    stream
+      .map_err(Error::WebSocket)
+      .try_for_each(|result| async { result.map(|_data| ()).map_err(Error::Json) })
+      .await
+      .unwrap();
+  }
+
+  /// Check that we can authenticate and subscribe over a user-provided
+  /// stream.
+  #[test(tokio::test)]
+  async fn authenticate_and_subscribe_over_custom_stream() {
+    let (client, server) = duplex(4096);
+
+    let server = spawn(async move {
+      let mut stream = accept_async(server).await?;
+      stream.send(Message::Text(CONN_RESP.to_string())).await?;
+      // Authentication.
+      assert_eq!(
+        stream.next().await.unwrap()?,
+        Message::Text(AUTH_REQ.to_string()),
+      );
+      stream.send(Message::Text(AUTH_RESP.to_string())).await?;
+
+      // Subscription.
+      assert_eq!(
+        stream.next().await.unwrap()?,
+        Message::Text(SUB_REQ.to_string()),
+      );
+      stream.send(Message::Text(SUB_RESP.to_string())).await?;
+      stream.send(Message::Close(None)).await?;
+      // Unlike with a TCP socket, writes to a dropped in-memory stream
+      // fail right away. Hence, wait for the client to acknowledge
+      // closing of the connection before dropping our end.
+      while let Some(Ok(..)) = stream.next().await {}
+      Ok::<_, WebSocketError>(())
+    });
+
+    let api_info = ApiInfo::from_parts(API_BASE_URL, KEY_ID, SECRET).unwrap();
+    let (mut stream, mut subscription) =
+      RealtimeData::<IEX>::connect_with(client, &api_info, StreamConfig::default())
+        .await
+        .unwrap();
+
+    let mut data = MarketData::default();
+    data.set_bars(["AAPL", "VOO"]);
+
+    let subscribe = subscription.subscribe(&data).boxed_local();
+    let _data = drive(subscribe, &mut stream)
+      .await
+      .unwrap()
+      .unwrap()
+      .unwrap();
+
+    assert_eq!(subscription.subscriptions(), &data);
+
+    stream
+      .map_err(Error::WebSocket)
+      .try_for_each(|result| async { result.map(|_data| ()).map_err(Error::Json) })
+      .await
+      .unwrap();
+
+    let () = server.await.unwrap().unwrap();
+  }
+
+  /// Check that connecting fails if the server does not respond to
+  /// our authentication request within the configured control timeout.
+  #[test(tokio::test)]
+  async fn authenticate_timeout() {
+    let (client, server) = duplex(4096);
+
+    let server = spawn(async move {
+      let mut stream = accept_async(server).await?;
+      stream.send(Message::Text(CONN_RESP.to_string())).await?;
+      assert_eq!(
+        stream.next().await.unwrap()?,
+        Message::Text(AUTH_REQ.to_string()),
+      );
+      // Never respond and just wait for the client to go away.
+      while let Some(Ok(..)) = stream.next().await {}
+      Ok::<_, WebSocketError>(())
+    });
+
+    let api_info = ApiInfo::from_parts(API_BASE_URL, KEY_ID, SECRET).unwrap();
+    let config = StreamConfig::default().with_control_timeout(Some(Duration::from_millis(10)));
+    let err = RealtimeData::<IEX>::connect_with(client, &api_info, config)
+      .await
+      .unwrap_err();
+    match err {
+      Error::Str(message) => assert_eq!(message, "authentication timed out"),
+      err => panic!("received unexpected error: {err}"),
+    }
+
+    let _result = server.await.unwrap();
+  }
+
+  /// Check that decoding errors are reported unchanged when the
+  /// offending payload is configured to be reported along with them.
+  #[test(tokio::test)]
+  async fn decode_error_with_payload() {
+    const INVALID_BAR: &str = r#"[{"T":"b","S":"AAPL"}]"#;
+
+    let (client, server) = duplex(4096);
+    let (connected, wait) = oneshot::channel();
+
+    let server = spawn(async move {
+      let mut stream = accept_async(server).await?;
+      stream.send(Message::Text(CONN_RESP.to_string())).await?;
+      // Authentication.
+      assert_eq!(
+        stream.next().await.unwrap()?,
+        Message::Text(AUTH_REQ.to_string()),
+      );
+      stream.send(Message::Text(AUTH_RESP.to_string())).await?;
+      // Only send the invalid message once the client is done
+      // connecting, as it would otherwise fail the connection attempt.
+      let () = wait.await.unwrap();
+      stream.send(Message::Text(INVALID_BAR.to_string())).await?;
+      stream.send(Message::Close(None)).await?;
+      while let Some(Ok(..)) = stream.next().await {}
+      Ok::<_, WebSocketError>(())
+    });
+
+    let api_info = ApiInfo::from_parts(API_BASE_URL, KEY_ID, SECRET).unwrap();
+    let config = StreamConfig::default().with_payload_in_errors(true);
+    let (mut stream, _subscription) = RealtimeData::<IEX>::connect_with(client, &api_info, config)
+      .await
+      .unwrap();
+    let () = connected.send(()).unwrap();
+
+    // The original error, including its position and category, is
+    // reported; the payload is only logged.
+    match stream.next().await.unwrap() {
+      Ok(Err(err)) => {
+        assert!(err.is_data(), "{err}");
+        assert_eq!(err.line(), 1);
+        assert_ne!(err.column(), 0);
+      },
+      result => panic!("received unexpected result: {result:?}"),
+    }
+
+    while stream.next().await.is_some() {}
+    let () = server.await.unwrap().unwrap();
+  }
+
+  /// Check that the stream reports an error if the server fails to
+  /// respond to pings sent at the configured interval.
+  #[test(tokio::test)]
+  async fn ping_timeout() {
+    let (client, server) = duplex(4096);
+
+    let server = spawn(async move {
+      let mut stream = accept_async(server).await?;
+      stream.send(Message::Text(CONN_RESP.to_string())).await?;
+      // Authentication.
+      assert_eq!(
+        stream.next().await.unwrap()?,
+        Message::Text(AUTH_REQ.to_string()),
+      );
+      stream.send(Message::Text(AUTH_RESP.to_string())).await?;
+      // Stop reading, so that pings remain unanswered.
+      let () = sleep(Duration::from_millis(500)).await;
+      Ok::<_, WebSocketError>(())
+    });
+
+    let api_info = ApiInfo::from_parts(API_BASE_URL, KEY_ID, SECRET).unwrap();
+    let config = StreamConfig::default().with_ping_interval(Some(Duration::from_millis(10)));
+    let (mut stream, _subscription) = RealtimeData::<IEX>::connect_with(client, &api_info, config)
+      .await
+      .unwrap();
+
+    match stream.next().await.unwrap() {
+      Err(WebSocketError::Io(err)) if err.kind() == ErrorKind::TimedOut => (),
+      result => panic!("received unexpected result: {result:?}"),
+    }
+
+    let () = server.await.unwrap().unwrap();
+  }
+
+  /// Check that messages injected into a mock stream are classified
+  /// correctly.
+  #[test(tokio::test)]
+  async fn inject_data_messages() {
+    let (stream, subscription, injector) = mock_subscription::<Bar, Quote, Trade, _>(|request| {
+      panic!("unexpected request: {request:?}")
+    });
+
+    let messages = json_from_str::<Vec<DataMessage>>(BAR_RESP).unwrap();
+    let () = injector.push(DataMessage::Success {
+      message: "authenticated".to_string(),
+    });
+    let () = messages
+      .into_iter()
+      .for_each(|message| injector.push(message));
+    let () = drop(injector);
+    let () = drop(subscription);
+
+    let data = stream.try_collect::<Vec<_>>().await.unwrap();
+    assert_eq!(data.len(), 2);
+    assert!(data.iter().all(|data| matches!(data, Ok(Data::Bar { .. }))));
+  }
+
+  /// Check that market data is dropped while delivery is paused in
+  /// `PauseMode::Drop` mode.
+  #[test(tokio::test)]
+  async fn pause_drop() {
+    let (mut stream, subscription, injector) =
+      mock_subscription::<Bar, Quote, Trade, _>(|request| {
+        panic!("unexpected request: {request:?}")
+      });
+    let mut messages = json_from_str::<Vec<DataMessage>>(BAR_RESP)
+      .unwrap()
+      .into_iter();
+
+    let () = subscription.pause(PauseMode::Drop);
+    assert!(subscription.is_paused());
+    let () = injector.push(messages.next().unwrap());
+    assert!(stream.next().now_or_never().is_none());
+
+    let () = subscription.resume();
+    assert!(!subscription.is_paused());
+    let () = injector.push(messages.next().unwrap());
+    let () = drop(injector);
+    let () = drop(subscription);
+
+    let data = stream.try_collect::<Vec<_>>().await.unwrap();
+    assert_eq!(data.len(), 1);
+    assert!(matches!(&data[0], Ok(Data::Bar { bar, .. }) if bar.symbol == "VOO"));
+  }
+
+  /// Check that control messages, solicited or not, are published to
+  /// control streams.
+  #[test(tokio::test)]
+  async fn control_stream() {
+    let (mut stream, mut subscription, injector) = mock_subscription(respond());
+    let mut control = subscription.control_stream();
+
+    let subscribe = subscription.subscribe_bars(["SPY"]).boxed_local();
+    let data = drive(subscribe, &mut stream)
+      .await
+      .unwrap()
+      .unwrap()
+      .unwrap();
+    assert_eq!(
+      control.next().await.unwrap(),
+      ControlMessage::Subscription(data)
+    );
+
+    let error = StreamApiError {
+      code: 500,
+      message: "internal error".to_string(),
+    };
+    let () = injector.push(DataMessage::Error(error.clone()));
+    let messages = json_from_str::<Vec<DataMessage>>(BAR_RESP).unwrap();
+    let () = injector.push(messages.into_iter().next().unwrap());
+    let _data = stream.next().await.unwrap().unwrap().unwrap();
+    assert_eq!(control.next().await.unwrap(), ControlMessage::Error(error));
+    assert!(control.next().now_or_never().is_none());
+
+    let () = injector.close();
+    let () = drop(subscription);
+    assert!(stream.next().await.is_none());
+    assert!(control.next().await.is_none());
+    assert!(control.is_terminated());
+  }
+
+  /// Check that `Subscription::health` reports whether anything is
+  /// subscribed to and when market data was last received.
+  #[test(tokio::test)]
+  async fn stream_health() {
+    let (mut stream, mut subscription, injector) = mock_subscription(respond());
+    let health = subscription.health();
+    assert!(!health.subscribed);
+    assert_eq!(health.subscribed_symbols.bars, Count::List(0));
+    assert_eq!(health.last_data_at, None);
+
+    let subscribe = subscription.subscribe_bars(["SPY"]).boxed_local();
+    let _data = drive(subscribe, &mut stream)
+      .await
+      .unwrap()
+      .unwrap()
+      .unwrap();
+    let health = subscription.health();
+    assert!(health.subscribed);
+    assert_eq!(health.subscribed_symbols.bars, Count::List(1));
+    // Control messages do not count as market data.
+    assert_eq!(health.last_data_at, None);
+
+    let before = Instant::now();
+    let messages = json_from_str::<Vec<DataMessage>>(BAR_RESP).unwrap();
+    let () = injector.push(messages.into_iter().next().unwrap());
+    let _data = stream.next().await.unwrap().unwrap().unwrap();
+    let last_data_at = subscription.health().last_data_at.unwrap();
+    assert!(last_data_at >= before);
+  }
+
+  /// Check that market data is held back while delivery is paused in
+  /// `PauseMode::Buffer` mode, while control messages are still
+  /// processed.
+  #[test(tokio::test)]
+  async fn pause_buffer() {
+    let (mut stream, mut subscription, injector) = mock_subscription(respond());
+    let messages = json_from_str::<Vec<DataMessage>>(BAR_RESP).unwrap();
+
+    let () = subscription.pause(PauseMode::Buffer);
+    let () = messages
+      .into_iter()
+      .for_each(|message| injector.push(message));
+    assert!(stream.next().now_or_never().is_none());
+
+    let subscribe = subscription.subscribe_all_bars().boxed_local();
+    let _data = drive(subscribe, &mut stream)
+      .await
+      .unwrap()
+      .unwrap()
+      .unwrap();
+    assert_eq!(subscription.stats().bars, 2);
+
+    let () = subscription.resume();
+    let () = drop(injector);
+    let () = drop(subscription);
+
+    let data = stream.try_collect::<Vec<_>>().await.unwrap();
+    assert_eq!(data.len(), 2);
+    assert!(matches!(&data[0], Ok(Data::Bar { bar, .. }) if bar.symbol == "AAPL"));
+    assert!(matches!(&data[1], Ok(Data::Bar { bar, .. }) if bar.symbol == "VOO"));
+  }
+
+  /// Check that we can probe the liveness of a connection by means of
+  /// a ping.
+  #[test(tokio::test)]
+  async fn ping() {
+    async fn test(mut stream: WebSocketStream) -> Result<(), WebSocketError> {
+      stream.send(Message::Text(CONN_RESP.to_string())).await?;
+      // Authentication.
+      assert_eq!(
+        stream.next().await.unwrap()?,
+        Message::Text(AUTH_REQ.to_string()),
+      );
+      stream.send(Message::Text(AUTH_RESP.to_string())).await?;
+      assert!(matches!(stream.next().await.unwrap()?, Message::Ping(..)));
+      // The pong is sent automatically once we flush.
+      stream.flush().await?;
+      while stream.next().await.is_some() {}
+      Ok(())
+    }
+
+    let (mut stream, mut subscription) =
+      mock_stream::<RealtimeData<IEX>, _, _>(test).await.unwrap();
+    let ping = subscription.ping(Some(Duration::from_secs(10))).boxed();
+    let () = drive(ping, &mut stream).await.unwrap().unwrap();
+  }
+
+  /// Check that pinging reports an error if no pong arrives in time.
+  #[test(tokio::test)]
+  async fn ping_pong_timeout() {
+    let (mut stream, mut subscription, _injector) = mock_subscription(respond());
+    let ping = subscription
+      .ping(Some(Duration::from_millis(10)))
+      .boxed_local();
+    let err = drive(ping, &mut stream).await.unwrap().unwrap_err();
+    match err {
+      Error::PongTimeout => (),
+      e => panic!("received unexpected error: {e}"),
+    }
+  }
+
+  /// Check that pinging reports an error if the connection ends before
+  /// a pong arrived.
+  #[test(tokio::test)]
+  async fn ping_connection_ended() {
+    async fn test(mut stream: WebSocketStream) -> Result<(), WebSocketError> {
+      stream.send(Message::Text(CONN_RESP.to_string())).await?;
+      // Authentication.
+      assert_eq!(
+        stream.next().await.unwrap()?,
+        Message::Text(AUTH_REQ.to_string()),
+      );
+      stream.send(Message::Text(AUTH_RESP.to_string())).await?;
+      // Hang up without ever answering a ping.
+      stream.send(Message::Close(None)).await?;
+      Ok(())
+    }
+
+    let (mut stream, mut subscription) =
+      mock_stream::<RealtimeData<IEX>, _, _>(test).await.unwrap();
+    let ping = subscription.ping(None);
+    let drain = async { while stream.next().await.is_some() {} };
+    let (result, ()) = join(ping, drain).await;
+    match result {
+      Err(Error::ConnectionEnded) => (),
+      result => panic!("received unexpected result: {result:?}"),
+    }
+  }
+
+  /// Check that the buffer used while paused is bounded by the
+  /// configured capacity, discarding the oldest messages first.
+  #[test(tokio::test)]
+  async fn pause_buffer_capacity() {
+    let messages = json_from_str::<Vec<DataMessage>>(BAR_RESP)
+      .unwrap()
+      .into_iter()
+      .map(|message| MessageResult::Ok(Ok(message)))
+      .collect::<Vec<_>>();
+    let pause = Arc::<Pause>::default();
+    let () = pause.pause(PauseMode::Buffer);
+
+    let stream = futures::stream::iter(messages);
+    let counted = Counted::new(stream, Arc::default(), pause, Interner::default(), Some(1));
+    let messages = counted.collect::<Vec<_>>().await;
+    assert_eq!(messages.len(), 1);
+    assert!(
+      matches!(&messages[0], MessageResult::Ok(Ok(DataMessage::Bar(bar))) if bar.symbol == "VOO")
+    );
+  }
+
+  /// Check that we can subscribe and unsubscribe against a mock stream
+  /// responding with synthetic messages.
+  #[test(tokio::test)]
+  async fn inject_subscribe_unsubscribe() {
+    let (mut stream, mut subscription, _injector) =
+      mock_subscription::<Bar, Quote, Trade, _>(|request| match request {
+        Request::Subscribe(data) => vec![DataMessage::Subscription(data.into_owned())],
+        Request::Unsubscribe(..) => vec![DataMessage::Subscription(MarketData::default())],
+        request => panic!("unexpected request: {request:?}"),
+      });
+
+    let mut data = MarketData::default();
+    data.set_bars(["AAPL", "VOO"]);
+
+    let subscribe = subscription.subscribe(&data).boxed_local();
+    let subscribed = drive(subscribe, &mut stream)
+      .await
+      .unwrap()
+      .unwrap()
+      .unwrap();
+    assert_eq!(subscribed, data);
+    assert_eq!(subscription.subscriptions(), &data);
+    assert_eq!(
+      subscription.subscriptions_json().unwrap(),
+      r#"{"bars":["AAPL","VOO"]}"#
+    );
+
+    let unsubscribe = subscription.unsubscribe(&data).boxed_local();
+    let subscribed = drive(unsubscribe, &mut stream)
+      .await
+      .unwrap()
+      .unwrap()
+      .unwrap();
+    assert_eq!(subscribed, MarketData::default());
+    assert_eq!(subscription.subscriptions(), &MarketData::default());
+  }
+
+  /// Check that subscribing reports the subscriptions as acknowledged
+  /// by the server, which may lack rejected symbols.
+  #[test(tokio::test)]
+  async fn subscribe_reports_acknowledged() {
+    let (mut stream, mut subscription, _injector) =
+      mock_subscription::<Bar, Quote, Trade, _>(|request| match request {
+        Request::Subscribe(..) => {
+          let mut data = MarketData::default();
+          data.set_bars(["AAPL"]);
+          vec![DataMessage::Subscription(data)]
+        },
+        request => panic!("unexpected request: {request:?}"),
+      });
+
+    let mut data = MarketData::default();
+    data.set_bars(["AAPL", "BOGUS"]);
+
+    let subscribe = subscription.subscribe(&data).boxed_local();
+    let subscribed = drive(subscribe, &mut stream)
+      .await
+      .unwrap()
+      .unwrap()
+      .unwrap();
+    assert_eq!(subscribed.bars, ["AAPL"]);
+    assert_eq!(subscription.subscriptions(), &subscribed);
+
+    // A no-op subscription reports the current state as well.
+    let mut data = MarketData::default();
+    data.set_bars(["AAPL"]);
+    let subscribe = subscription.subscribe(&data).boxed_local();
+    let subscribed = drive(subscribe, &mut stream)
+      .await
+      .unwrap()
+      .unwrap()
+      .unwrap();
+    assert_eq!(subscribed.bars, ["AAPL"]);
+  }
+
+  /// Create a function responding to subscription requests the way
+  /// the Alpaca server does.
+  pub(crate) fn respond() -> impl FnMut(Request<'_>) -> Vec<DataMessage> + Unpin {
+    let mut state = MarketData::default();
+
+    move |request| {
+      match request {
+        Request::Subscribe(data) => state.merge(&data),
+        Request::Unsubscribe(data) => state = state.diff(&data).0,
+        request => panic!("unexpected request: {request:?}"),
+      }
+      vec![DataMessage::Subscription(state.clone())]
+    }
+  }
+
+  /// Check that interleaved subscribe requests issued through a
+  /// `SharedSubscription` are all reflected in the subscription state.
+  #[test(tokio::test)]
+  async fn shared_subscription_concurrent_subscribe() {
+    let (mut stream, subscription, _injector) = mock_subscription(respond());
+    let shared = SharedSubscription::from(subscription);
+    let other = shared.clone();
+
+    let mut first = MarketData::default();
+    first.set_bars(["AAPL"]);
+    let mut second = MarketData::default();
+    second.set_bars(["MSFT"]);
+    second.set_trades(["SPY"]);
+
+    let subscribe = join(shared.subscribe(&first), other.subscribe(&second)).boxed_local();
+    let (first, second) = drive(subscribe, &mut stream).await.unwrap();
+    let _data = first.unwrap().unwrap();
+    let _data = second.unwrap().unwrap();
+
+    let data = shared.snapshot().await;
+    assert_eq!(data.bars, ["AAPL", "MSFT"]);
+    assert_eq!(data.trades, ["SPY"]);
+    assert_eq!(other.snapshot().await, data);
+  }
+
+  /// Check that we can subscribe to and unsubscribe from individual
+  /// kinds of market data directly.
+  #[test(tokio::test)]
+  async fn subscribe_unsubscribe_by_kind() {
+    let (mut stream, mut subscription, _injector) = mock_subscription(respond());
+
+    let subscribe = subscription.subscribe_bars(["AAPL", "MSFT"]).boxed_local();
+    let _data = drive(subscribe, &mut stream)
+      .await
+      .unwrap()
+      .unwrap()
+      .unwrap();
+    let subscribe = subscription.subscribe_quotes(["SPY"]).boxed_local();
+    let _data = drive(subscribe, &mut stream)
+      .await
+      .unwrap()
+      .unwrap()
+      .unwrap();
+    let subscribe = subscription.subscribe_trades(["VOO"]).boxed_local();
+    let data = drive(subscribe, &mut stream)
+      .await
+      .unwrap()
+      .unwrap()
+      .unwrap();
+    assert_eq!(data.bars, ["AAPL", "MSFT"]);
+    assert_eq!(data.quotes, ["SPY"]);
+    assert_eq!(data.trades, ["VOO"]);
+
+    let unsubscribe = subscription.unsubscribe_bars(["AAPL"]).boxed_local();
+    let _data = drive(unsubscribe, &mut stream)
+      .await
+      .unwrap()
+      .unwrap()
+      .unwrap();
+    let unsubscribe = subscription.unsubscribe_quotes(["SPY"]).boxed_local();
+    let _data = drive(unsubscribe, &mut stream)
+      .await
+      .unwrap()
+      .unwrap()
+      .unwrap();
+    let unsubscribe = subscription.unsubscribe_trades(["VOO"]).boxed_local();
+    let data = drive(unsubscribe, &mut stream)
+      .await
+      .unwrap()
+      .unwrap()
+      .unwrap();
+    assert_eq!(data.bars, ["MSFT"]);
+    assert!(data.quotes.is_empty());
+    assert!(data.trades.is_empty());
+  }
+
+  /// Check that `Subscription::subscribe_and_recv` returns the first
+  /// market data item received after subscribing.
+  #[test(tokio::test)]
+  async fn subscribe_and_recv() {
+    let mut respond = respond();
+    let (mut stream, mut subscription, _injector) = mock_subscription(move |request| {
+      let mut messages = respond(request);
+      let bars = json_from_str::<Vec<DataMessage>>(BAR_RESP).unwrap();
+      let () = messages.extend(bars);
+      messages
+    });
+
+    let data = MarketData::with_bars(["AAPL", "VOO"]);
+    let data = subscription
+      .subscribe_and_recv(&mut stream, &data)
+      .await
+      .unwrap();
+    match data {
+      Data::Bar { bar, .. } => assert_eq!(bar.symbol, "AAPL"),
+      _ => panic!("encountered unexpected data: {data:?}"),
+    }
+    assert_eq!(subscription.subscriptions().bars, ["AAPL", "VOO"]);
+  }
+
+  /// Check that a rejected subscription to all symbols is reported as
+  /// such, without affecting the tracked subscriptions.
+  #[test(tokio::test)]
+  async fn subscribe_wildcard_not_permitted() {
+    let mut respond = respond();
+    let (mut stream, mut subscription, _injector) = mock_subscription(move |request| {
+      if let Request::Subscribe(data) = &request {
+        if data.trades == Symbols::All {
+          return vec![DataMessage::Error(StreamApiError {
+            code: 405,
+            message: "symbol limit exceeded".to_string(),
+          })]
+        }
+        if data.has_wildcard() || data.quotes.contains("SPY") {
+          return vec![DataMessage::Error(StreamApiError {
+            code: 409,
+            message: "insufficient subscription".to_string(),
+          })]
+        }
+      }
+      respond(request)
+    });
+
+    let data = MarketData::with_bars(["AAPL"]);
+    let subscribe = subscription.subscribe(&data).boxed_local();
+    let _data = drive(subscribe, &mut stream)
+      .await
+      .unwrap()
+      .unwrap()
+      .unwrap();
+
+    let subscribe = subscription.subscribe_all_bars().boxed_local();
+    let err = drive(subscribe, &mut stream)
+      .await
+      .unwrap()
+      .unwrap()
+      .unwrap_err();
+    match err {
+      Error::WildcardNotPermitted(StreamError::InvalidSubscription(error)) => {
+        assert_eq!(error.code, 409)
+      },
+      _ => panic!("encountered unexpected error: {err:?}"),
+    }
+    assert_eq!(subscription.subscriptions().bars, ["AAPL"]);
+
+    let data = MarketData::with_quotes(["SPY"]);
+    let subscribe = subscription.subscribe(&data).boxed_local();
+    let err = drive(subscribe, &mut stream)
+      .await
+      .unwrap()
+      .unwrap()
+      .unwrap_err();
+    assert!(
+      matches!(err, Error::Stream(StreamError::InvalidSubscription(..))),
+      "{err:?}"
+    );
+
+    // Other reasons for rejecting a request containing a wildcard are
+    // reported as is.
+    let data = MarketData::with_trades(["*"]);
+    let subscribe = subscription.subscribe(&data).boxed_local();
+    let err = drive(subscribe, &mut stream)
+      .await
+      .unwrap()
+      .unwrap()
+      .unwrap_err();
+    match err {
+      Error::Stream(StreamError::InvalidSubscription(error)) => assert_eq!(error.code, 405),
+      _ => panic!("encountered unexpected error: {err:?}"),
+    }
+  }
+
+  /// Check that a subscribe request only contains the symbols not
+  /// already subscribed to.
+  #[test(tokio::test)]
+  async fn subscribe_sends_delta() {
+    let requests = Rc::new(RefCell::new(Vec::<String>::new()));
+    let mut respond = respond();
+    let (mut stream, mut subscription, _injector) = mock_subscription({
+      let requests = requests.clone();
+      move |request| {
+        let () = requests.borrow_mut().push(to_json(&request).unwrap());
+        respond(request)
+      }
+    });
+
+    let mut data = MarketData::default();
+    data.set_bars(["AAPL", "MSFT", "SPY"]);
+    let subscribe = subscription.subscribe(&data).boxed_local();
+    let _data = drive(subscribe, &mut stream)
+      .await
+      .unwrap()
+      .unwrap()
+      .unwrap();
+
+    let mut data = MarketData::default();
+    data.set_bars(["MSFT", "VOO"]);
+    let subscribe = subscription.subscribe(&data).boxed_local();
+    let subscribed = drive(subscribe, &mut stream)
+      .await
+      .unwrap()
+      .unwrap()
+      .unwrap();
+    assert_eq!(subscribed.bars, ["AAPL", "MSFT", "SPY", "VOO"]);
+
+    let requests = requests.take();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(requests[1], r#"{"action":"subscribe","bars":["VOO"]}"#);
+  }
+
+  /// Check that we can send raw messages and that doing so does not
+  /// affect subscription tracking.
+  #[test(tokio::test)]
+  async fn send_raw() {
+    let (mut stream, mut subscription, _injector) = mock_subscription(respond());
+
+    let json = r#"{"action":"subscribe","bars":["AAPL"]}"#;
+    let message = wrap::Message::Text(json.to_string());
+    let send = subscription.send_raw(message).boxed_local();
+    let control = drive(send, &mut stream).await.unwrap().unwrap().unwrap();
+
+    match control {
+      ControlMessage::Subscription(data) => assert_eq!(data.bars, ["AAPL"]),
+      control => panic!("unexpected control message: {control:?}"),
+    }
+    assert!(subscription.subscriptions().is_empty());
+  }
+
+  /// Check that `subscribe_with_deadline` reports partial progress
+  /// when the deadline is hit.
+  #[test(tokio::test)]
+  async fn subscribe_with_deadline() {
+    let mut respond = respond();
+    let mut requests = 0;
+    let (mut stream, mut subscription, _injector) = mock_subscription(move |request| {
+      requests += 1;
+      // Leave the second request unanswered.
+      if requests == 2 {
+        Vec::new()
+      } else {
+        respond(request)
+      }
+    });
+
+    let symbols = (0..2500)
+      .map(|i| Symbol::from(format!("S{i:04}")))
+      .collect::<Vec<_>>();
+    let data = MarketData::with_bars(symbols.clone());
+    let deadline = Instant::now() + Duration::from_millis(100);
+    let subscribe = subscription
+      .subscribe_with_deadline(&data, deadline)
+      .boxed_local();
+    let outcome = drive(subscribe, &mut stream).await.unwrap().unwrap();
+
+    assert!(outcome.error.is_none());
+    assert_eq!(outcome.acknowledged.bars, symbols[..1000]);
+    assert_eq!(outcome.pending.bars, symbols[1000..]);
+    assert_eq!(subscription.subscriptions(), &outcome.acknowledged);
+  }
+
+  /// Check that `subscribe_with_deadline` subscribes to everything if
+  /// the deadline is not hit.
+  #[test(tokio::test)]
+  async fn subscribe_with_deadline_complete() {
+    let (mut stream, mut subscription, _injector) = mock_subscription(respond());
+
+    let symbols = (0..2500)
+      .map(|i| Symbol::from(format!("S{i:04}")))
+      .collect::<Vec<_>>();
+    let data = MarketData::with_bars(symbols);
+    let deadline = Instant::now() + Duration::from_secs(60);
+    let subscribe = subscription
+      .subscribe_with_deadline(&data, deadline)
+      .boxed_local();
+    let outcome = drive(subscribe, &mut stream).await.unwrap().unwrap();
+
+    assert!(outcome.error.is_none());
+    assert!(outcome.pending.is_empty());
+    assert!(outcome.rejected.is_empty());
+    assert_eq!(outcome.acknowledged, data);
+    assert_eq!(subscription.subscriptions(), &data);
+  }
+
+  /// Check that market data omitted from the server's acknowledgement
+  /// is reported as rejected.
+  #[test(tokio::test)]
+  async fn subscribe_reports_rejected() {
+    let mut respond = respond();
+    let (mut stream, mut subscription, _injector) = mock_subscription(move |request| {
+      let request = match request {
+        Request::Subscribe(data) => {
+          let (valid, _) = data.diff(&MarketData::with_bars(["BOGUS"]));
+          Request::Subscribe(Cow::Owned(valid))
+        },
+        request => request,
+      };
+      respond(request)
+    });
+
+    let data = MarketData::with_bars(["AAPL", "BOGUS"]);
+    let subscribe = subscription.subscribe(&data).boxed_local();
+    let subscribed = drive(subscribe, &mut stream)
+      .await
+      .unwrap()
+      .unwrap()
+      .unwrap();
+    assert_eq!(subscribed.bars, ["AAPL"]);
+    assert_eq!(subscription.rejected(), &MarketData::with_bars(["BOGUS"]));
+
+    let data = MarketData::with_bars(["MSFT"]);
+    let subscribe = subscription.subscribe(&data).boxed_local();
+    let _data = drive(subscribe, &mut stream)
+      .await
+      .unwrap()
+      .unwrap()
+      .unwrap();
+    assert!(subscription.rejected().is_empty());
+  }
+
+  /// Check that previewing a subscription reports the merged result
+  /// without sending a request.
+  #[test(tokio::test)]
+  async fn preview_subscribe() {
+    let requests = Rc::new(Cell::new(0));
+    let mut respond = respond();
+    let (mut stream, mut subscription, _injector) = mock_subscription({
+      let requests = requests.clone();
+      move |request| {
+        let () = requests.set(requests.get() + 1);
+        respond(request)
+      }
+    });
+
+    let mut data = MarketData::default();
+    data.set_bars(["AAPL"]);
+    let subscribe = subscription.subscribe(&data).boxed_local();
+    let _data = drive(subscribe, &mut stream)
+      .await
+      .unwrap()
+      .unwrap()
+      .unwrap();
+
+    let () = requests.set(0);
+    let mut data = MarketData::default();
+    data.set_bars(["MSFT"]);
+    data.set_trades(["SPY"]);
+    let preview = subscription.preview_subscribe(&data);
+    assert_eq!(preview.bars, ["AAPL", "MSFT"]);
+    assert_eq!(preview.trades, ["SPY"]);
+    assert_eq!(preview.counts().bars, Count::List(2));
+    assert_eq!(requests.get(), 0);
+    assert_eq!(subscription.subscriptions().bars, ["AAPL"]);
+
+    let subscribe = subscription.subscribe(&data).boxed_local();
+    let _data = drive(subscribe, &mut stream)
+      .await
+      .unwrap()
+      .unwrap()
+      .unwrap();
+    assert_eq!(subscription.subscriptions(), &preview);
+  }
+
+  /// Check that a `SubscriptionState` handle tracks the subscriptions
+  /// as they change.
+  #[test(tokio::test)]
+  async fn observe_subscription_state() {
+    let (mut stream, mut subscription, _injector) = mock_subscription(respond());
+    let state = subscription.state();
+    let cloned = state.clone();
+    assert!(state.snapshot().is_empty());
+
+    let mut data = MarketData::default();
+    data.set_bars(["AAPL", "MSFT"]);
+    let subscribe = subscription.subscribe(&data).boxed_local();
+    let _data = drive(subscribe, &mut stream)
+      .await
+      .unwrap()
+      .unwrap()
+      .unwrap();
+    assert_eq!(state.snapshot().bars, ["AAPL", "MSFT"]);
+    assert_eq!(cloned.snapshot(), subscription.snapshot());
+
+    let mut data = MarketData::default();
+    data.set_bars(["AAPL"]);
+    let unsubscribe = subscription.unsubscribe(&data).boxed_local();
+    let _data = drive(unsubscribe, &mut stream)
+      .await
+      .unwrap()
+      .unwrap()
+      .unwrap();
+    assert_eq!(cloned.snapshot().bars, ["MSFT"]);
+  }
+
+  /// Check that no control request is sent while a previously
+  /// abandoned one still awaits its response.
+  #[test(tokio::test)]
+  async fn abandoned_request_blocks_next() {
+    let mut respond = respond();
+    let mut first = true;
+    let (mut stream, mut subscription, injector) = mock_subscription(move |request| {
+      if first {
+        // Leave the first request unanswered for now.
+        first = false;
+        Vec::new()
+      } else {
+        respond(request)
+      }
+    });
+
+    let data = MarketData::with_bars(["AAPL"]);
+    let subscribe = subscription.subscribe(&data).boxed_local();
+    assert!(subscribe.now_or_never().is_none());
+
+    let subscribe = subscription.subscribe_bars(["MSFT"]).boxed_local();
+    let err = drive(subscribe, &mut stream)
+      .await
+      .unwrap()
+      .unwrap()
+      .unwrap_err();
+    assert_eq!(
+      err.to_string(),
+      "a previous control request is still awaiting a response"
+    );
+
+    // A control message not constituting a response to the
+    // outstanding request does not unblock us.
+    let () = injector.push(DataMessage::Success {
+      message: "unrelated".to_string(),
+    });
+    assert!(stream.next().now_or_never().is_none());
+
+    let subscribe = subscription.subscribe_bars(["MSFT"]).boxed_local();
+    let err = drive(subscribe, &mut stream)
+      .await
+      .unwrap()
+      .unwrap()
+      .unwrap_err();
+    assert_eq!(
+      err.to_string(),
+      "a previous control request is still awaiting a response"
+    );
+
+    // Once the outstanding response arrived, requests can be issued
+    // again.
+    let () = injector.push(DataMessage::Subscription(data));
+    assert!(stream.next().now_or_never().is_none());
+
+    let subscribe = subscription.subscribe_bars(["MSFT"]).boxed_local();
+    let data = drive(subscribe, &mut stream)
+      .await
+      .unwrap()
+      .unwrap()
+      .unwrap();
+    assert_eq!(data.bars, ["MSFT"]);
+  }
+
+  /// Check that a control request that timed out does not block
+  /// subsequent ones.
+  #[test(tokio::test)]
+  async fn timed_out_request_does_not_block_next() {
+    let mut respond = respond();
+    let mut first = true;
+    let (mut stream, mut subscription, _injector) = mock_subscription(move |request| {
+      if first {
+        first = false;
+        Vec::new()
+      } else {
+        respond(request)
+      }
+    });
+    let () = subscription.set_timeout(Some(Duration::from_millis(10)));
+
+    let subscribe = subscription.subscribe_bars(["AAPL"]).boxed_local();
+    let err = drive(subscribe, &mut stream)
+      .await
+      .unwrap()
+      .unwrap()
+      .unwrap_err();
+    assert_eq!(err.to_string(), "subscription request timed out");
+
+    let subscribe = subscription.subscribe_bars(["MSFT"]).boxed_local();
+    let data = drive(subscribe, &mut stream)
+      .await
+      .unwrap()
+      .unwrap()
+      .unwrap();
+    assert_eq!(data.bars, ["MSFT"]);
+  }
+
+  /// Check that we can converge the subscriptions to a target state.
+  #[test(tokio::test)]
+  async fn set_subscriptions() {
+    let requests = Rc::new(RefCell::new(Vec::<String>::new()));
+    let mut respond = respond();
+    let (mut stream, mut subscription, _injector) = mock_subscription({
+      let requests = requests.clone();
+      move |request| {
+        let () = requests.borrow_mut().push(to_json(&request).unwrap());
+        respond(request)
+      }
+    });
+
+    let mut data = MarketData::default();
+    data.set_bars(["AAPL", "MSFT"]);
+    data.set_trades(["SPY"]);
+    let subscribe = subscription.subscribe(&data).boxed_local();
+    let _data = drive(subscribe, &mut stream)
+      .await
+      .unwrap()
+      .unwrap()
+      .unwrap();
+
+    let _requests = requests.take();
+    let mut target = MarketData::default();
+    target.set_bars(["MSFT", "TSLA"]);
+    target.set_quotes(["AAPL"]);
+    let set = subscription.set_subscriptions(&target).boxed_local();
+    let subscribed = drive(set, &mut stream).await.unwrap().unwrap().unwrap();
+    assert_eq!(subscribed.bars, ["MSFT", "TSLA"]);
+    assert_eq!(subscribed.quotes, ["AAPL"]);
+    assert!(subscribed.trades.is_empty());
+    assert_eq!(subscription.subscriptions(), &subscribed);
+    assert_eq!(
+      requests.take(),
+      [
+        r#"{"action":"unsubscribe","bars":["AAPL"],"trades":["SPY"]}"#,
+        r#"{"action":"subscribe","bars":["TSLA"],"quotes":["AAPL"]}"#,
+      ]
+    );
+
+    // Already matching the target, nothing should be sent.
+    let set = subscription.set_subscriptions(&target).boxed_local();
+    let _data = drive(set, &mut stream).await.unwrap().unwrap().unwrap();
+    assert!(requests.take().is_empty());
+  }
+
+  /// Check that we can narrow a subscription to bars for all symbols
+  /// down to an explicit list.
+  #[test(tokio::test)]
+  async fn restrict_bars() {
+    let requests = Rc::new(Cell::new(0));
+    let mut respond = respond();
+    let (mut stream, mut subscription, _injector) = mock_subscription({
+      let requests = requests.clone();
+      move |request| {
+        let () = requests.set(requests.get() + 1);
+        if let Request::Subscribe(data) = &request {
+          if data.bars == ["BOGUS"] {
+            return vec![DataMessage::Error(StreamApiError {
+              code: 400,
+              message: "invalid syntax".to_string(),
+            })]
+          }
+        }
+        respond(request)
+      }
+    });
+
+    let subscribe = subscription.subscribe_all_bars().boxed_local();
+    let _data = drive(subscribe, &mut stream)
+      .await
+      .unwrap()
+      .unwrap()
+      .unwrap();
+    assert_eq!(subscription.subscriptions().bars, Symbols::All);
+
+    let () = requests.set(0);
+    let restrict = subscription.restrict_bars(["AAPL", "MSFT"]).boxed_local();
+    let subscribed = drive(restrict, &mut stream)
+      .await
+      .unwrap()
+      .unwrap()
+      .unwrap();
+    assert_eq!(subscribed.bars, ["AAPL", "MSFT"]);
+    assert_eq!(subscription.subscriptions().bars, ["AAPL", "MSFT"]);
+    assert_eq!(requests.get(), 2);
+
+    let subscribe = subscription.subscribe_all_bars().boxed_local();
+    let _data = drive(subscribe, &mut stream)
+      .await
+      .unwrap()
+      .unwrap()
+      .unwrap();
+
+    // The unsubscribe from all bars goes through, but the subscribe
+    // to the explicit list is rejected, leaving no bars subscribed.
+    let () = requests.set(0);
+    let restrict = subscription.restrict_bars(["BOGUS"]).boxed_local();
+    let _err = drive(restrict, &mut stream)
+      .await
+      .unwrap()
+      .unwrap()
+      .unwrap_err();
+    assert_eq!(requests.get(), 2);
+    assert!(subscription.subscriptions().bars.is_empty());
+  }
+
+  /// Check that subscribing to multiple kinds of market data at once
+  /// results in a single combined request and updates all of them.
+  #[test(tokio::test)]
+  async fn subscribe_multiple_fields() {
+    let requests = Rc::new(Cell::new(0));
+    let mut respond = respond();
+    let (mut stream, mut subscription, _injector) = mock_subscription({
+      let requests = requests.clone();
+      move |request| {
+        if let Request::Subscribe(data) = &request {
+          if requests.get() == 1 {
+            let mut expected = MarketData::default();
+            expected.set_bars(["MSFT"]);
+            expected.set_quotes(["AAPL", "MSFT"]);
+            assert_eq!(data.as_ref(), &expected);
+          }
+        }
+        let () = requests.set(requests.get() + 1);
+        respond(request)
+      }
+    });
+
+    let mut data = MarketData::default();
+    data.set_bars(["AAPL"]);
+    let subscribe = subscription.subscribe(&data).boxed_local();
+    let _data = drive(subscribe, &mut stream)
+      .await
+      .unwrap()
+      .unwrap()
+      .unwrap();
+
+    let mut data = MarketData::default();
+    data.set_bars(["AAPL", "MSFT"]);
+    data.set_quotes(["AAPL", "MSFT"]);
+    let subscribe = subscription.subscribe(&data).boxed_local();
+    let _data = drive(subscribe, &mut stream)
+      .await
+      .unwrap()
+      .unwrap()
+      .unwrap();
+
+    assert_eq!(requests.get(), 2);
+    assert_eq!(subscription.subscriptions(), &data);
+  }
+
+  /// Check that subscribing merges each kind of market data
+  /// independently, leaving kinds not requested untouched.
+  #[test(tokio::test)]
+  async fn subscribe_fields_independently() {
+    let (mut stream, mut subscription, _injector) = mock_subscription(respond());
+
+    let mut data = MarketData::default();
+    data.set_bars(["AAPL", "MSFT", "SPY"]);
+    data.set_trades(["SPY"]);
+    let subscribe = subscription.subscribe(&data).boxed_local();
+    let _data = drive(subscribe, &mut stream)
+      .await
+      .unwrap()
+      .unwrap()
+      .unwrap();
+
+    let data = MarketData::with_bars(["AMZN", "SPY"]);
+    let subscribe = subscription.subscribe(&data).boxed_local();
+    let subscribed = drive(subscribe, &mut stream)
+      .await
+      .unwrap()
+      .unwrap()
+      .unwrap();
+    assert_eq!(subscribed.bars, ["AAPL", "AMZN", "MSFT", "SPY"]);
+    assert_eq!(subscribed.trades, ["SPY"]);
+
+    let data = MarketData::with_trades(["AAPL"]);
+    let subscribe = subscription.subscribe(&data).boxed_local();
+    let subscribed = drive(subscribe, &mut stream)
+      .await
+      .unwrap()
+      .unwrap()
+      .unwrap();
+    assert_eq!(subscribed.bars, ["AAPL", "AMZN", "MSFT", "SPY"]);
+    assert_eq!(subscribed.trades, ["AAPL", "SPY"]);
+    assert!(subscribed.quotes.is_empty());
+    assert_eq!(subscription.subscriptions(), &subscribed);
+  }
+
+  /// Check that subscribing to all symbols while being subscribed to
+  /// individual ones, and vice versa, behaves as expected.
+  #[test(tokio::test)]
+  async fn subscribe_all_transitions() {
+    let requests = Rc::new(Cell::new(0));
+    let mut respond = respond();
+    let (mut stream, mut subscription, _injector) = mock_subscription({
+      let requests = requests.clone();
+      move |request| {
+        let () = requests.set(requests.get() + 1);
+        respond(request)
+      }
+    });
+
+    let mut data = MarketData::default();
+    data.set_bars(["AAPL"]);
+    let subscribe = subscription.subscribe(&data).boxed_local();
+    let _data = drive(subscribe, &mut stream)
+      .await
+      .unwrap()
+      .unwrap()
+      .unwrap();
+    assert_eq!(requests.get(), 1);
+    assert!(subscription.is_subscribed_bars("AAPL"));
+    assert!(subscription.is_subscribed_bars("aapl"));
+    assert!(subscription.is_subscribed_bars(Symbol::from("Aapl")));
+    assert!(!subscription.is_subscribed_bars("MSFT"));
+    assert!(!subscription.is_subscribed_quotes("AAPL"));
+
+    // Subscribing to all bars while subscribed to individual ones
+    // should send a single subscribe-to-all request and collapse the
+    // tracked subscriptions accordingly.
+    let subscribe = subscription.subscribe_all_bars().boxed_local();
+    let _data = drive(subscribe, &mut stream)
+      .await
+      .unwrap()
+      .unwrap()
+      .unwrap();
+    assert_eq!(requests.get(), 2);
+    assert_eq!(subscription.subscriptions(), &MarketData::all_bars());
+
+    // Subscribing to an individual symbol is a no-op now.
+    let mut data = MarketData::default();
+    data.set_bars(["MSFT"]);
+    let subscribe = subscription.subscribe(&data).boxed_local();
+    let _data = drive(subscribe, &mut stream)
+      .await
+      .unwrap()
+      .unwrap()
+      .unwrap();
+    assert_eq!(requests.get(), 2);
+    assert_eq!(subscription.subscriptions(), &MarketData::all_bars());
+    assert!(subscription.is_subscribed_bars("MSFT"));
+    assert!(subscription.is_subscribed_bars("msft"));
+    assert!(!subscription.is_subscribed_trades("MSFT"));
+  }
+
+  /// Check that closing a subscription unsubscribes from everything
+  /// and allows the stream to end.
+  #[test(tokio::test)]
+  async fn close_subscription() {
+    let unsubscribed = Rc::new(Cell::new(false));
+    let mut respond = respond();
+    let (mut stream, mut subscription, injector) = mock_subscription({
+      let unsubscribed = unsubscribed.clone();
+      move |request| {
+        if let Request::Unsubscribe(data) = &request {
+          assert_eq!(data.bars, ["AAPL", "SPY"]);
+          let () = unsubscribed.set(true);
+        }
+        respond(request)
+      }
+    });
+
+    let mut data = MarketData::default();
+    data.set_bars(["SPY", "AAPL"]);
+    let subscribe = subscription.subscribe(&data).boxed_local();
+    let _data = drive(subscribe, &mut stream)
+      .await
+      .unwrap()
+      .unwrap()
+      .unwrap();
+
+    let close = subscription.close().boxed_local();
+    let () = drive(close, &mut stream).await.unwrap().unwrap().unwrap();
+    assert!(unsubscribed.get());
+
+    let () = drop(injector);
+    assert!(stream.next().await.is_none());
+  }
+
+  /// Check that closing a subscription sends a close frame to the
+  /// server, even if unsubscribing failed.
+  #[test(tokio::test)]
+  async fn close_sends_close_frame() {
+    async fn test(mut stream: WebSocketStream) -> Result<(), WebSocketError> {
+      stream.send(Message::Text(CONN_RESP.to_string())).await?;
+      // Authentication.
+      assert_eq!(
+        stream.next().await.unwrap()?,
+        Message::Text(AUTH_REQ.to_string()),
+      );
+      stream.send(Message::Text(AUTH_RESP.to_string())).await?;
+      // Subscription.
+      assert_eq!(
+        stream.next().await.unwrap()?,
+        Message::Text(SUB_REQ.to_string()),
+      );
+      stream.send(Message::Text(SUB_RESP.to_string())).await?;
+      // Unsubscription, which we reject.
+      assert_eq!(
+        stream.next().await.unwrap()?,
+        Message::Text(UNSUB_REQ.to_string()),
+      );
+      let error = r#"[{"T":"error","code":500,"msg":"internal error"}]"#;
+      stream.send(Message::Text(error.to_string())).await?;
+
+      assert!(matches!(stream.next().await.unwrap()?, Message::Close(..)));
+      while stream.next().await.is_some() {}
+      Ok(())
+    }
+
+    let (mut stream, mut subscription) =
+      mock_stream::<RealtimeData<IEX>, _, _>(test).await.unwrap();
+
+    let mut data = MarketData::default();
+    data.set_bars(["AAPL", "VOO"]);
+    let subscribe = subscription.subscribe(&data).boxed();
+    let _data = drive(subscribe, &mut stream)
+      .await
+      .unwrap()
+      .unwrap()
+      .unwrap();
+
+    let close = subscription.close().boxed();
+    let err = drive(close, &mut stream)
+      .await
+      .unwrap()
+      .unwrap()
+      .unwrap_err();
+    match err {
+      Error::Stream(StreamError::InternalError(error)) => assert_eq!(error.code, 500),
+      err => panic!("received unexpected error: {err}"),
+    }
+
+    while stream.next().await.is_some() {}
+  }
+
+  /// Check that we can unsubscribe from all market data at once.
+  #[test(tokio::test)]
+  async fn subscribe_clear() {
+    async fn test(mut stream: WebSocketStream) -> Result<(), WebSocketError> {
+      stream.send(Message::Text(CONN_RESP.to_string())).await?;
+      // Authentication.
+      assert_eq!(
+        stream.next().await.unwrap()?,
+        Message::Text(AUTH_REQ.to_string()),
+      );
+      stream.send(Message::Text(AUTH_RESP.to_string())).await?;
+
+      // Subscription.
+      assert_eq!(
+        stream.next().await.unwrap()?,
+        Message::Text(SUB_REQ.to_string()),
+      );
+      stream.send(Message::Text(SUB_RESP.to_string())).await?;
+
+      // Unsubscription.
+      assert_eq!(
+        stream.next().await.unwrap()?,
+        Message::Text(UNSUB_REQ.to_string()),
+      );
+      stream.send(Message::Text(UNSUB_RESP.to_string())).await?;
+      stream.send(Message::Close(None)).await?;
+      Ok(())
+    }
+
+    let (mut stream, mut subscription) =
+      mock_stream::<RealtimeData<IEX>, _, _>(test).await.unwrap();
+
+    let mut data = MarketData::default();
+    data.set_bars(["AAPL", "VOO"]);
+
+    let subscribe = subscription.subscribe(&data).boxed_local();
+    let _data = drive(subscribe, &mut stream)
+      .await
+      .unwrap()
+      .unwrap()
+      .unwrap();
+
+    let clear = subscription.clear().boxed_local();
+    let _data = drive(clear, &mut stream).await.unwrap().unwrap().unwrap();
+    assert_eq!(subscription.subscriptions(), &MarketData::default());
+
+    // Clearing once more should not result in another request being
+    // sent.
+    let clear = subscription.clear().boxed_local();
+    let _data = drive(clear, &mut stream).await.unwrap().unwrap().unwrap();
+  }
+
+  /// Check that subscribing to already active symbols does not result
+  /// in a request being sent.
+  #[test(tokio::test)]
+  async fn subscribe_noop() {
+    async fn test(mut stream: WebSocketStream) -> Result<(), WebSocketError> {
+      stream.send(Message::Text(CONN_RESP.to_string())).await?;
+      // Authentication.
+      assert_eq!(
+        stream.next().await.unwrap()?,
+        Message::Text(AUTH_REQ.to_string()),
+      );
+      stream.send(Message::Text(AUTH_RESP.to_string())).await?;
+
+      // Subscription.
+      assert_eq!(
+        stream.next().await.unwrap()?,
+        Message::Text(SUB_REQ.to_string()),
+      );
+      stream.send(Message::Text(SUB_RESP.to_string())).await?;
+      stream.send(Message::Close(None)).await?;
+      Ok(())
+    }
+
+    let (mut stream, mut subscription) =
+      mock_stream::<RealtimeData<IEX>, _, _>(test).await.unwrap();
+
+    let mut data = MarketData::default();
+    data.set_bars(["AAPL", "VOO"]);
+
+    let subscribe = subscription.subscribe(&data).boxed_local();
+    let _data = drive(subscribe, &mut stream)
+      .await
+      .unwrap()
+      .unwrap()
+      .unwrap();
+
+    // The server would not respond to a second request, so we would
+    // error out if one were sent.
+    let mut data = MarketData::default();
+    data.set_bars(["VOO"]);
+
+    let subscribe = subscription.subscribe(&data).boxed_local();
+    let _data = drive(subscribe, &mut stream)
+      .await
+      .unwrap()
+      .unwrap()
+      .unwrap();
+
+    let mut expected = MarketData::default();
+    expected.set_bars(["AAPL", "VOO"]);
+    assert_eq!(subscription.subscriptions(), &expected);
+    assert_eq!(subscription.snapshot(), expected);
+  }
+
+  /// Check that subscribing to individual symbols while subscribed to
+  /// all of them does not result in a request being sent.
+  #[test(tokio::test)]
+  async fn subscribe_all_bars() {
+    async fn test(mut stream: WebSocketStream) -> Result<(), WebSocketError> {
+      stream.send(Message::Text(CONN_RESP.to_string())).await?;
+      // Authentication.
+      assert_eq!(
+        stream.next().await.unwrap()?,
+        Message::Text(AUTH_REQ.to_string()),
+      );
+      stream.send(Message::Text(AUTH_RESP.to_string())).await?;
+
+      // Subscription.
+      assert_eq!(
+        stream.next().await.unwrap()?,
+        Message::Text(SUB_ALL_REQ.to_string()),
+      );
+      stream.send(Message::Text(SUB_ALL_RESP.to_string())).await?;
+      stream.send(Message::Close(None)).await?;
+      Ok(())
+    }
+
+    let (mut stream, mut subscription) =
+      mock_stream::<RealtimeData<IEX>, _, _>(test).await.unwrap();
+
+    let subscribe = subscription.subscribe_all_bars().boxed_local();
+    let _data = drive(subscribe, &mut stream)
+      .await
+      .unwrap()
+      .unwrap()
+      .unwrap();
+    assert_eq!(subscription.subscriptions(), &MarketData::all_bars());
+
+    let mut data = MarketData::default();
+    data.set_bars(["AAPL"]);
+
+    let subscribe = subscription.subscribe(&data).boxed_local();
+    let _data = drive(subscribe, &mut stream)
+      .await
+      .unwrap()
+      .unwrap()
+      .unwrap();
+    assert_eq!(subscription.subscriptions(), &MarketData::all_bars());
+  }
+
+  /// A custom message type extending [`DataMessage`] with trading
+  /// status messages.
+  #[derive(Debug)]
+  #[allow(clippy::large_enum_variant)]
+  enum StatusMessage {
+    Status(String),
+    Other(DataMessage),
+  }
+
+  impl<'de> Deserialize<'de> for StatusMessage {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+      D: Deserializer<'de>,
+    {
+      let value = JsonValue::deserialize(deserializer)?;
+      if value["T"] == "s" {
+        let symbol = value["S"]
+          .as_str()
+          .ok_or_else(|| D::Error::custom("status message lacks symbol"))?;
+        Ok(Self::Status(symbol.to_string()))
+      } else {
+        DataMessage::deserialize(value)
+          .map(Self::Other)
+          .map_err(D::Error::custom)
+      }
+    }
+  }
+
+  /// The data contained in a [`StatusMessage`].
+  #[derive(Debug)]
+  #[allow(clippy::large_enum_variant)]
+  enum StatusData {
+    Status(String),
+    Market(Data),
+  }
+
+  impl StreamMessage for StatusMessage {
+    type Data = StatusData;
+    type Channels = MarketData;
+
+    fn kind(&self) -> MessageKind {
+      match self {
+        Self::Status(..) => MessageKind::OtherData,
+        Self::Other(message) => message.kind(),
+      }
+    }
+
+    fn classify(self) -> Result<Self::Data, ControlMessage> {
+      match self {
+        Self::Status(symbol) => Ok(StatusData::Status(symbol)),
+        Self::Other(message) => message.classify().map(StatusData::Market),
+      }
+    }
+  }
+
+  /// Check that we can stream a custom message type.
+  #[test(tokio::test)]
+  async fn stream_custom_message() {
+    async fn test(mut stream: WebSocketStream) -> Result<(), WebSocketError> {
+      stream.send(Message::Text(CONN_RESP.to_string())).await?;
+      // Authentication.
+      assert_eq!(
+        stream.next().await.unwrap()?,
+        Message::Text(AUTH_REQ.to_string()),
+      );
+      stream.send(Message::Text(AUTH_RESP.to_string())).await?;
+
+      // Subscription.
+      assert_eq!(
+        stream.next().await.unwrap()?,
+        Message::Text(SUB_REQ.to_string()),
+      );
+      stream.send(Message::Text(SUB_RESP.to_string())).await?;
+
+      let status = r#"[{"T":"s","S":"AAPL"}]"#;
+      stream.send(Message::Text(status.to_string())).await?;
+      stream.send(Message::Text(BAR_RESP.to_string())).await?;
+      stream.send(Message::Close(None)).await?;
+      Ok(())
+    }
+
+    let (mut stream, mut subscription) =
+      mock_stream::<RealtimeData<IEX, StatusMessage>, _, _>(test)
+        .await
+        .unwrap();
+
+    let data = MarketData::with_bars(["AAPL", "VOO"]);
+    let status = subscription
+      .subscribe_and_recv(&mut stream, &data)
+      .await
+      .unwrap();
+    assert!(matches!(status, StatusData::Status(symbol) if symbol == "AAPL"));
+
+    let data = stream
+      .map_err(Error::WebSocket)
+      .try_collect::<Vec<_>>()
+      .await
+      .unwrap();
+    assert_eq!(data.len(), 2);
+    assert!(data
+      .iter()
+      .all(|data| matches!(data, Ok(StatusData::Market(Data::Bar { .. })))));
+
+    let stats = subscription.stats();
+    assert_eq!(stats.bars, 2);
+    assert_eq!(stats.successes, 2);
+  }
+
+  /// Check that messages of unknown type are discarded by the stream.
+  #[test(tokio::test)]
+  async fn subscribe_with_unknown_message() {
+    async fn test(mut stream: WebSocketStream) -> Result<(), WebSocketError> {
+      stream.send(Message::Text(CONN_RESP.to_string())).await?;
+      // Authentication.
+      assert_eq!(
+        stream.next().await.unwrap()?,
+        Message::Text(AUTH_REQ.to_string()),
+      );
+      stream.send(Message::Text(AUTH_RESP.to_string())).await?;
+
+      // Subscription.
+      assert_eq!(
+        stream.next().await.unwrap()?,
+        Message::Text(SUB_REQ.to_string()),
+      );
+      let response = r#"[{"T":"x"},{"T":"subscription","bars":["AAPL","VOO"]}]"#;
+      stream.send(Message::Text(response.to_string())).await?;
+      stream.send(Message::Close(None)).await?;
+      Ok(())
+    }
+
+    let (mut stream, mut subscription) =
+      mock_stream::<RealtimeData<IEX>, _, _>(test).await.unwrap();
+
+    let mut data = MarketData::default();
+    data.set_bars(["AAPL", "VOO"]);
+
+    let subscribe = subscription.subscribe(&data).boxed_local();
+    let _data = drive(subscribe, &mut stream)
+      .await
+      .unwrap()
+      .unwrap()
+      .unwrap();
+    assert_eq!(subscription.subscriptions(), &data);
+  }
+
+  /// Check that unsubscribing from inactive symbols does not result in
+  /// a request being sent.
+  #[test(tokio::test)]
+  async fn unsubscribe_noop() {
+    async fn test(mut stream: WebSocketStream) -> Result<(), WebSocketError> {
+      stream.send(Message::Text(CONN_RESP.to_string())).await?;
+      // Authentication.
+      assert_eq!(
+        stream.next().await.unwrap()?,
+        Message::Text(AUTH_REQ.to_string()),
+      );
+      stream.send(Message::Text(AUTH_RESP.to_string())).await?;
+      stream.send(Message::Close(None)).await?;
+      Ok(())
+    }
+
+    let (mut stream, mut subscription) =
+      mock_stream::<RealtimeData<IEX>, _, _>(test).await.unwrap();
+
+    let mut data = MarketData::default();
+    data.set_bars(["AAPL", "VOO"]);
+    data.quotes = Symbols::All;
+
+    let unsubscribe = subscription.unsubscribe(&data).boxed_local();
+    let _data = drive(unsubscribe, &mut stream)
+      .await
+      .unwrap()
+      .unwrap()
+      .unwrap();
+
+    assert_eq!(subscription.subscriptions(), &MarketData::default());
+  }
+
+  /// Check that a subscription request times out if the server does
+  /// not respond in time.
+  #[test(tokio::test)]
+  async fn subscribe_timeout() {
+    async fn test(mut stream: WebSocketStream) -> Result<(), WebSocketError> {
+      stream.send(Message::Text(CONN_RESP.to_string())).await?;
+      // Authentication.
+      assert_eq!(
+        stream.next().await.unwrap()?,
+        Message::Text(AUTH_REQ.to_string()),
+      );
+      stream.send(Message::Text(AUTH_RESP.to_string())).await?;
+
+      // Subscription. We never respond to it.
+      assert_eq!(
+        stream.next().await.unwrap()?,
+        Message::Text(SUB_REQ.to_string()),
+      );
+      let _ = stream.next().await;
+      Ok(())
+    }
+
+    let (mut stream, mut subscription) =
+      mock_stream::<RealtimeData<IEX>, _, _>(test).await.unwrap();
+    subscription.set_timeout(Some(Duration::from_millis(100)));
+
+    let mut data = MarketData::default();
+    data.set_bars(["AAPL", "VOO"]);
+
+    let subscribe = subscription.subscribe(&data).boxed_local();
+    let error = drive(subscribe, &mut stream)
+      .await
+      .unwrap()
+      .unwrap()
+      .unwrap_err();
+
+    match error {
+      Error::Str(ref e) if e == "subscription request timed out" => {},
+      e => panic!("received unexpected error: {e}"),
+    }
+  }
+
+  /// Check that we can explicitly re-authenticate an existing
+  /// connection.
+  #[test(tokio::test)]
+  async fn reauthenticate() {
+    async fn test(mut stream: WebSocketStream) -> Result<(), WebSocketError> {
+      stream.send(Message::Text(CONN_RESP.to_string())).await?;
+      // Authentication.
+      assert_eq!(
+        stream.next().await.unwrap()?,
+        Message::Text(AUTH_REQ.to_string()),
+      );
+      stream.send(Message::Text(AUTH_RESP.to_string())).await?;
+
+      // Re-authentication.
+      assert_eq!(
+        stream.next().await.unwrap()?,
+        Message::Text(AUTH_REQ.to_string()),
+      );
+      stream.send(Message::Text(AUTH_RESP.to_string())).await?;
+      stream.send(Message::Close(None)).await?;
+      Ok(())
+    }
+
+    let (mut stream, mut subscription) =
+      mock_stream::<RealtimeData<IEX>, _, _>(test).await.unwrap();
+
+    let authenticate = subscription.authenticate(KEY_ID, SECRET).boxed_local();
+    let () = drive(authenticate, &mut stream)
+      .await
+      .unwrap()
+      .unwrap()
+      .unwrap();
+  }
+
+  /// Check that we can replay recorded market data, with control
+  /// messages being filtered out.
+  #[test(tokio::test)]
+  async fn replay_recording() {
+    let recording = r#"[{"T":"success","msg":"connected"},{"T":"success","msg":"authenticated"}]
+[{"T":"subscription","trades":["SPY"],"quotes":[],"bars":["SPY"]}]
+
+[{"T":"t","S":"SPY","i":1,"p":400.1,"s":10,"t":"2022-11-29T15:00:00Z"},{"T":"b","S":"SPY","o":400,"h":401,"l":399,"c":400.5,"v":1000,"t":"2022-11-29T15:00:00Z"}]
+[{"T":"t","S":"SPY","i":2
+"#;
+
+    let items = replay::<_, Bar, Quote, Trade>(recording.as_bytes())
+      .collect::<Vec<_>>()
+      .await;
+    assert_eq!(items.len(), 3);
+
+    let trade = match items[0].as_ref().unwrap().as_ref().unwrap() {
+      Data::Trade(trade) => trade,
+      data => panic!("unexpected data: {data:?}"),
+    };
+    assert_eq!(trade.trade_id, 1);
+    assert!(items[1].as_ref().unwrap().as_ref().unwrap().is_bar());
+    assert!(items[2].as_ref().unwrap().is_err());
+  }
+
+  /// Check that we can convert errors into `OwnedStreamError`
+  /// objects.
+  #[test]
+  fn owned_stream_errors() {
+    let error = json_from_str::<Vec<DataMessage>>("[\n  {]").unwrap_err();
+    let owned = OwnedStreamError::from(&error);
+    assert_eq!(owned.to_string(), error.to_string());
+    match &owned {
+      OwnedStreamError::Json { line, column, .. } => {
+        assert_eq!(*line, error.line());
+        assert_eq!(*column, error.column());
+      },
+      error => panic!("unexpected error: {error:?}"),
+    }
+    assert_eq!(owned.clone(), owned);
+
+    let error = JsonError::custom("invalid value");
+    let owned = OwnedStreamError::from(&error);
+    assert_eq!(owned.to_string(), error.to_string());
+
+    let error = WebSocketError::ConnectionClosed;
+    let owned = OwnedStreamError::from(&error);
+    assert_eq!(owned, OwnedStreamError::WebSocket(error.to_string()));
+
+    let error = StreamError::from(StreamApiError {
+      code: 406,
+      message: "connection limit exceeded".to_string(),
+    });
+    let owned = OwnedStreamError::from(error.clone());
+    assert_eq!(owned, OwnedStreamError::Api(error));
+  }
+
+  /// Check that empty members of a `MarketData` object are omitted
+  /// when serializing it.
+  #[test]
+  fn serialize_market_data_omits_empty() {
+    let data = MarketData::with_bars(["AAPL"]);
+    let json = to_json(&data).unwrap();
+    assert_eq!(json, r#"{"bars":["AAPL"]}"#);
+
+    let request = Request::Subscribe(Cow::Owned(data));
+    let json = to_json(&request).unwrap();
+    assert_eq!(json, r#"{"action":"subscribe","bars":["AAPL"]}"#);
+    assert_eq!(json_from_str::<Request<'_>>(&json).unwrap(), request);
+  }
+
+  /// Check that we can split market data into chunks of a given size.
+  #[test]
+  fn market_data_chunks() {
+    let mut data = MarketData::builder()
+      .bars(["AAPL", "MSFT", "SPY"])
+      .trades(["VOO"])
+      .build();
+    data.quotes = Symbols::All;
+
+    let chunks = data.chunks(2);
+    assert_eq!(chunks.len(), 3);
+    assert_eq!(chunks[0].bars, ["AAPL", "MSFT"]);
+    assert!(chunks[0].quotes.is_empty());
+    assert_eq!(chunks[1].bars, ["SPY"]);
+    assert_eq!(chunks[1].quotes, Symbols::All);
+    assert!(chunks[1].trades.is_empty());
+    assert!(chunks[2].bars.is_empty());
+    assert_eq!(chunks[2].trades, ["VOO"]);
+
+    let mut merged = MarketData::default();
+    let () = chunks.iter().for_each(|chunk| merged.merge(chunk));
+    assert_eq!(merged, data);
+
+    assert!(MarketData::default().chunks(2).is_empty());
+  }
+
+  /// Check that the `MarketData::with_*` constructors set only the
+  /// respective member.
+  #[test]
+  fn market_data_with_symbols() {
+    let symbols = SymbolList::from(["MSFT", "AAPL"]);
+    let data = MarketData::with_bars(symbols.clone());
+    assert_eq!(data.bars, ["AAPL", "MSFT"]);
+    assert!(data.quotes.is_empty());
+    assert!(data.trades.is_empty());
+
+    let data = MarketData::with_quotes(symbols.clone());
+    assert_eq!(data.quotes, ["AAPL", "MSFT"]);
+    assert!(data.bars.is_empty());
+
+    let data = MarketData::with_trades(symbols);
+    assert_eq!(data.trades, ["AAPL", "MSFT"]);
+    assert!(data.bars.is_empty());
+  }
+
+  /// Check that we can count the symbols per kind of market data.
+  #[test]
+  fn market_data_counts() {
+    let mut data = MarketData::builder()
+      .bars(["AAPL", "MSFT"])
+      .trades(["*"])
+      .build();
+    data.quotes = Symbols::All;
+
+    let counts = data.counts();
+    assert_eq!(counts.bars, Count::List(2));
+    assert_eq!(counts.quotes, Count::All);
+    assert_eq!(counts.trades, Count::All);
+    assert_eq!(counts.daily_bars, Count::List(0));
+  }
+
+  /// Check that control messages are summarized without listing
+  /// individual symbols.
+  #[test]
+  fn summarize_control_messages() {
+    let mut data = MarketData::builder()
+      .bars(["AAPL", "MSFT"])
+      .trades(["SPY"])
+      .build();
+    data.quotes = Symbols::All;
+    let control = ControlMessage::Subscription(data);
+    assert_eq!(
+      ControlSummary(&control).to_string(),
+      "subscription (bars: 2, quotes: all, trades: 1, daily bars: 0, lulds: 0)"
+    );
+    let control = ControlMessage::<MarketData>::Success {
+      message: "authenticated".to_string(),
+    };
+    assert_eq!(
+      ControlSummary(&control).to_string(),
+      "success: authenticated"
+    );
+  }
+
+  /// Check that we report a typed error when the connection limit is
+  /// exceeded during authentication.
+  #[test(tokio::test)]
+  async fn connection_limit_exceeded() {
+    async fn test(mut stream: WebSocketStream) -> Result<(), WebSocketError> {
+      stream.send(Message::Text(CONN_RESP.to_string())).await?;
+      // Authentication.
+      assert_eq!(
+        stream.next().await.unwrap()?,
+        Message::Text(AUTH_REQ.to_string()),
+      );
+      let response = r#"[{"T":"error","code":406,"msg":"connection limit exceeded"}]"#;
+      stream.send(Message::Text(response.to_string())).await?;
+      stream.send(Message::Close(None)).await?;
+      Ok(())
+    }
+
+    let result = mock_stream::<RealtimeData<IEX>, _, _>(test).await;
+    let error = match result {
+      Err(Error::Stream(error @ StreamError::ConnectionLimitExceeded(..))) => error,
+      Err(error) => panic!("received unexpected error: {error}"),
+      Ok(..) => panic!("authentication succeeded unexpectedly"),
+    };
+    assert_eq!(error.api_error().code, 406);
+    assert_eq!(error.api_error().message, "connection limit exceeded");
+  }
+
+  /// Check that we keep track of whether the connection is
+  /// authenticated.
+  #[test(tokio::test)]
+  async fn authentication_state() {
+    async fn test(mut stream: WebSocketStream) -> Result<(), WebSocketError> {
+      stream.send(Message::Text(CONN_RESP.to_string())).await?;
+      // Authentication.
+      assert_eq!(
+        stream.next().await.unwrap()?,
+        Message::Text(AUTH_REQ.to_string()),
+      );
+      stream.send(Message::Text(AUTH_RESP.to_string())).await?;
+
+      // Re-authentication.
+      assert_eq!(
+        stream.next().await.unwrap()?,
+        Message::Text(AUTH_REQ.to_string()),
+      );
+      let response = r#"[{"T":"error","code":402,"msg":"auth failed"}]"#;
+      stream.send(Message::Text(response.to_string())).await?;
+      stream.send(Message::Close(None)).await?;
+      Ok(())
+    }
+
+    let (mut stream, mut subscription) =
+      mock_stream::<RealtimeData<IEX>, _, _>(test).await.unwrap();
+    assert!(subscription.is_authenticated());
+
+    let authenticate = subscription.authenticate(KEY_ID, SECRET).boxed_local();
+    let error = drive(authenticate, &mut stream)
+      .await
+      .unwrap()
+      .unwrap()
+      .unwrap_err();
+
+    match error {
+      Error::Stream(StreamError::AuthenticationFailed(..)) => (),
+      e => panic!("received unexpected error: {e}"),
+    }
+    assert!(!subscription.is_authenticated());
+  }
+
+  /// Check that the handshake requires the server to send the
+  /// `connected` message before the `authenticated` one.
+  #[test(tokio::test)]
+  async fn wait_connected_then_authenticate() {
+    let (mut stream, mut subscription, injector) =
+      mock_subscription::<Bar, Quote, Trade, _>(|request| match request {
+        Request::Authenticate { .. } => vec![DataMessage::Success {
+          message: "authenticated".to_string(),
+        }],
+        request => panic!("unexpected request: {request:?}"),
+      });
+
+    let () = injector.push(DataMessage::Success {
+      message: "connected".to_string(),
+    });
+    let connect = subscription.wait_connected().boxed_local();
+    let () = drive(connect, &mut stream).await.unwrap().unwrap();
+
+    let authenticate = subscription.authenticate(KEY_ID, SECRET).boxed_local();
+    let () = drive(authenticate, &mut stream)
+      .await
+      .unwrap()
+      .unwrap()
+      .unwrap();
+    assert!(subscription.is_authenticated());
+
+    // An `authenticated` message is not an acceptable greeting.
+    let () = injector.push(DataMessage::Success {
+      message: "authenticated".to_string(),
+    });
+    let connect = subscription.wait_connected().boxed_local();
+    let error = drive(connect, &mut stream).await.unwrap().unwrap_err();
+    assert!(matches!(error, Error::Str(..)), "{error}");
+  }
+
+  /// Check that we can authenticate using the credentials of an
+  /// `ApiInfo` object.
+  #[test(tokio::test)]
+  async fn authenticate_with_api_info() {
+    let (mut stream, mut subscription, _injector) =
+      mock_subscription::<Bar, Quote, Trade, _>(|request| match request {
+        Request::Authenticate { key_id, secret } => {
+          assert_eq!(key_id, KEY_ID);
+          assert_eq!(secret, SECRET);
+          vec![DataMessage::Success {
+            message: "authenticated".to_string(),
+          }]
+        },
+        request => panic!("unexpected request: {request:?}"),
+      });
+
+    let api_info = ApiInfo::from_parts(API_BASE_URL, KEY_ID, SECRET).unwrap();
+    let authenticate = subscription.authenticate_with(&api_info).boxed_local();
+    let () = drive(authenticate, &mut stream)
+      .await
+      .unwrap()
+      .unwrap()
+      .unwrap();
+    assert!(subscription.is_authenticated());
+  }
+
+  /// Check that authentication only succeeds once the server reported
+  /// the connection as authenticated.
+  #[test(tokio::test)]
+  async fn authenticate_unexpected_success() {
+    async fn test(mut stream: WebSocketStream) -> Result<(), WebSocketError> {
+      stream.send(Message::Text(CONN_RESP.to_string())).await?;
+      // Authentication.
+      assert_eq!(
+        stream.next().await.unwrap()?,
+        Message::Text(AUTH_REQ.to_string()),
+      );
+      stream.send(Message::Text(AUTH_RESP.to_string())).await?;
+
+      // Re-authentication.
+      assert_eq!(
+        stream.next().await.unwrap()?,
+        Message::Text(AUTH_REQ.to_string()),
+      );
+      stream.send(Message::Text(CONN_RESP.to_string())).await?;
+      stream.send(Message::Close(None)).await?;
+      Ok(())
+    }
+
+    let (mut stream, mut subscription) =
+      mock_stream::<RealtimeData<IEX>, _, _>(test).await.unwrap();
+
+    let authenticate = subscription.authenticate(KEY_ID, SECRET).boxed_local();
+    let error = drive(authenticate, &mut stream)
+      .await
+      .unwrap()
+      .unwrap()
+      .unwrap_err();
+
+    match error {
+      Error::Str(message) => assert!(message.contains("connected"), "{message}"),
+      e => panic!("received unexpected error: {e}"),
+    }
+    assert!(!subscription.is_authenticated());
+  }
+
+  /// Check that we keep track of the messages received.
+  #[test(tokio::test)]
+  async fn stream_stats() {
+    async fn test(mut stream: WebSocketStream) -> Result<(), WebSocketError> {
+      stream.send(Message::Text(CONN_RESP.to_string())).await?;
+      // Authentication.
+      assert_eq!(
+        stream.next().await.unwrap()?,
+        Message::Text(AUTH_REQ.to_string()),
+      );
+      stream.send(Message::Text(AUTH_RESP.to_string())).await?;
+
+      // Subscription.
+      assert_eq!(
+        stream.next().await.unwrap()?,
+        Message::Text(SUB_REQ.to_string()),
+      );
+      stream.send(Message::Text(SUB_RESP.to_string())).await?;
+      stream.send(Message::Text(BAR_RESP.to_string())).await?;
+      stream.send(Message::Close(None)).await?;
+      Ok(())
+    }
+
+    let (mut stream, mut subscription) =
+      mock_stream::<RealtimeData<IEX>, _, _>(test).await.unwrap();
+
+    let expected = StreamStats {
+      successes: 2,
+      ..Default::default()
+    };
+    assert_eq!(subscription.stats(), expected);
+
+    let mut data = MarketData::default();
+    data.set_bars(["AAPL", "VOO"]);
+
+    let subscribe = subscription.subscribe(&data).boxed_local();
+    let _data = drive(subscribe, &mut stream)
+      .await
+      .unwrap()
+      .unwrap()
+      .unwrap();
+
+    stream
+      .map_err(Error::WebSocket)
+      .try_for_each(|result| async { result.map(|_data| ()).map_err(Error::Json) })
+      .await
+      .unwrap();
+
+    let expected = StreamStats {
+      bars: 2,
+      successes: 2,
+      ..Default::default()
+    };
+    assert_eq!(subscription.stats(), expected);
+  }
+
+  /// Check that we correctly handle errors reported as part of
+  /// subscription.
+  #[test(tokio::test)]
+  async fn subscribe_error() {
+    async fn test(mut stream: WebSocketStream) -> Result<(), WebSocketError> {
+      stream.send(Message::Text(CONN_RESP.to_string())).await?;
+      // Authentication.
+      assert_eq!(
+        stream.next().await.unwrap()?,
+        Message::Text(AUTH_REQ.to_string()),
+      );
+      stream.send(Message::Text(AUTH_RESP.to_string())).await?;
+
+      // Subscription.
+      assert_eq!(
+        stream.next().await.unwrap()?,
+        Message::Text(SUB_ERR_REQ.to_string()),
+      );
+      stream.send(Message::Text(SUB_ERR_RESP.to_string())).await?;
+      stream.send(Message::Close(None)).await?;
+      Ok(())
+    }
+
+    let (mut stream, mut subscription) =
+      mock_stream::<RealtimeData<IEX>, _, _>(test).await.unwrap();
+
+    let mut data = MarketData::default();
+    data.set_quotes(["AAPL"]);
+
+    let subscribe = subscription.subscribe(&data).boxed_local();
+    let error = drive(subscribe, &mut stream)
+      .await
+      .unwrap()
+      .unwrap()
+      .unwrap_err();
+
+    match error {
+      Error::Stream(StreamError::InvalidSyntax(ref e)) if e.code == 400 => {},
+      e => panic!("received unexpected error: {e}"),
+    }
+  }
+
+  /// Check that we keep track of the last control message received in
+  /// response to an operation.
+  #[test(tokio::test)]
+  async fn last_control_message() {
+    let (mut stream, mut subscription, _injector) =
+      mock_subscription::<Bar, Quote, Trade, _>(|request| match request {
+        Request::Subscribe(..) => vec![DataMessage::Error(StreamApiError {
+          code: 400,
+          message: "invalid syntax".to_string(),
+        })],
+        request => panic!("unexpected request: {request:?}"),
+      });
+    assert_eq!(subscription.last_control(), None);
+
+    let data = MarketData::with_quotes(["AAPL"]);
+    let subscribe = subscription.subscribe(&data).boxed_local();
+    let _error = drive(subscribe, &mut stream)
+      .await
+      .unwrap()
+      .unwrap()
+      .unwrap_err();
+
+    match subscription.last_control() {
+      Some(ControlMessage::Error(error)) => assert_eq!(error.code, 400),
+      control => panic!("unexpected control message: {control:?}"),
+    }
+  }
+
+  /// Check that we can adjust the current market data subscription on
+  /// the fly.
+  #[test(tokio::test)]
+  #[serial(realtime_data)]
+  async fn subscribe_resubscribe() {
+    let api_info = ApiInfo::from_env().unwrap();
+    let client = Client::new(api_info);
+    let (mut stream, mut subscription) = client.subscribe::<RealtimeData<IEX>>().await.unwrap();
+
+    let mut data = MarketData::default();
+    data.set_bars(["AAPL", "SPY"]);
+
+    let subscribe = subscription.subscribe(&data).boxed_local();
+    let _data = drive(subscribe, &mut stream)
+      .await
+      .unwrap()
+      .unwrap()
+      .unwrap();
+
+    assert_eq!(subscription.subscriptions(), &data);
+
+    let mut data = MarketData::default();
+    data.set_bars(["XLK"]);
+    let subscribe = subscription.subscribe(&data).boxed_local();
+    let _data = drive(subscribe, &mut stream)
+      .await
+      .unwrap()
+      .unwrap()
+      .unwrap();
+
+    let mut expected = MarketData::default();
+    expected.set_bars(["AAPL", "SPY", "XLK"]);
+    assert_eq!(subscription.subscriptions(), &expected);
+  }
+
+  /// Check that we can stream realtime market data updates.
+  ///
+  /// Note that we do not have any control over whether the market is
+  /// open or not and as such we can only try on a best-effort basis to
+  /// receive and decode updates.
+  #[test(tokio::test)]
+  #[serial(realtime_data)]
+  async fn stream_market_data_updates() {
+    let api_info = ApiInfo::from_env().unwrap();
+    let client = Client::new(api_info);
+    let (mut stream, mut subscription) = client.subscribe::<RealtimeData<IEX>>().await.unwrap();
+
+    let data = MarketData {
+      bars: Symbols::All,
+      ..Default::default()
+    };
+
+    let subscribe = subscription.subscribe(&data).boxed_local();
+    let _data = drive(subscribe, &mut stream)
+      .await
+      .unwrap()
+      .unwrap()
+      .unwrap();
+
+    assert_eq!(subscription.subscriptions(), &data);
+
+    let read = stream
+      .map_err(Error::WebSocket)
+      .try_for_each(|result| async {
+        result
+          .map(|data| {
+            assert!(data.is_bar());
+          })
+          .map_err(Error::Json)
+      });
+
+    if timeout(Duration::from_millis(100), read).await.is_ok() {
+      panic!("realtime data stream got exhausted unexpectedly")
+    }
+  }
+
+  /// Check that we can stream realtime stock quotes.
+  ///
+  /// Note that we do not have any control over whether the market is
+  /// open or not and as such we can only try on a best-effort basis to
+  /// receive and decode updates.
+  #[test(tokio::test)]
+  #[serial(realtime_data)]
+  async fn stream_quotes() {
+    async fn test<S>()
+    where
+      S: Source,
+    {
+      let api_info = ApiInfo::from_env().unwrap();
+      let client = Client::new(api_info);
+      let (mut stream, mut subscription) = client.subscribe::<RealtimeData<S>>().await.unwrap();
+
+      let mut data = MarketData::default();
+      data.set_quotes(["SPY"]);
+
+      let subscribe = subscription.subscribe(&data).boxed_local();
+      let _data = drive(subscribe, &mut stream)
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+
+      let read = stream
+        .map_err(Error::WebSocket)
+        .try_for_each(|result| async {
+          result
+            .map(|data| {
+              assert!(data.is_quote());
+            })
+            .map_err(Error::Json)
+        });
+
+      if timeout(Duration::from_millis(100), read).await.is_ok() {
+        panic!("realtime data stream got exhausted unexpectedly")
+      }
+    }
+
+    test::<IEX>().await;
+
+    #[derive(Default)]
+    struct IexWithUrl;
+
+    impl ToString for IexWithUrl {
+      fn to_string(&self) -> String {
+        "wss://stream.data.alpaca.markets/v2/iex".into()
+      }
+    }
+
+    test::<CustomUrl<IexWithUrl>>().await;
+  }
+
+  /// Check that we can stream realtime stock trades.
+  #[test(tokio::test)]
+  #[serial(realtime_data)]
+  async fn stream_trades() {
+    let api_info = ApiInfo::from_env().unwrap();
+    let client = Client::new(api_info);
+    let (mut stream, mut subscription) = client.subscribe::<RealtimeData<IEX>>().await.unwrap();
+
+    let mut data = MarketData::default();
+    data.set_trades(["SPY"]);
+
+    let subscribe = subscription.subscribe(&data).boxed_local();
+    let _data = drive(subscribe, &mut stream)
+      .await
+      .unwrap()
+      .unwrap()
+      .unwrap();
+
+    let read = stream
+      .map_err(Error::WebSocket)
+      .try_for_each(|result| async {
+        result
+          .map(|data| {
+            assert!(data.is_trade());
+          })
+          .map_err(Error::Json)
+      });
+
+    if timeout(Duration::from_millis(100), read).await.is_ok() {
+      panic!("realtime data stream got exhausted unexpectedly")
+    }
+  }
+
+  /// Check that the Alpaca API reports no error when unsubscribing
+  /// from a symbol not currently subscribed to.
+  #[test(tokio::test)]
+  #[serial(realtime_data)]
+  async fn unsubscribe_not_subscribed_symbol() {
+    let api_info = ApiInfo::from_env().unwrap();
+    let client = Client::new(api_info);
+    let (mut stream, mut subscription) = client.subscribe::<RealtimeData<IEX>>().await.unwrap();
+
+    let mut data = MarketData::default();
+    data.set_bars(["AAPL"]);
+
+    let unsubscribe = subscription.unsubscribe(&data).boxed_local();
+    let _data = drive(unsubscribe, &mut stream)
+      .await
+      .unwrap()
+      .unwrap()
+      .unwrap();
+
+    assert_eq!(subscription.subscriptions(), &MarketData::default());
+  }
+
+  /// Test that we fail as expected when attempting to authenticate for
+  /// real time market updates using invalid credentials.
+  #[test(tokio::test)]
+  #[serial(realtime_data)]
+  async fn stream_with_invalid_credentials() {
+    let api_info = ApiInfo::from_parts(API_BASE_URL, "invalid", "invalid-too").unwrap();
+    let client = Client::new(api_info);
+    let err = client.subscribe::<RealtimeData<IEX>>().await.unwrap_err();
+
+    match err {
+      Error::Stream(StreamError::AuthenticationFailed(..)) => (),
+      e => panic!("received unexpected error: {e}"),
+    }
+  }
+
+  /// Check that we fail connection as expected on an invalid URL.
+  #[test(tokio::test)]
+  #[serial(realtime_data)]
+  async fn stream_with_invalid_url() {
+    #[derive(Default)]
+    struct Invalid;
+
+    impl ToString for Invalid {
+      fn to_string(&self) -> String {
+        "<invalid-url-is-invalid>".into()
+      }
+    }
+
+    let api_info = ApiInfo::from_env().unwrap();
+    let client = Client::new(api_info);
+    let err = client
+      .subscribe::<RealtimeData<CustomUrl<Invalid>>>()
+      .await
+      .unwrap_err();
+
+    match err {
+      Error::Url(..) => (),
+      _ => panic!("Received unexpected error: {err:?}"),
+    };
+  }
+}
@@ -0,0 +1,503 @@
+// Copyright (C) 2021-2023 The apca Developers
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::fmt::Debug;
+use std::pin::Pin;
+
+use futures::stream::Fuse;
+use futures::stream::FusedStream;
+use futures::stream::SplitSink;
+use futures::stream::SplitStream;
+use futures::task::Context;
+use futures::task::Poll;
+use futures::FutureExt as _;
+use futures::Sink;
+use futures::Stream as FuturesStream;
+use futures::StreamExt as _;
+
+use serde::de::DeserializeOwned;
+
+use websocket_util::subscribe::MessageStream;
+use websocket_util::tungstenite::Error as WebSocketError;
+use websocket_util::wrap;
+
+use super::drive;
+use super::field_mut;
+use super::stream_error;
+use super::Bar;
+use super::Channels;
+use super::DataMessage;
+use super::MarketData;
+use super::ParsedMessage;
+use super::Quote;
+use super::RealtimeData;
+use super::Source;
+use super::Stream;
+use super::StreamMessage;
+use super::SubscribeOutcome;
+use super::Subscription;
+use super::Symbol;
+use super::SymbolList;
+use super::Symbols;
+use super::Trade;
+use super::UserMessage;
+
+use crate::subscribable::Subscribable as _;
+use crate::ApiInfo;
+use crate::Error;
+
+
+/// A manager spreading market data subscriptions across multiple
+/// connections.
+///
+/// Alpaca limits the number of symbols that can be subscribed to over
+/// a single connection. A `SubscriptionPool` distributes symbols over
+/// the connections it manages, making sure that no connection exceeds
+/// a configurable maximum, and keeps track of which connection is
+/// subscribed to which symbols, so that unsubscription requests are
+/// routed correctly.
+///
+/// Data received over any of the connections is available through the
+/// pool's [`Stream`][FuturesStream] implementation. Note that, akin to
+/// [`drive`], data arriving while a subscription change is in progress
+/// is discarded.
+#[derive(Debug)]
+pub struct SubscriptionPool<St, Si, M = DataMessage>
+where
+  M: StreamMessage,
+{
+  /// The maximum number of symbols to subscribe to per connection.
+  max_symbols: usize,
+  /// The managed connections.
+  connections: Vec<(St, Subscription<Si, M>)>,
+  /// The index of the connection to poll first for data, used for
+  /// ensuring fairness among connections.
+  next: usize,
+}
+
+
+/// A [`SubscriptionPool`] managing connections established via
+/// [`RealtimeData`].
+pub type RealtimeDataPool<B = Bar, Q = Quote, T = Trade> = SubscriptionPool<
+  Fuse<
+    MessageStream<SplitStream<Stream<DataMessage<B, Q, T>>>, ParsedMessage<DataMessage<B, Q, T>>>,
+  >,
+  SplitSink<Stream<DataMessage<B, Q, T>>, wrap::Message>,
+  DataMessage<B, Q, T>,
+>;
+
+
+/// Count the number of subscription slots occupied by the given market
+/// data. A subscription to all symbols occupies a single slot.
+fn load<C>(data: &C) -> usize
+where
+  C: Channels,
+{
+  data
+    .fields()
+    .into_iter()
+    .map(|symbols| match symbols {
+      Symbols::List(list) => list.len(),
+      Symbols::All => 1,
+    })
+    .sum()
+}
+
+impl<St, Si, M> SubscriptionPool<St, Si, M>
+where
+  M: StreamMessage,
+{
+  /// Create a new, empty pool, subscribing to at most `max_symbols`
+  /// symbols per connection.
+  #[inline]
+  pub fn new(max_symbols: usize) -> Self {
+    Self {
+      max_symbols,
+      connections: Vec::new(),
+      next: 0,
+    }
+  }
+
+  /// Add a connection to the pool.
+  ///
+  /// Symbols already subscribed to over the connection are accounted
+  /// for, but the connection is allowed to exceed the pool's maximum.
+  #[inline]
+  pub fn push(&mut self, stream: St, subscription: Subscription<Si, M>) {
+    let () = self.connections.push((stream, subscription));
+  }
+
+  /// Retrieve the number of connections managed by the pool.
+  #[inline]
+  pub fn len(&self) -> usize {
+    self.connections.len()
+  }
+
+  /// Check whether the pool manages no connections at all.
+  #[inline]
+  pub fn is_empty(&self) -> bool {
+    self.connections.is_empty()
+  }
+
+  /// Retrieve the subscriptions of the individual connections, in the
+  /// order in which the connections were added.
+  pub fn connection_subscriptions(&self) -> impl Iterator<Item = &M::Channels> {
+    self
+      .connections
+      .iter()
+      .map(|(_, subscription)| subscription.subscriptions())
+  }
+
+  /// Inquire the combined market data subscriptions of all
+  /// connections.
+  pub fn subscriptions(&self) -> M::Channels {
+    self
+      .connection_subscriptions()
+      .fold(M::Channels::default(), |mut data, subscriptions| {
+        let () = data.merge(subscriptions);
+        data
+      })
+  }
+
+  /// Determine the number of additional connections needed to
+  /// subscribe to the provided market data.
+  pub fn missing_connections(&self, data: &M::Channels) -> usize {
+    let subscriptions = self.subscriptions();
+    let (mut new, _) = data.diff(&subscriptions);
+    let () = new.strip_covered(&subscriptions);
+    let free = self
+      .connection_subscriptions()
+      .map(|subscriptions| self.max_symbols.saturating_sub(load(subscriptions)))
+      .sum::<usize>();
+    let needed = load(&new).saturating_sub(free);
+
+    match self.max_symbols {
+      0 => 0,
+      max => (needed + max - 1) / max,
+    }
+  }
+
+  /// Distribute the provided market data over the managed connections,
+  /// returning a request for each of them.
+  fn distribute(&self, data: &M::Channels) -> Result<Vec<M::Channels>, Error> {
+    let mut loads = self
+      .connection_subscriptions()
+      .map(load)
+      .collect::<Vec<_>>();
+    // The symbols to subscribe to, per field and connection.
+    let mut symbols = vec![vec![Vec::<Symbol>::new(); loads.len()]; data.fields().len()];
+    let mut requests = vec![M::Channels::default(); loads.len()];
+
+    let mut data = data.clone();
+    for (index, symbols) in symbols.iter_mut().enumerate() {
+      let list = match field_mut(&mut data, index) {
+        Symbols::List(list) => list,
+        Symbols::All => {
+          // A subscription to all symbols always goes to the first
+          // connection.
+          let request = requests
+            .first_mut()
+            .ok_or_else(|| Error::Str("no connection available for subscription".into()))?;
+          *field_mut(request, index) = Symbols::All;
+          loads[0] += 1;
+          continue
+        },
+      };
+
+      for symbol in list.iter() {
+        let connection = loads
+          .iter()
+          .position(|load| *load < self.max_symbols)
+          .ok_or_else(|| Error::Str("insufficient connection capacity for subscription".into()))?;
+        let () = symbols[connection].push(symbol.clone());
+        loads[connection] += 1;
+      }
+    }
+
+    for (index, symbols) in symbols.into_iter().enumerate() {
+      for (request, symbols) in requests.iter_mut().zip(symbols) {
+        if !symbols.is_empty() {
+          *field_mut(request, index) = Symbols::List(SymbolList::from(symbols));
+        }
+      }
+    }
+    Ok(requests)
+  }
+}
+
+impl<St, Si, M> SubscriptionPool<St, Si, M>
+where
+  St: FusedStream<Item = UserMessage<M>> + Unpin,
+  Si: Sink<wrap::Message, Error = WebSocketError> + Unpin,
+  M: StreamMessage,
+  M::Data: Debug,
+{
+  /// Subscribe to the provided market data, distributing the symbols
+  /// not yet subscribed to over the managed connections.
+  ///
+  /// If the connections' remaining capacity does not suffice, an error
+  /// is reported without any request being sent. Use
+  /// [`missing_connections`][Self::missing_connections] to determine
+  /// how many connections to add beforehand.
+  ///
+  /// Requests are issued one connection after the other. Should one of
+  /// them fail, no further requests are sent, but subscriptions
+  /// acknowledged over earlier connections stay in place. The outcome
+  /// reports which market data was acknowledged and which is still
+  /// pending, along with the error that caused the operation to stop.
+  pub async fn subscribe(
+    &mut self,
+    data: &M::Channels,
+  ) -> Result<SubscribeOutcome<M::Channels>, Error> {
+    let subscriptions = self.subscriptions();
+    let (mut new, _) = data.diff(&subscriptions);
+    let () = new.strip_covered(&subscriptions);
+    let requests = self.distribute(&new)?;
+
+    let mut outcome = SubscribeOutcome {
+      acknowledged: M::Channels::default(),
+      pending: M::Channels::default(),
+      rejected: M::Channels::default(),
+      error: None,
+    };
+    let mut requests = self
+      .connections
+      .iter_mut()
+      .zip(requests)
+      .filter(|(_, request)| !request.is_empty());
+
+    for ((stream, subscription), request) in requests.by_ref() {
+      let subscribe = subscription.subscribe(&request).boxed_local();
+      let result = match drive(subscribe, stream).await {
+        Ok(Ok(result)) => result,
+        Ok(Err(err)) => Err(Error::from(err)),
+        Err(err) => Err(stream_error(err)),
+      };
+
+      match result {
+        Ok(subscribed) => {
+          let (rejected, _) = request.diff(&subscribed);
+          let (accepted, _) = request.diff(&rejected);
+          let () = outcome.acknowledged.merge(&accepted);
+          let () = outcome.rejected.merge(&rejected);
+        },
+        Err(error) => {
+          let () = outcome.pending.merge(&request);
+          outcome.error = Some(error);
+          break
+        },
+      }
+    }
+
+    let () = requests.for_each(|(_, request)| outcome.pending.merge(&request));
+    Ok(outcome)
+  }
+
+  /// Unsubscribe from the provided market data, on whichever
+  /// connections are subscribed to it.
+  pub async fn unsubscribe(&mut self, data: &M::Channels) -> Result<(), Error> {
+    for (stream, subscription) in self.connections.iter_mut() {
+      let unsubscribe = subscription.unsubscribe(data).boxed_local();
+      let _data = drive(unsubscribe, stream).await.map_err(stream_error)???;
+    }
+    Ok(())
+  }
+}
+
+impl<B, Q, T> RealtimeDataPool<B, Q, T>
+where
+  B: Send + Unpin + Debug + DeserializeOwned,
+  Q: Send + Unpin + Debug + DeserializeOwned,
+  T: Send + Unpin + Debug + DeserializeOwned,
+{
+  /// Subscribe to the provided market data, establishing additional
+  /// connections to the source `S` as necessary.
+  ///
+  /// Please refer to [`subscribe`][Self::subscribe] for details on the
+  /// outcome reported.
+  pub async fn connect_and_subscribe<S>(
+    &mut self,
+    data: &MarketData,
+    api_info: &ApiInfo,
+  ) -> Result<SubscribeOutcome, Error>
+  where
+    S: Source,
+  {
+    for _ in 0..self.missing_connections(data) {
+      let (stream, subscription) =
+        RealtimeData::<S, DataMessage<B, Q, T>>::connect(api_info).await?;
+      let () = self.push(stream, subscription);
+    }
+    self.subscribe(data).await
+  }
+}
+
+impl<St, Si, M> FuturesStream for SubscriptionPool<St, Si, M>
+where
+  St: FusedStream + Unpin,
+  Si: Unpin,
+  M: StreamMessage + Unpin,
+{
+  type Item = St::Item;
+
+  fn poll_next(self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+    let this = Pin::get_mut(self);
+    let count = this.connections.len();
+    let mut terminated = 0;
+
+    for offset in 0..count {
+      let index = (this.next + offset) % count;
+      let (stream, _) = &mut this.connections[index];
+      if stream.is_terminated() {
+        terminated += 1;
+        continue
+      }
+
+      match stream.poll_next_unpin(ctx) {
+        Poll::Ready(Some(item)) => {
+          this.next = (index + 1) % count;
+          return Poll::Ready(Some(item))
+        },
+        Poll::Ready(None) => terminated += 1,
+        Poll::Pending => (),
+      }
+    }
+
+    if terminated == count {
+      Poll::Ready(None)
+    } else {
+      Poll::Pending
+    }
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use serde_json::from_str as json_from_str;
+
+  use test_log::test;
+
+  use crate::data::v2::stream::test::mock_subscription;
+  use crate::data::v2::stream::tests::respond;
+  use crate::data::v2::stream::tests::BAR_RESP;
+  use crate::data::v2::stream::Data;
+  use crate::data::v2::stream::Request;
+  use crate::data::v2::stream::StreamApiError;
+  use crate::data::v2::stream::StreamError;
+
+
+  /// Check that a `SubscriptionPool` distributes symbols over its
+  /// connections and routes unsubscription requests correctly.
+  #[test(tokio::test)]
+  async fn pool_subscribe_unsubscribe() {
+    let mut pool = SubscriptionPool::new(2);
+    let (stream, subscription, _injector1) = mock_subscription(respond());
+    let () = pool.push(stream, subscription);
+    let (stream, subscription, _injector2) = mock_subscription(respond());
+    let () = pool.push(stream, subscription);
+
+    let mut data = MarketData::default();
+    data.set_bars(["AAPL", "MSFT", "SPY"]);
+    assert_eq!(pool.missing_connections(&data), 0);
+    let outcome = pool.subscribe(&data).await.unwrap();
+    assert_eq!(outcome.acknowledged, data);
+    assert!(outcome.pending.is_empty());
+    assert!(outcome.error.is_none());
+    assert_eq!(pool.subscriptions(), data);
+
+    let mut first = MarketData::default();
+    first.set_bars(["AAPL", "MSFT"]);
+    let mut second = MarketData::default();
+    second.set_bars(["SPY"]);
+    let subscriptions = pool.connection_subscriptions().collect::<Vec<_>>();
+    assert_eq!(subscriptions, [&first, &second]);
+
+    // Only one slot is left, so subscribing to two more symbols
+    // requires an additional connection.
+    let mut data = MarketData::default();
+    data.set_quotes(["AAPL", "VOO"]);
+    assert_eq!(pool.missing_connections(&data), 1);
+    let _err = pool.subscribe(&data).await.unwrap_err();
+    let subscriptions = pool.connection_subscriptions().collect::<Vec<_>>();
+    assert_eq!(subscriptions, [&first, &second]);
+
+    let mut data = MarketData::default();
+    data.set_bars(["SPY"]);
+    let () = pool.unsubscribe(&data).await.unwrap();
+    let subscriptions = pool.connection_subscriptions().collect::<Vec<_>>();
+    assert_eq!(subscriptions, [&first, &MarketData::default()]);
+  }
+
+  /// Check that a `SubscriptionPool` reports the market data
+  /// acknowledged over earlier connections when a request on a later
+  /// one fails.
+  #[test(tokio::test)]
+  async fn pool_subscribe_partial_failure() {
+    let responder = |reject: bool| {
+      let mut respond = respond();
+      move |request: Request<'_>| -> Vec<DataMessage> {
+        if reject {
+          vec![DataMessage::Error(StreamApiError {
+            code: 405,
+            message: "symbol limit exceeded".to_string(),
+          })]
+        } else {
+          respond(request)
+        }
+      }
+    };
+
+    let mut pool = SubscriptionPool::new(2);
+    let (stream, subscription, _injector1) = mock_subscription(responder(false));
+    let () = pool.push(stream, subscription);
+    let (stream, subscription, _injector2) = mock_subscription(responder(true));
+    let () = pool.push(stream, subscription);
+
+    let mut data = MarketData::default();
+    data.set_bars(["AAPL", "MSFT", "SPY"]);
+    let outcome = pool.subscribe(&data).await.unwrap();
+
+    let mut first = MarketData::default();
+    first.set_bars(["AAPL", "MSFT"]);
+    let mut second = MarketData::default();
+    second.set_bars(["SPY"]);
+    assert_eq!(outcome.acknowledged, first);
+    assert_eq!(outcome.pending, second);
+    assert!(outcome.rejected.is_empty());
+    match outcome.error {
+      Some(Error::Stream(StreamError::InvalidSubscription(error))) => assert_eq!(error.code, 405),
+      error => panic!("received unexpected error: {error:?}"),
+    }
+
+    let subscriptions = pool.connection_subscriptions().collect::<Vec<_>>();
+    assert_eq!(subscriptions, [&first, &MarketData::default()]);
+  }
+
+  /// Check that a `SubscriptionPool` yields data from all of its
+  /// connections.
+  #[test(tokio::test)]
+  async fn pool_stream() {
+    let mut pool = SubscriptionPool::new(2);
+    let (stream, subscription, injector1) = mock_subscription(respond());
+    let () = pool.push(stream, subscription);
+    let (stream, subscription, injector2) = mock_subscription(respond());
+    let () = pool.push(stream, subscription);
+
+    let mut messages = json_from_str::<Vec<DataMessage>>(BAR_RESP).unwrap();
+    let () = injector2.push(messages.pop().unwrap());
+    let () = injector1.push(messages.pop().unwrap());
+
+    let mut symbols = Vec::new();
+    for _ in 0..2 {
+      match pool.next().await.unwrap().unwrap().unwrap() {
+        Data::Bar { bar, .. } => symbols.push(bar.symbol),
+        data => panic!("received unexpected data: {data:?}"),
+      }
+    }
+    let () = symbols.sort();
+    assert_eq!(symbols, ["AAPL", "VOO"]);
+  }
+}
@@ -0,0 +1,721 @@
+// Copyright (C) 2021-2023 The apca Developers
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::collections::hash_map::RandomState;
+use std::fmt::Debug;
+use std::fmt::Formatter;
+use std::fmt::Result as FmtResult;
+use std::hash::BuildHasher as _;
+use std::hash::Hasher as _;
+use std::pin::Pin;
+use std::time::Duration;
+
+use futures::future::BoxFuture;
+use futures::stream::FusedStream;
+use futures::task::Context;
+use futures::task::Poll;
+use futures::Future;
+use futures::FutureExt as _;
+use futures::Sink;
+use futures::Stream as FuturesStream;
+use futures::StreamExt as _;
+
+use tokio::time::sleep;
+
+use tracing::debug;
+
+use websocket_util::tungstenite::Error as WebSocketError;
+use websocket_util::wrap;
+
+use super::drive;
+use super::stream_error;
+use super::DataMessage;
+use super::Generational;
+use super::StreamError;
+use super::StreamMessage;
+use super::Subscription;
+use super::UserMessage;
+
+use crate::Error;
+
+
+/// Retrieve a pseudo-random value in the range `[0, 1)`.
+///
+/// The quality of the returned values is sufficient for spreading out
+/// reconnection attempts, but nothing else.
+fn random_fraction() -> f64 {
+  // Each `RandomState` is seeded differently, so hashing nothing gives
+  // us a fresh pseudo-random value every time.
+  let value = RandomState::new().build_hasher().finish();
+  (value >> 11) as f64 / (1u64 << 53) as f64
+}
+
+
+/// A policy for computing the delay between successive attempts of
+/// reconnecting to the realtime data stream.
+///
+/// Delays grow exponentially with the number of failed attempts, up
+/// to a maximum, and are randomly shortened by up to a configurable
+/// fraction (the jitter), to prevent many clients from reconnecting
+/// in lockstep.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[non_exhaustive]
+pub struct ReconnectPolicy {
+  /// The delay before the second attempt, i.e., after the first one
+  /// failed.
+  pub base_delay: Duration,
+  /// The maximum delay between two attempts.
+  pub max_delay: Duration,
+  /// The factor by which the delay grows with each failed attempt.
+  pub multiplier: f64,
+  /// The maximum fraction, in the range `[0, 1]`, by which each delay
+  /// is randomly shortened. A value of zero disables jitter.
+  pub jitter: f64,
+}
+
+impl ReconnectPolicy {
+  /// Set the delay after the first failed attempt.
+  #[inline]
+  pub fn with_base_delay(mut self, base_delay: Duration) -> Self {
+    self.base_delay = base_delay;
+    self
+  }
+
+  /// Set the maximum delay between two attempts.
+  #[inline]
+  pub fn with_max_delay(mut self, max_delay: Duration) -> Self {
+    self.max_delay = max_delay;
+    self
+  }
+
+  /// Set the factor by which the delay grows with each failed attempt.
+  #[inline]
+  pub fn with_multiplier(mut self, multiplier: f64) -> Self {
+    self.multiplier = multiplier;
+    self
+  }
+
+  /// Set the maximum fraction by which delays are randomly shortened.
+  #[inline]
+  pub fn with_jitter(mut self, jitter: f64) -> Self {
+    self.jitter = jitter;
+    self
+  }
+
+  /// Compute the delay to wait for after `attempt` failed attempts,
+  /// with `attempt` starting at zero for the first failure.
+  pub fn delay(&self, attempt: u32) -> Duration {
+    let max = self.max_delay.as_secs_f64();
+    let delay = self.base_delay.as_secs_f64() * self.multiplier.powf(f64::from(attempt));
+    // Also take care of the delay overflowing or becoming NaN.
+    let delay = if delay.is_nan() {
+      max
+    } else {
+      delay.clamp(0.0, max)
+    };
+    let jitter = if self.jitter.is_nan() {
+      0.0
+    } else {
+      self.jitter.clamp(0.0, 1.0) * random_fraction()
+    };
+    Duration::from_secs_f64(delay * (1.0 - jitter))
+  }
+
+  /// Repeatedly invoke `attempt` until it succeeds, sleeping in between
+  /// attempts according to this policy.
+  ///
+  /// Errors that retrying cannot fix, such as authentication failures
+  /// or rejected subscriptions, are reported immediately.
+  pub(crate) async fn retry<F, Fut, R>(&self, mut attempt: F) -> Result<R, Error>
+  where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<R, Error>>,
+  {
+    let mut failures = 0;
+    loop {
+      match attempt().await {
+        Ok(result) => break Ok(result),
+        Err(error) if is_fatal(&error) => break Err(error),
+        Err(error) => {
+          let delay = self.delay(failures);
+          debug!(%error, ?delay, "failed to reconnect; retrying");
+          let () = sleep(delay).await;
+          failures = failures.saturating_add(1);
+        },
+      }
+    }
+  }
+}
+
+
+/// Check whether `error` is one that reconnecting cannot fix.
+fn is_fatal(error: &Error) -> bool {
+  matches!(
+    error,
+    Error::Stream(
+      StreamError::NotAuthenticated(..)
+        | StreamError::AuthenticationFailed(..)
+        | StreamError::ConnectionLimitExceeded(..)
+        | StreamError::InvalidSubscription(..)
+    ) | Error::WildcardNotPermitted(..)
+  )
+}
+
+impl Default for ReconnectPolicy {
+  fn default() -> Self {
+    Self {
+      base_delay: Duration::from_secs(1),
+      max_delay: Duration::from_secs(60),
+      multiplier: 2.0,
+      jitter: 0.2,
+    }
+  }
+}
+
+
+/// An event yielded by an [`AutoSubscription`].
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum AutoEvent<D> {
+  /// Market data received over the current connection, tagged with
+  /// the connection's generation.
+  Data(Generational<D>),
+  /// The connection was lost and has been replaced by a new one,
+  /// subscribed to the desired market data.
+  ///
+  /// This event is only reported if enabled via
+  /// [`AutoSubscription::with_reconnect_events`].
+  Reconnected,
+}
+
+
+/// A future establishing a subscribed connection.
+type ConnectFuture<St, Si, M> = BoxFuture<'static, Result<(St, Subscription<Si, M>), Error>>;
+
+
+/// The state of an [`AutoSubscription`].
+#[allow(clippy::large_enum_variant)]
+enum AutoState<St, Si, M>
+where
+  M: StreamMessage,
+{
+  /// No connection is established and no attempt is in progress.
+  Disconnected,
+  /// A connection is being established and subscribed.
+  Connecting(ConnectFuture<St, Si, M>),
+  /// A connection is established. The subscription is only kept around
+  /// to keep the connection alive.
+  Connected(St, Subscription<Si, M>),
+  /// Connecting failed for good.
+  Terminated,
+}
+
+
+/// A stream of market data that transparently reconnects.
+///
+/// An `AutoSubscription` owns a factory for establishing authenticated
+/// connections, e.g., by means of
+/// [`RealtimeData::connect_configured`][crate::data::v2::stream::RealtimeData::connect_configured],
+/// as well as the market data to subscribe to. Connecting happens
+/// lazily, once the stream is first polled. Whenever the connection
+/// ends, a new one is established and subscribed to the same market
+/// data, retrying with backoff according to the configured
+/// [`ReconnectPolicy`]. Websocket errors merely preceding the end of a
+/// connection are not reported.
+///
+/// Errors that retrying cannot fix, such as authentication failures
+/// or rejected subscriptions, are reported as the last item of the
+/// stream. Market data is tagged with the
+/// [`generation`][Subscription::generation] of the connection it was
+/// received over, with the first connection being of generation zero.
+/// JSON errors are reported, but do not end the stream. Note that
+/// market data sent by the server while no connection is established
+/// is lost.
+///
+/// ```no_run
+/// # use futures::StreamExt as _;
+/// # use apca::ApiInfo;
+/// # use apca::data::v2::stream::AutoSubscription;
+/// # use apca::data::v2::stream::IEX;
+/// # use apca::data::v2::stream::MarketData;
+/// # use apca::data::v2::stream::RealtimeData;
+/// # use apca::data::v2::stream::StreamConfig;
+/// # async fn example() {
+/// let api_info = ApiInfo::from_env().unwrap();
+/// let connect = move || {
+///   let api_info = api_info.clone();
+///   async move {
+///     RealtimeData::<IEX>::connect_configured(&api_info, StreamConfig::default()).await
+///   }
+/// };
+/// let mut stream = AutoSubscription::new(connect, MarketData::with_bars(["SPY"]));
+/// while let Some(event) = stream.next().await {
+///   println!("{event:?}");
+/// }
+/// # }
+/// ```
+#[must_use = "streams do nothing unless polled"]
+pub struct AutoSubscription<F, St, Si, M = DataMessage>
+where
+  M: StreamMessage,
+{
+  /// The factory used for establishing connections.
+  connect: F,
+  /// The market data to subscribe to.
+  subscriptions: M::Channels,
+  /// The policy governing reconnection attempts.
+  policy: ReconnectPolicy,
+  /// Whether to report [`AutoEvent::Reconnected`] events.
+  reconnect_events: bool,
+  /// Whether a connection has been established before.
+  connected: bool,
+  /// The generation of the most recently established connection.
+  generation: u64,
+  /// The number of connections that ended since market data was last
+  /// received.
+  drops: u32,
+  /// The current state.
+  state: AutoState<St, Si, M>,
+}
+
+impl<F, St, Si, M> AutoSubscription<F, St, Si, M>
+where
+  M: StreamMessage,
+{
+  /// Create a new `AutoSubscription` object using `connect` for
+  /// establishing connections, each of which gets subscribed to
+  /// `subscriptions`.
+  pub fn new(connect: F, subscriptions: M::Channels) -> Self {
+    Self {
+      connect,
+      subscriptions,
+      policy: ReconnectPolicy::default(),
+      reconnect_events: false,
+      connected: false,
+      generation: 0,
+      drops: 0,
+      state: AutoState::Disconnected,
+    }
+  }
+
+  /// Set the policy governing reconnection attempts.
+  #[inline]
+  pub fn with_policy(mut self, policy: ReconnectPolicy) -> Self {
+    self.policy = policy;
+    self
+  }
+
+  /// Enable or disable reporting of [`AutoEvent::Reconnected`] events.
+  #[inline]
+  pub fn with_reconnect_events(mut self, enable: bool) -> Self {
+    self.reconnect_events = enable;
+    self
+  }
+
+  /// Retrieve the market data subscribed to on each connection.
+  #[inline]
+  pub fn subscriptions(&self) -> &M::Channels {
+    &self.subscriptions
+  }
+}
+
+impl<F, Fut, St, Si, M> AutoSubscription<F, St, Si, M>
+where
+  F: FnMut() -> Fut + Clone + Send + 'static,
+  Fut: Future<Output = Result<(St, Subscription<Si, M>), Error>> + Send,
+  St: FusedStream<Item = UserMessage<M>> + Send + Unpin + 'static,
+  Si: Sink<wrap::Message, Error = WebSocketError> + Send + Unpin + 'static,
+  M: StreamMessage + Send + 'static,
+  M::Data: Debug,
+{
+  /// Create a future establishing a connection and subscribing it to
+  /// the desired market data.
+  ///
+  /// When replacing a lost connection, the attempt is delayed according
+  /// to the policy, so that a server accepting and then immediately
+  /// closing connections does not cause us to reconnect in a tight
+  /// loop.
+  fn reconnect(&self) -> ConnectFuture<St, Si, M> {
+    let mut connect = self.connect.clone();
+    let subscriptions = self.subscriptions.clone();
+    let policy = self.policy;
+    let delay = self.connected.then(|| policy.delay(self.drops));
+
+    async move {
+      if let Some(delay) = delay {
+        let () = sleep(delay).await;
+      }
+
+      policy
+        .retry(|| {
+          let connect = connect();
+          let subscriptions = &subscriptions;
+          async move {
+            let (mut stream, mut subscription) = connect.await?;
+            let subscribe = Box::pin(subscription.subscribe(subscriptions));
+            let _data = drive(subscribe, &mut stream)
+              .await
+              .map_err(stream_error)???;
+            Ok((stream, subscription))
+          }
+        })
+        .await
+    }
+    .boxed()
+  }
+}
+
+impl<F, Fut, St, Si, M> FuturesStream for AutoSubscription<F, St, Si, M>
+where
+  F: FnMut() -> Fut + Clone + Send + Unpin + 'static,
+  Fut: Future<Output = Result<(St, Subscription<Si, M>), Error>> + Send,
+  St: FusedStream<Item = UserMessage<M>> + Send + Unpin + 'static,
+  Si: Sink<wrap::Message, Error = WebSocketError> + Send + Unpin + 'static,
+  M: StreamMessage + Send + 'static,
+  M::Data: Debug,
+{
+  type Item = Result<AutoEvent<M::Data>, Error>;
+
+  fn poll_next(mut self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+    let this = &mut *self;
+
+    loop {
+      match &mut this.state {
+        AutoState::Disconnected => this.state = AutoState::Connecting(this.reconnect()),
+        AutoState::Connecting(future) => match future.poll_unpin(ctx) {
+          Poll::Ready(Ok((stream, mut subscription))) => {
+            let reconnected = this.connected;
+            if reconnected {
+              subscription.generation = this.generation.wrapping_add(1);
+            }
+            this.connected = true;
+            this.generation = subscription.generation;
+            this.state = AutoState::Connected(stream, subscription);
+            if reconnected && this.reconnect_events {
+              return Poll::Ready(Some(Ok(AutoEvent::Reconnected)))
+            }
+          },
+          Poll::Ready(Err(error)) => {
+            this.state = AutoState::Terminated;
+            return Poll::Ready(Some(Err(error)))
+          },
+          Poll::Pending => return Poll::Pending,
+        },
+        AutoState::Connected(stream, _subscription) => match stream.poll_next_unpin(ctx) {
+          Poll::Ready(Some(Ok(Ok(data)))) => {
+            this.drops = 0;
+            let data = Generational {
+              generation: this.generation,
+              item: data,
+            };
+            return Poll::Ready(Some(Ok(AutoEvent::Data(data))))
+          },
+          Poll::Ready(Some(Ok(Err(error)))) => return Poll::Ready(Some(Err(Error::Json(error)))),
+          Poll::Ready(Some(Err(error))) => {
+            debug!(%error, "encountered websocket error");
+          },
+          Poll::Ready(None) => {
+            debug!("connection ended; reconnecting");
+            this.drops = this.drops.saturating_add(1);
+            this.state = AutoState::Disconnected;
+          },
+          Poll::Pending => return Poll::Pending,
+        },
+        AutoState::Terminated => return Poll::Ready(None),
+      }
+    }
+  }
+}
+
+impl<F, Fut, St, Si, M> FusedStream for AutoSubscription<F, St, Si, M>
+where
+  F: FnMut() -> Fut + Clone + Send + Unpin + 'static,
+  Fut: Future<Output = Result<(St, Subscription<Si, M>), Error>> + Send,
+  St: FusedStream<Item = UserMessage<M>> + Send + Unpin + 'static,
+  Si: Sink<wrap::Message, Error = WebSocketError> + Send + Unpin + 'static,
+  M: StreamMessage + Send + 'static,
+  M::Data: Debug,
+{
+  #[inline]
+  fn is_terminated(&self) -> bool {
+    matches!(self.state, AutoState::Terminated)
+  }
+}
+
+impl<F, St, Si, M> Debug for AutoSubscription<F, St, Si, M>
+where
+  M: StreamMessage,
+{
+  fn fmt(&self, fmt: &mut Formatter<'_>) -> FmtResult {
+    let state = match self.state {
+      AutoState::Disconnected => "disconnected",
+      AutoState::Connecting(..) => "connecting",
+      AutoState::Connected(..) => "connected",
+      AutoState::Terminated => "terminated",
+    };
+
+    fmt
+      .debug_struct("AutoSubscription")
+      .field("subscriptions", &self.subscriptions)
+      .field("policy", &self.policy)
+      .field("reconnect_events", &self.reconnect_events)
+      .field("state", &state)
+      .finish_non_exhaustive()
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use std::sync::atomic::AtomicUsize;
+  use std::sync::atomic::Ordering as AtomicOrdering;
+  use std::sync::Arc;
+  use std::sync::Mutex as StdMutex;
+
+  use futures::future::ready;
+
+  use tokio::time::timeout;
+
+  use serde_json::from_str as json_from_str;
+
+  use test_log::test;
+
+  use crate::data::v2::stream::test::mock_subscription;
+  use crate::data::v2::stream::tests::respond;
+  use crate::data::v2::stream::tests::BAR_RESP;
+  use crate::data::v2::stream::Bar;
+  use crate::data::v2::stream::Data;
+  use crate::data::v2::stream::MarketData;
+  use crate::data::v2::stream::Quote;
+  use crate::data::v2::stream::StreamApiError;
+  use crate::data::v2::stream::Trade;
+
+
+  /// Check that `ReconnectPolicy` computes the expected delays.
+  #[test]
+  fn reconnect_delays() {
+    let policy = ReconnectPolicy::default()
+      .with_base_delay(Duration::from_millis(100))
+      .with_max_delay(Duration::from_secs(1))
+      .with_multiplier(2.0)
+      .with_jitter(0.0);
+
+    assert_eq!(policy.delay(0), Duration::from_millis(100));
+    assert_eq!(policy.delay(1), Duration::from_millis(200));
+    assert_eq!(policy.delay(3), Duration::from_millis(800));
+    assert_eq!(policy.delay(4), Duration::from_secs(1));
+    assert_eq!(policy.delay(u32::MAX), Duration::from_secs(1));
+
+    let policy = policy.with_jitter(0.5);
+    for attempt in 0..10 {
+      let delay = policy.delay(attempt);
+      let nominal = policy.with_jitter(0.0).delay(attempt);
+      assert!(delay <= nominal, "{delay:?}");
+      assert!(delay >= nominal / 2, "{delay:?}");
+    }
+  }
+
+  /// Check that `ReconnectPolicy::retry` retries failed attempts but
+  /// bails out on authentication failures.
+  #[test(tokio::test)]
+  async fn reconnect_retry() {
+    let policy = ReconnectPolicy::default()
+      .with_base_delay(Duration::from_millis(1))
+      .with_max_delay(Duration::from_millis(5));
+
+    let mut attempts = 0;
+    let result = policy
+      .retry(|| {
+        attempts += 1;
+        let result = if attempts < 3 {
+          Err(Error::Str("connection refused".into()))
+        } else {
+          Ok(attempts)
+        };
+        ready(result)
+      })
+      .await
+      .unwrap();
+    assert_eq!(result, 3);
+
+    let mut attempts = 0;
+    let error = policy
+      .retry(|| {
+        attempts += 1;
+        let error = StreamApiError {
+          code: 402,
+          message: "auth failed".to_string(),
+        };
+        ready(Result::<(), _>::Err(Error::Stream(error.into())))
+      })
+      .await
+      .unwrap_err();
+    assert_eq!(attempts, 1);
+    assert!(matches!(
+      error,
+      Error::Stream(StreamError::AuthenticationFailed(..))
+    ));
+
+    for code in [405, 406, 409, 410] {
+      let mut attempts = 0;
+      let error = policy
+        .retry(|| {
+          attempts += 1;
+          let error = StreamApiError {
+            code,
+            message: "rejected".to_string(),
+          };
+          ready(Result::<(), _>::Err(Error::Stream(error.into())))
+        })
+        .await
+        .unwrap_err();
+      assert_eq!(attempts, 1, "{code}");
+      assert!(is_fatal(&error), "{code}");
+    }
+  }
+
+  /// Check that an `AutoSubscription` transparently reconnects and
+  /// resubscribes once a connection ends.
+  #[test(tokio::test)]
+  async fn auto_subscription_reconnect() {
+    let injectors = Arc::new(StdMutex::new(Vec::new()));
+    let connect = {
+      let injectors = injectors.clone();
+      move || {
+        let (stream, subscription, injector) = mock_subscription(respond());
+        let () = injectors.lock().unwrap().push(injector);
+        ready(Ok((stream, subscription)))
+      }
+    };
+    let policy = ReconnectPolicy::default().with_base_delay(Duration::ZERO);
+    let mut stream = AutoSubscription::new(connect, MarketData::with_bars(["SPY"]))
+      .with_policy(policy)
+      .with_reconnect_events(true);
+
+    assert!(stream.next().now_or_never().is_none());
+    let bar = json_from_str::<Vec<DataMessage>>(BAR_RESP)
+      .unwrap()
+      .remove(0);
+    let () = injectors.lock().unwrap()[0].push(bar.clone());
+    let event = stream.next().await.unwrap().unwrap();
+    assert!(matches!(
+      event,
+      AutoEvent::Data(Generational {
+        generation: 0,
+        item: Data::Bar { .. },
+      })
+    ));
+
+    let () = injectors.lock().unwrap()[0].close();
+    let event = stream.next().await.unwrap().unwrap();
+    assert!(matches!(event, AutoEvent::Reconnected));
+    assert_eq!(injectors.lock().unwrap().len(), 2);
+
+    let () = injectors.lock().unwrap()[1].push(bar);
+    let event = stream.next().await.unwrap().unwrap();
+    assert!(matches!(
+      event,
+      AutoEvent::Data(Generational {
+        generation: 1,
+        item: Data::Bar { .. },
+      })
+    ));
+    assert!(!stream.is_terminated());
+  }
+
+  /// Check that an `AutoSubscription` reports errors that retrying
+  /// cannot fix and ends afterwards.
+  #[test(tokio::test)]
+  async fn auto_subscription_fatal_error() {
+    let injectors = Arc::new(StdMutex::new(Vec::new()));
+    let connect = {
+      let injectors = injectors.clone();
+      move || {
+        let (stream, subscription, injector) = mock_subscription(respond());
+        let mut injectors = injectors.lock().unwrap();
+        let result = if injectors.is_empty() {
+          Ok((stream, subscription))
+        } else {
+          let error = StreamApiError {
+            code: 402,
+            message: "auth failed".to_string(),
+          };
+          Err(Error::Stream(error.into()))
+        };
+        let () = injectors.push(injector);
+        ready(result)
+      }
+    };
+    let mut stream = AutoSubscription::new(connect, MarketData::with_bars(["SPY"]));
+
+    assert!(stream.next().now_or_never().is_none());
+    let () = injectors.lock().unwrap()[0].close();
+
+    let err = stream.next().await.unwrap().unwrap_err();
+    assert!(matches!(
+      err,
+      Error::Stream(StreamError::AuthenticationFailed(..))
+    ));
+    assert!(stream.next().await.is_none());
+    assert!(stream.is_terminated());
+  }
+
+  /// Check that an `AutoSubscription` does not retry a subscribe
+  /// request rejected by the server.
+  #[test(tokio::test)]
+  async fn auto_subscription_rejected_subscribe() {
+    let attempts = Arc::new(AtomicUsize::new(0));
+    let connect = {
+      let attempts = attempts.clone();
+      move || {
+        let _prev = attempts.fetch_add(1, AtomicOrdering::Relaxed);
+        let (stream, subscription, _injector) = mock_subscription(|_request| {
+          let error = StreamApiError {
+            code: 405,
+            message: "symbol limit exceeded".to_string(),
+          };
+          vec![DataMessage::<Bar, Quote, Trade>::Error(error)]
+        });
+        ready(Ok((stream, subscription)))
+      }
+    };
+    let policy = ReconnectPolicy::default().with_base_delay(Duration::ZERO);
+    let mut stream =
+      AutoSubscription::new(connect, MarketData::with_bars(["SPY"])).with_policy(policy);
+
+    let err = stream.next().await.unwrap().unwrap_err();
+    assert!(matches!(
+      err,
+      Error::Stream(StreamError::InvalidSubscription(..))
+    ));
+    assert!(stream.next().await.is_none());
+    assert_eq!(attempts.load(AtomicOrdering::Relaxed), 1);
+  }
+
+  /// Check that an `AutoSubscription` waits before replacing a
+  /// connection that ended.
+  #[test(tokio::test)]
+  async fn auto_subscription_reconnect_delay() {
+    let injectors = Arc::new(StdMutex::new(Vec::new()));
+    let connect = {
+      let injectors = injectors.clone();
+      move || {
+        let (stream, subscription, injector) = mock_subscription(respond());
+        let () = injectors.lock().unwrap().push(injector);
+        ready(Ok((stream, subscription)))
+      }
+    };
+    let policy = ReconnectPolicy::default().with_base_delay(Duration::from_secs(60));
+    let mut stream =
+      AutoSubscription::new(connect, MarketData::with_bars(["SPY"])).with_policy(policy);
+
+    assert!(stream.next().now_or_never().is_none());
+    let () = injectors.lock().unwrap()[0].close();
+
+    let result = timeout(Duration::from_millis(100), stream.next()).await;
+    assert!(result.is_err());
+    assert_eq!(injectors.lock().unwrap().len(), 1);
+  }
+}
@@ -16,6 +16,8 @@ use thiserror::Error;
 use url::ParseError;
 use websocket_util::tungstenite::Error as WebSocketError;
 
+use crate::data::v2::stream::StreamApiError;
+use crate::data::v2::stream::StreamError;
 use crate::Str;
 
 
@@ -77,6 +79,26 @@ pub enum Error {
     #[source]
     JsonError,
   ),
+  /// An error reported by the Alpaca Stream API.
+  #[error("the stream API reported an error")]
+  Stream(
+    #[from]
+    #[source]
+    StreamError,
+  ),
+  /// A subscription to all symbols was rejected by the Alpaca Stream
+  /// API as exceeding the account's subscription (409), i.e., because
+  /// the account's data plan does not permit it. Subscribing to an
+  /// explicit list of symbols may still succeed.
+  #[error("subscribing to all symbols is not permitted")]
+  WildcardNotPermitted(#[source] StreamError),
+  /// No pong was received in response to a ping within the allotted
+  /// time.
+  #[error("timed out waiting for pong")]
+  PongTimeout,
+  /// The connection ended before an operation involving it completed.
+  #[error("the connection ended")]
+  ConnectionEnded,
   /// An error directly originating in this crate.
   #[error("{0}")]
   Str(Str),
@@ -96,6 +118,15 @@ pub enum Error {
   ),
 }
 
+impl From<StreamApiError> for Error {
+  /// Convert an error reported by the Alpaca Stream API into an
+  /// [`Error::Stream`], retaining its code and message.
+  #[inline]
+  fn from(error: StreamApiError) -> Self {
+    Self::Stream(StreamError::from(error))
+  }
+}
+
 
 #[cfg(test)]
 mod tests {
@@ -119,4 +150,26 @@ mod tests {
       "encountered an unexpected HTTP status: 404 Not Found: invalid"
     );
   }
+
+  /// Check that a [`StreamApiError`] can be converted into an
+  /// [`Error`] without losing information.
+  #[test]
+  fn from_stream_api_error() {
+    #[allow(clippy::result_large_err)]
+    fn fail() -> Result<(), Error> {
+      Err(StreamApiError {
+        code: 406,
+        message: "connection limit exceeded".to_string(),
+      })?
+    }
+
+    let err = fail().unwrap_err();
+    match err {
+      Error::Stream(StreamError::ConnectionLimitExceeded(error)) => {
+        assert_eq!(error.code, 406);
+        assert_eq!(error.message, "connection limit exceeded");
+      },
+      err => panic!("unexpected error: {err:?}"),
+    }
+  }
 }
@@ -3,6 +3,10 @@
 
 use crate::Str;
 
+use chrono::DateTime;
+use chrono::TimeZone as _;
+use chrono::Utc;
+
 use num_decimal::Num;
 
 use serde::Deserialize;
@@ -22,6 +26,27 @@ where
 }
 
 
+/// Deserialize a `DateTime<Utc>` from either an RFC 3339 formatted
+/// string or an integer denoting nanoseconds since the Unix epoch.
+pub(crate) fn datetime_from_str_or_nanos<'de, D>(deserializer: D) -> Result<DateTime<Utc>, D::Error>
+where
+  D: Deserializer<'de>,
+{
+  #[derive(Deserialize)]
+  #[serde(untagged)]
+  enum Timestamp {
+    Nanos(i64),
+    DateTime(DateTime<Utc>),
+  }
+
+  let timestamp = match Timestamp::deserialize(deserializer)? {
+    Timestamp::Nanos(nanos) => Utc.timestamp_nanos(nanos),
+    Timestamp::DateTime(datetime) => datetime,
+  };
+  Ok(timestamp)
+}
+
+
 /// Deserialize a `Vec` from a string that could contain a `null`.
 pub(crate) fn vec_from_str<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
 where
@@ -1,8 +1,26 @@
 // Copyright (C) 2019-2023 The apca Developers
 // SPDX-License-Identifier: GPL-3.0-or-later
 
+use std::pin::Pin;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::task::Context;
+use std::task::Poll;
+use std::time::Duration;
+
+use futures::task::AtomicWaker;
+use futures::Sink;
+use futures::SinkExt as _;
+use futures::Stream;
+use futures::StreamExt as _;
+
 use url::Url;
 
+use tokio::io::AsyncRead;
+use tokio::io::AsyncWrite;
 use tokio::net::TcpStream;
 
 use tracing::debug;
@@ -11,15 +29,23 @@ use tracing::trace;
 use tracing::Level;
 use tracing_futures::Instrument;
 
+use tungstenite::client_async;
 use tungstenite::connect_async;
 use tungstenite::MaybeTlsStream;
 use tungstenite::WebSocketStream;
 
+use websocket_util::tungstenite::protocol::CloseFrame;
+use websocket_util::tungstenite::Error as WebSocketError;
+use websocket_util::tungstenite::Message as WebSocketMessage;
 use websocket_util::wrap::Wrapper;
 
 use crate::Error;
 
 
+/// The default interval at which to send pings to the server.
+pub(crate) const PING_INTERVAL: Duration = Duration::from_secs(30);
+
+
 /// A custom [`Result`]-style type that we can implement a foreign trait
 /// on.
 #[derive(Debug)]
@@ -42,6 +68,251 @@ impl<T, E> From<Result<T, E>> for MessageResult<T, E> {
 }
 
 
+/// State of a websocket connection, shared between a [`Monitor`] and
+/// interested parties.
+#[derive(Debug, Default)]
+pub(crate) struct ConnectionState {
+  /// The close frame sent by the server, if any.
+  close: Mutex<Option<CloseFrame<'static>>>,
+  /// Whether sending a ping has been requested.
+  ping: AtomicBool,
+  /// The number of pongs received so far.
+  pongs: AtomicU64,
+  /// Whether sending a close frame has been requested.
+  close_requested: AtomicBool,
+  /// Whether a close frame has been sent.
+  close_sent: AtomicBool,
+  /// Whether the state is backed by a [`Monitor`], i.e., whether
+  /// requests are serviced at all.
+  monitored: bool,
+  /// Whether the stream has ended.
+  ended: AtomicBool,
+  /// The waker of the task polling the stream.
+  stream_waker: AtomicWaker,
+  /// The waker of the task awaiting a pong.
+  pong_waker: AtomicWaker,
+  /// The waker of the task awaiting the close frame being sent.
+  close_waker: AtomicWaker,
+}
+
+impl ConnectionState {
+  /// Retrieve the close frame sent by the server, if any.
+  pub(crate) fn close_frame(&self) -> Option<CloseFrame<'static>> {
+    self
+      .close
+      .lock()
+      .unwrap_or_else(|err| err.into_inner())
+      .clone()
+  }
+
+  /// Request a ping to be sent the next time the stream is polled,
+  /// returning the number of pongs received so far.
+  pub(crate) fn request_ping(&self) -> u64 {
+    let pongs = self.pongs.load(Ordering::Acquire);
+    let () = self.ping.store(true, Ordering::Release);
+    let () = self.stream_waker.wake();
+    pongs
+  }
+
+  /// Check whether more than `pongs` pongs have been received.
+  ///
+  /// The result is `false` if the stream ended before that happened.
+  pub(crate) fn poll_pong(&self, ctx: &mut Context<'_>, pongs: u64) -> Poll<bool> {
+    let () = self.pong_waker.register(ctx.waker());
+
+    if self.pongs.load(Ordering::Acquire) > pongs {
+      Poll::Ready(true)
+    } else if self.ended.load(Ordering::Acquire) {
+      Poll::Ready(false)
+    } else {
+      Poll::Pending
+    }
+  }
+
+  /// Request a close frame to be sent the next time the stream is
+  /// polled.
+  pub(crate) fn request_close(&self) {
+    let () = self.close_requested.store(true, Ordering::Release);
+    let () = self.stream_waker.wake();
+  }
+
+  /// Check whether a previously requested close frame has been sent.
+  ///
+  /// The result is `false` if the stream ended before that happened or
+  /// if there is no connection to close to begin with.
+  pub(crate) fn poll_close(&self, ctx: &mut Context<'_>) -> Poll<bool> {
+    let () = self.close_waker.register(ctx.waker());
+
+    if self.close_sent.load(Ordering::Acquire) {
+      Poll::Ready(true)
+    } else if !self.monitored || self.ended.load(Ordering::Acquire) {
+      Poll::Ready(false)
+    } else {
+      Poll::Pending
+    }
+  }
+}
+
+
+/// A stream adapter monitoring a websocket connection.
+///
+/// [`Wrapper`] swallows close messages and with them the close frame,
+/// which contains the reason for the server to close the connection.
+/// It also handles pings and pongs internally. By interposing this
+/// adapter between the websocket stream and the `Wrapper`, we make the
+/// close frame available to interested parties nevertheless and allow
+/// for actively probing the connection with pings as well as for
+/// closing it.
+#[derive(Debug)]
+#[doc(hidden)]
+pub struct Monitor<S> {
+  /// The wrapped websocket stream.
+  inner: S,
+  /// The state shared with interested parties.
+  state: Arc<ConnectionState>,
+  /// Whether a ping has been sent but not yet flushed.
+  flush: bool,
+  /// Whether a close frame has been sent but not yet flushed.
+  closing: bool,
+}
+
+impl<S> Monitor<S> {
+  /// Wrap the provided stream, returning the adapter along with the
+  /// state it shares.
+  fn new(stream: S) -> (Self, Arc<ConnectionState>) {
+    let state = Arc::new(ConnectionState {
+      monitored: true,
+      ..Default::default()
+    });
+    let this = Self {
+      inner: stream,
+      state: state.clone(),
+      flush: false,
+      closing: false,
+    };
+    (this, state)
+  }
+}
+
+impl<S> Monitor<S>
+where
+  S: Sink<WebSocketMessage, Error = WebSocketError> + Unpin,
+{
+  /// Send a ping if one was requested, making progress on flushing it.
+  #[allow(clippy::result_large_err)]
+  fn advance_ping(&mut self, ctx: &mut Context<'_>) -> Result<(), WebSocketError> {
+    if self.state.ping.load(Ordering::Acquire) {
+      if let Poll::Ready(()) = self.inner.poll_ready_unpin(ctx)? {
+        let () = self.state.ping.store(false, Ordering::Release);
+        debug!("sending ping");
+        let () = self
+          .inner
+          .start_send_unpin(WebSocketMessage::Ping(Vec::new()))?;
+        self.flush = true;
+      }
+    }
+
+    if self.flush {
+      if let Poll::Ready(()) = self.inner.poll_flush_unpin(ctx)? {
+        self.flush = false;
+      }
+    }
+    Ok(())
+  }
+
+  /// Send a close frame if one was requested, making progress on
+  /// flushing it.
+  #[allow(clippy::result_large_err)]
+  fn advance_close(&mut self, ctx: &mut Context<'_>) -> Result<(), WebSocketError> {
+    if self.state.close_requested.load(Ordering::Acquire) {
+      if let Poll::Ready(()) = self.inner.poll_ready_unpin(ctx)? {
+        let () = self.state.close_requested.store(false, Ordering::Release);
+        debug!("sending close frame");
+        let () = self.inner.start_send_unpin(WebSocketMessage::Close(None))?;
+        self.closing = true;
+      }
+    }
+
+    if self.closing {
+      if let Poll::Ready(()) = self.inner.poll_flush_unpin(ctx)? {
+        self.closing = false;
+        let () = self.state.close_sent.store(true, Ordering::Release);
+        let () = self.state.close_waker.wake();
+      }
+    }
+    Ok(())
+  }
+}
+
+impl<S> Stream for Monitor<S>
+where
+  S: Stream<Item = Result<WebSocketMessage, WebSocketError>>
+    + Sink<WebSocketMessage, Error = WebSocketError>
+    + Unpin,
+{
+  type Item = S::Item;
+
+  fn poll_next(mut self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+    let this = &mut *self;
+    let () = this.state.stream_waker.register(ctx.waker());
+
+    if let Err(err) = this.advance_ping(ctx) {
+      return Poll::Ready(Some(Err(err)))
+    }
+
+    if let Err(err) = this.advance_close(ctx) {
+      return Poll::Ready(Some(Err(err)))
+    }
+
+    let poll = this.inner.poll_next_unpin(ctx);
+    match &poll {
+      Poll::Ready(Some(Ok(WebSocketMessage::Close(Some(frame))))) => {
+        debug!(code = u16::from(frame.code), reason = %frame.reason, "received close frame");
+        *this
+          .state
+          .close
+          .lock()
+          .unwrap_or_else(|err| err.into_inner()) = Some(frame.clone());
+      },
+      Poll::Ready(Some(Ok(WebSocketMessage::Pong(..)))) => {
+        let _pongs = this.state.pongs.fetch_add(1, Ordering::AcqRel);
+        let () = this.state.pong_waker.wake();
+      },
+      Poll::Ready(None) => {
+        let () = this.state.ended.store(true, Ordering::Release);
+        let () = this.state.pong_waker.wake();
+        let () = this.state.close_waker.wake();
+      },
+      _ => (),
+    }
+    poll
+  }
+}
+
+impl<S> Sink<WebSocketMessage> for Monitor<S>
+where
+  S: Sink<WebSocketMessage, Error = WebSocketError> + Unpin,
+{
+  type Error = WebSocketError;
+
+  fn poll_ready(mut self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+    self.inner.poll_ready_unpin(ctx)
+  }
+
+  fn start_send(mut self: Pin<&mut Self>, message: WebSocketMessage) -> Result<(), Self::Error> {
+    self.inner.start_send_unpin(message)
+  }
+
+  fn poll_flush(mut self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+    self.inner.poll_flush_unpin(ctx)
+  }
+
+  fn poll_close(mut self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+    self.inner.poll_close_unpin(ctx)
+  }
+}
+
+
 /// Internal function to connect to websocket server.
 async fn connect_internal(url: &Url) -> Result<WebSocketStream<MaybeTlsStream<TcpStream>>, Error> {
   let span = span!(Level::DEBUG, "stream");
@@ -63,13 +334,74 @@ async fn connect_internal(url: &Url) -> Result<WebSocketStream<MaybeTlsStream<Tc
 }
 
 
-/// Connect to a websocket server.
+/// Internal function to connect to websocket server over an already
+/// established stream.
+async fn connect_with_internal<S>(url: &Url, stream: S) -> Result<WebSocketStream<S>, Error>
+where
+  S: AsyncRead + AsyncWrite + Unpin,
+{
+  let span = span!(Level::DEBUG, "stream");
+
+  async move {
+    debug!(message = "connecting", url = display(url));
+
+    let (stream, response) = client_async(url, stream).await?;
+    debug!("connection successful");
+    trace!(response = debug(&response));
+
+    Ok(stream)
+  }
+  .instrument(span)
+  .await
+}
+
+
+/// Connect to a websocket server, sending pings at the provided
+/// interval.
+///
+/// The connection is monitored, with the returned [`ConnectionState`]
+/// reflecting its state.
 pub(crate) async fn connect(
   url: &Url,
-) -> Result<Wrapper<WebSocketStream<MaybeTlsStream<TcpStream>>>, Error> {
-  connect_internal(url)
-    .await
-    .map(|stream| Wrapper::builder().build(stream))
+  ping_interval: Option<Duration>,
+) -> Result<
+  (
+    Wrapper<Monitor<WebSocketStream<MaybeTlsStream<TcpStream>>>>,
+    Arc<ConnectionState>,
+  ),
+  Error,
+> {
+  connect_internal(url).await.map(|stream| {
+    let (stream, close) = Monitor::new(stream);
+    let stream = Wrapper::builder()
+      .set_ping_interval(ping_interval)
+      .build(stream);
+    (stream, close)
+  })
+}
+
+
+/// Connect to a websocket server over the provided stream, performing
+/// the websocket handshake on it and sending pings at the provided
+/// interval.
+///
+/// The connection is monitored, with the returned [`ConnectionState`]
+/// reflecting its state.
+pub(crate) async fn connect_with<S>(
+  url: &Url,
+  stream: S,
+  ping_interval: Option<Duration>,
+) -> Result<(Wrapper<Monitor<WebSocketStream<S>>>, Arc<ConnectionState>), Error>
+where
+  S: AsyncRead + AsyncWrite + Unpin,
+{
+  connect_with_internal(url, stream).await.map(|stream| {
+    let (stream, close) = Monitor::new(stream);
+    let stream = Wrapper::builder()
+      .set_ping_interval(ping_interval)
+      .build(stream);
+    (stream, close)
+  })
 }
 
 